@@ -0,0 +1,127 @@
+//! Reading inputs where the cost of detecting the format of every layer and entry dominates.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::io::{self, Write};
+use std::path::Path;
+
+fn gzip_data(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(1));
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn zstd_data(data: &[u8]) -> Vec<u8> {
+    ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest)
+}
+
+fn tar_archive<'a>(files: impl IntoIterator<Item = (String, &'a [u8])>) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+/// A tar of `count` small text files.
+fn small_files(count: usize) -> Vec<u8> {
+    let files: Vec<_> = (0..count)
+        .map(|i| format!("file {i} holds a short line of text\n").repeat(4))
+        .collect();
+    tar_archive(
+        files
+            .iter()
+            .enumerate()
+            .map(|(i, data)| (format!("dir-{}/file-{i}.txt", i % 64), data.as_bytes())),
+    )
+}
+
+/// `depth` tars nested inside each other, alternating between gzip and zstd, with a few small
+/// files at every level.
+fn nested(depth: usize) -> Vec<u8> {
+    let file = b"a small file at every level\n".repeat(8);
+    let mut archive = tar_archive((0..4).map(|i| (format!("file-{i}"), file.as_slice())));
+    for level in 0..depth {
+        let compressed = match level % 2 {
+            0 => gzip_data(&archive),
+            _ => zstd_data(&archive),
+        };
+        let files = (0..4).map(|i| (format!("file-{i}"), file.as_slice()));
+        archive = tar_archive(files.chain([(format!("level-{level}.tar"), compressed.as_slice())]));
+    }
+    archive
+}
+
+fn read_all(data: &[u8]) -> usize {
+    let mut files = 0;
+    anyreader::recursive_read(Path::new("input"), data, &mut |item| {
+        io::copy(item.reader, &mut io::sink())?;
+        files += 1;
+        Ok(())
+    })
+    .unwrap();
+    files
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    let inputs = [
+        ("small_files.tar", small_files(10_000)),
+        ("small_files.tar.gz", gzip_data(&small_files(10_000))),
+        ("small_files.tar.zst", zstd_data(&small_files(10_000))),
+        ("nested.tar", nested(32)),
+    ];
+    for (name, data) in &inputs {
+        group.throughput(Throughput::Elements(read_all(data) as u64));
+        group.bench_function(*name, |b| b.iter(|| read_all(black_box(data))));
+    }
+    group.finish();
+}
+
+/// A tar of a few large files of random letters, which deflate splits into many blocks.
+fn large_files() -> Vec<u8> {
+    let mut state = 1u32;
+    let letters: Vec<u8> = (0..8 * 1024 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            b'a' + (state % 16) as u8
+        })
+        .collect();
+    tar_archive(
+        letters
+            .chunks(1024 * 1024)
+            .enumerate()
+            .map(|(i, data)| (format!("file-{i}"), data)),
+    )
+}
+
+fn read_indexed(data: &[u8]) -> usize {
+    let index = anyreader::ReadOptions::new()
+        .recursive_read_indexed(Path::new("input"), data, &mut |item| {
+            io::copy(item.reader, &mut io::sink())?;
+            Ok(())
+        })
+        .unwrap();
+    index.entries().len()
+}
+
+/// Indexing decodes gzip with its own deflate decoder, to find where blocks end, rather than
+/// with zlib as reading does.
+fn bench_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index");
+    let archive = large_files();
+    let data = gzip_data(&archive);
+    group.throughput(Throughput::Bytes(archive.len() as u64));
+    group.bench_function("read", |b| b.iter(|| read_all(black_box(&data))));
+    group.bench_function("read_indexed", |b| {
+        b.iter(|| read_indexed(black_box(&data)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_read, bench_index);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use anyreader::{iterate_archive, recursive_read};
+use anyreader::{OciView, ReadOptions, SpoolOptions};
 use clap::Parser;
 use clio::*;
 use std::io;
@@ -23,6 +23,9 @@ struct Args {
     /// Don't recurse into nested archives or decompress entry contents.
     #[clap(long, short = 's')]
     shallow: bool,
+    /// Read inputs as container images, writing the merged filesystem of each image.
+    #[clap(long, conflicts_with = "shallow")]
+    oci: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -36,11 +39,14 @@ fn main() -> anyhow::Result<()> {
     let mut builder = tar::Builder::new(BufWriter::new(args.output));
 
     for input in args.input {
+        let seekable = input.can_seek();
         handle_reader(
             &input.path().clone(),
             BufReader::new(input),
+            seekable,
             &mut builder,
             args.shallow,
+            args.oci,
         )?;
     }
 
@@ -50,9 +56,11 @@ fn main() -> anyhow::Result<()> {
 
 fn handle_reader(
     path: &Path,
-    reader: impl Read,
+    reader: impl Read + Seek,
+    seekable: bool,
     builder: &mut tar::Builder<impl Write + Seek>,
     shallow: bool,
+    oci: bool,
 ) -> io::Result<()> {
     let mut callback = |item: anyreader::FileItem<&mut dyn Read>| {
         if !item.kind.is_file() {
@@ -65,10 +73,19 @@ fn handle_reader(
         Ok(())
     };
 
-    if shallow {
-        iterate_archive(reader, &mut callback)?;
+    // Zip archives are read through their central directory when the input is a file, and
+    // nested ones are spooled so they can be too.
+    let options = ReadOptions::new().spool(SpoolOptions::new());
+    if shallow && seekable {
+        options.iterate_archive_seekable(reader, &mut callback)?;
+    } else if shallow {
+        options.iterate_archive(reader, &mut callback)?;
+    } else if oci {
+        options.read_oci_image(path, reader, OciView::Merged, &mut callback)?;
+    } else if seekable {
+        options.recursive_read_seekable(path, reader, &mut callback)?;
     } else {
-        recursive_read(path, reader, &mut callback)?;
+        options.recursive_read(path, reader, &mut callback)?;
     }
     Ok(())
 }
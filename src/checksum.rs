@@ -0,0 +1,128 @@
+//! Checksums stored by archives for their entries, and checking them against the data.
+
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// A checksum of an entry's data, as stored by the archive holding it.
+///
+/// Only zip entries and cpio entries with the `070702` magic have one. The checksums in gzip,
+/// xz and zstd streams are checked by their decoders instead, and the tar header checksum only
+/// covers the header, which the tar reader checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Checksum {
+    /// The CRC-32 used by zip archives.
+    Crc32(u32),
+    /// The 32-bit sum of every byte, used by cpio archives with the `070702` magic.
+    Sum32(u32),
+}
+
+impl Checksum {
+    /// The name of the algorithm, such as `crc32`.
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            Self::Crc32(_) => "crc32",
+            Self::Sum32(_) => "sum32",
+        }
+    }
+
+    fn value(&self) -> u32 {
+        match self {
+            Self::Crc32(v) | Self::Sum32(v) => *v,
+        }
+    }
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:08x}", self.algorithm(), self.value())
+    }
+}
+
+/// The result of checking an entry's data against its stored checksum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// The full path of the entry.
+    pub path: PathBuf,
+    /// The checksum stored by the archive.
+    pub expected: Checksum,
+    /// The checksum of the data that was read.
+    pub actual: Checksum,
+}
+
+impl Verification {
+    /// Returns true if the data matches the stored checksum.
+    pub fn is_match(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Sum32(u32),
+}
+
+/// Computes the checksum of the data read through it, if the entry has a stored checksum.
+pub(crate) struct ChecksumReader<'a> {
+    reader: &'a mut dyn Read,
+    expected: Option<Checksum>,
+    hasher: Option<Hasher>,
+    eof: bool,
+}
+
+impl<'a> ChecksumReader<'a> {
+    pub(crate) fn new(reader: &'a mut dyn Read, expected: Option<Checksum>) -> Self {
+        let hasher = expected.map(|checksum| match checksum {
+            Checksum::Crc32(_) => Hasher::Crc32(crc32fast::Hasher::new()),
+            Checksum::Sum32(_) => Hasher::Sum32(0),
+        });
+        Self {
+            reader,
+            expected,
+            hasher,
+            eof: false,
+        }
+    }
+
+    pub(crate) fn expected(&self) -> Option<Checksum> {
+        self.expected
+    }
+
+    /// Returns the result of the check, or `None` if there is no stored checksum or the data
+    /// was not read to the end.
+    pub(crate) fn finish(self, path: PathBuf) -> Option<Verification> {
+        if !self.eof {
+            return None;
+        }
+        let actual = match self.hasher? {
+            Hasher::Crc32(hasher) => Checksum::Crc32(hasher.finalize()),
+            Hasher::Sum32(sum) => Checksum::Sum32(sum),
+        };
+        Some(Verification {
+            path,
+            expected: self.expected?,
+            actual,
+        })
+    }
+}
+
+impl Read for ChecksumReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.eof = true;
+        }
+        match &mut self.hasher {
+            Some(Hasher::Crc32(hasher)) => hasher.update(&buf[..read]),
+            Some(Hasher::Sum32(sum)) => {
+                *sum = buf[..read]
+                    .iter()
+                    .fold(*sum, |sum, b| sum.wrapping_add(u32::from(*b)));
+            }
+            None => {}
+        }
+        Ok(read)
+    }
+}
@@ -0,0 +1,205 @@
+use crate::container::{
+    BoundedReader, Container, Items, NEWC_CPIO_MAGIC, NEWC_CRC_CPIO_MAGIC, ODC_CPIO_MAGIC, skip,
+};
+use crate::{Checksum, FileItem, FileKind, Metadata, SizeHint};
+use std::fmt::Debug;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+const TRAILER: &str = "TRAILER!!!";
+/// The longest name that is read, including its NUL terminator, as the size comes from the header.
+const MAX_NAME_SIZE: u64 = 4096;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+pub struct CpioContainer<T: Read> {
+    reader: T,
+}
+
+impl<T: Read> Debug for CpioContainer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cpio").finish()
+    }
+}
+
+impl<T: Read> CpioContainer<T> {
+    pub fn new(reader: T) -> Self {
+        Self { reader }
+    }
+}
+
+impl<T: Read> Container for CpioContainer<T> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        Ok(CpioFileIter {
+            reader: &mut self.reader,
+            remaining: 0,
+            padding: 0,
+            done: false,
+        })
+    }
+}
+
+struct Header {
+    mode: u32,
+    file_size: u64,
+    name_size: u64,
+    // Alignment applied to the header + name and to the file data. 1 for odc, 4 for newc.
+    align: u64,
+    header_len: u64,
+    // Only the `070702` format stores a checksum of the file data.
+    checksum: Option<Checksum>,
+}
+
+pub struct CpioFileIter<'a, T: Read> {
+    reader: &'a mut T,
+    // Unread bytes of the previous entry, plus its trailing padding.
+    remaining: u64,
+    padding: u64,
+    done: bool,
+}
+
+impl<T: Read> CpioFileIter<'_, T> {
+    fn read_header(&mut self) -> io::Result<Option<Header>> {
+        let mut magic = [0; 6];
+        if !read_exact_or_eof(&mut self.reader, &mut magic)? {
+            return Ok(None);
+        }
+        match &magic[..] {
+            ODC_CPIO_MAGIC => {
+                let mut rest = [0; 70];
+                self.reader.read_exact(&mut rest)?;
+                Ok(Some(Header {
+                    mode: parse_number(&rest[12..18], 8)? as u32,
+                    name_size: parse_number(&rest[53..59], 8)?,
+                    file_size: parse_number(&rest[59..70], 8)?,
+                    align: 1,
+                    header_len: 76,
+                    checksum: None,
+                }))
+            }
+            NEWC_CPIO_MAGIC | NEWC_CRC_CPIO_MAGIC => {
+                let mut rest = [0; 104];
+                self.reader.read_exact(&mut rest)?;
+                let field = |idx: usize| parse_number(&rest[idx * 8..(idx + 1) * 8], 16);
+                let checksum = if &magic[..] == NEWC_CRC_CPIO_MAGIC {
+                    Some(Checksum::Sum32(field(12)? as u32))
+                } else {
+                    None
+                };
+                Ok(Some(Header {
+                    mode: field(1)? as u32,
+                    file_size: field(6)?,
+                    name_size: field(11)?,
+                    align: 4,
+                    header_len: 110,
+                    checksum,
+                }))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid cpio header magic",
+            )),
+        }
+    }
+}
+
+impl<T: Read> Items for CpioFileIter<'_, T> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some((path, header))) => {
+                let kind = match header.mode & S_IFMT {
+                    S_IFREG => FileKind::File,
+                    S_IFDIR => FileKind::Directory,
+                    _ => FileKind::Other,
+                };
+                let checksum = header.checksum.filter(|_| kind.is_file());
+                self.remaining = header.file_size;
+                self.padding = padding(header.file_size, header.align);
+                Some(Ok(FileItem {
+                    path,
+                    reader: BoundedReader::new(&mut self.reader, &mut self.remaining),
+                    kind,
+                    size_hint: SizeHint::Exact(header.file_size),
+                    metadata: Metadata::default(),
+                    content_type: None,
+                    checksum,
+                    offsets: None,
+                }))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<T: Read> CpioFileIter<'_, T> {
+    fn next_entry(&mut self) -> io::Result<Option<(PathBuf, Header)>> {
+        // Skip whatever the caller didn't read from the previous entry.
+        skip(&mut self.reader, self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+
+        let Some(header) = self.read_header()? else {
+            return Ok(None);
+        };
+        if header.name_size > MAX_NAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "cpio entry name is too long",
+            ));
+        }
+        let mut name = vec![0; header.name_size as usize];
+        self.reader.read_exact(&mut name)?;
+        let name_padding = padding(header.header_len + header.name_size, header.align);
+        skip(&mut self.reader, name_padding)?;
+
+        // Names are NUL terminated.
+        if let Some(end) = name.iter().position(|b| *b == 0) {
+            name.truncate(end);
+        }
+        let name = String::from_utf8_lossy(&name);
+        if name == TRAILER {
+            return Ok(None);
+        }
+        let name = name.trim_start_matches("./").trim_start_matches('/');
+        Ok(Some((PathBuf::from(name), header)))
+    }
+}
+
+fn padding(len: u64, align: u64) -> u64 {
+    (align - len % align) % align
+}
+
+fn parse_number(field: &[u8], radix: u32) -> io::Result<u64> {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|s| u64::from_str_radix(s, radix).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid cpio header field"))
+}
+
+/// Fills `buf` completely, returning false if the reader was already at EOF.
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,231 @@
+use crate::container::{Container, Items, MBOX_PREFIX};
+use crate::{FileItem, FileKind, Metadata, SizeHint};
+use mail_parser::{MessageParser, MimeHeaders, PartType};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+/// The largest message that is read, unless the spool options set another limit.
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// An email message or mbox mailbox. Each MIME part is yielded as a file, named by its filename
+/// parameter if it has one. Messages in a mailbox are yielded under `message-<n>/`.
+///
+/// Messages are buffered in memory one at a time, as MIME parsing needs the whole message.
+/// Messages larger than 64 MiB are reported as errors.
+pub struct EmailContainer<T: Read> {
+    reader: T,
+    max_size: u64,
+}
+
+impl<T: Read> Debug for EmailContainer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Email").finish()
+    }
+}
+
+impl<T: Read> EmailContainer<T> {
+    pub fn new(reader: T) -> Self {
+        Self {
+            reader,
+            max_size: MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the largest message that is read.
+    pub fn with_max_size(mut self, size: u64) -> Self {
+        self.max_size = size;
+        self
+    }
+}
+
+impl<T: Read> Container for EmailContainer<T> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        let mut reader = BufReader::new(&mut self.reader);
+        let is_mbox = reader.fill_buf()?.starts_with(MBOX_PREFIX);
+        let messages = if is_mbox {
+            let mbox = Mbox {
+                reader,
+                in_message: false,
+                max_size: self.max_size,
+            };
+            Messages::Mbox(mbox, 0)
+        } else {
+            let mut message = Vec::new();
+            reader
+                .take(self.max_size.saturating_add(1))
+                .read_to_end(&mut message)?;
+            if message.len() as u64 > self.max_size {
+                return Err(message_too_large(self.max_size));
+            }
+            Messages::Single(Some(message))
+        };
+        Ok(EmailFileIter {
+            messages,
+            parts: VecDeque::new(),
+            resumable: false,
+        })
+    }
+}
+
+fn message_too_large(max_size: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        format!("email message is larger than the limit of {max_size} bytes"),
+    )
+}
+
+enum Messages<R: BufRead> {
+    Single(Option<Vec<u8>>),
+    Mbox(Mbox<R>, usize),
+}
+
+impl<R: BufRead> Messages<R> {
+    fn next_message(&mut self) -> Option<io::Result<(PathBuf, Vec<u8>)>> {
+        match self {
+            Messages::Single(message) => message.take().map(|m| Ok((PathBuf::new(), m))),
+            Messages::Mbox(mbox, count) => {
+                let message = mbox.next_message()?;
+                *count += 1;
+                let prefix = PathBuf::from(format!("message-{count}"));
+                Some(message.map(|m| (prefix, m)))
+            }
+        }
+    }
+}
+
+/// The messages of an mbox mailbox, each starting after a line that begins with `From `. Lines
+/// in a message that begin with `>From ` have their first `>` removed, as in the QMail format.
+struct Mbox<R: BufRead> {
+    reader: R,
+    // Whether the `From ` line of the next message has been read.
+    in_message: bool,
+    max_size: u64,
+}
+
+impl<R: BufRead> Mbox<R> {
+    /// Returns the next message. A message larger than `max_size` is skipped, returning an error.
+    fn next_message(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let mut message = Vec::new();
+        let mut too_large = false;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            // A line longer than the limit is read in pieces, so it is not held in memory.
+            let limit = self.max_size.saturating_add(1);
+            match (&mut self.reader).take(limit).read_until(b'\n', &mut line) {
+                // The last message ends with the input, if its `From ` line was read.
+                Ok(0) if !std::mem::take(&mut self.in_message) => return None,
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            if line.starts_with(b"From ") {
+                if self.in_message {
+                    break;
+                }
+                self.in_message = true;
+                continue;
+            }
+            if !self.in_message || too_large {
+                continue;
+            }
+            let quoted = line
+                .iter()
+                .position(|b| *b != b'>')
+                .is_some_and(|i| i > 0 && line[i..].starts_with(b"From "));
+            let line = if quoted { &line[1..] } else { &line[..] };
+            if (message.len() + line.len()) as u64 > self.max_size {
+                too_large = true;
+                message = Vec::new();
+                continue;
+            }
+            message.extend_from_slice(line);
+        }
+        match too_large {
+            true => Some(Err(message_too_large(self.max_size))),
+            false => Some(Ok(message)),
+        }
+    }
+}
+
+pub struct EmailFileIter<R: BufRead> {
+    messages: Messages<R>,
+    parts: VecDeque<(PathBuf, Vec<u8>)>,
+    // Whether the last error was a message that could not be parsed or was too large, rather
+    // than one that could not be read.
+    resumable: bool,
+}
+
+impl<R: BufRead> Items for EmailFileIter<R> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        while self.parts.is_empty() {
+            let (prefix, message) = match self.messages.next_message()? {
+                Ok(message) => message,
+                Err(e) => {
+                    self.resumable = e.kind() == io::ErrorKind::FileTooLarge;
+                    return Some(Err(e));
+                }
+            };
+            match message_parts(&prefix, &message) {
+                Ok(parts) => self.parts = parts,
+                Err(e) => {
+                    self.resumable = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let (path, data) = self.parts.pop_front()?;
+        Some(Ok(FileItem {
+            path,
+            size_hint: SizeHint::Exact(data.len() as u64),
+            reader: Cursor::new(data),
+            kind: FileKind::File,
+            metadata: Metadata::default(),
+            content_type: None,
+            checksum: None,
+            offsets: None,
+        }))
+    }
+
+    // The next message in a mailbox can be read after one that could not be parsed.
+    fn continues_after_error(&self) -> bool {
+        self.resumable
+    }
+}
+
+/// Decodes the leaf parts of a message. Nested messages are returned as-is, to be read
+/// recursively.
+fn message_parts(prefix: &Path, raw: &[u8]) -> io::Result<VecDeque<(PathBuf, Vec<u8>)>> {
+    let Some(message) = MessageParser::default().parse(raw) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "email message could not be parsed",
+        ));
+    };
+    Ok(message
+        .parts
+        .iter()
+        .enumerate()
+        .filter(|(_, part)| !matches!(part.body, PartType::Multipart(_)))
+        .map(|(idx, part)| {
+            // Only the final component is used, so a filename can't escape the message.
+            let name = part
+                .attachment_name()
+                .and_then(|n| Path::new(n).file_name())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| {
+                    let extension = match part.body {
+                        PartType::Text(_) => "txt",
+                        PartType::Html(_) => "html",
+                        PartType::Message(_) => "eml",
+                        _ => "bin",
+                    };
+                    PathBuf::from(format!("part-{idx}.{extension}"))
+                });
+            (prefix.join(name), part.contents().to_vec())
+        })
+        .collect())
+}
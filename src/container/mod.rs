@@ -1,84 +1,537 @@
+#[cfg(feature = "cfb")]
+mod cfb_container;
+#[cfg(feature = "cpio")]
+mod cpio_container;
+#[cfg(feature = "email")]
+mod email_container;
+#[cfg(feature = "tar")]
 mod tar_container;
+#[cfg(feature = "warc")]
+mod warc_container;
+#[cfg(feature = "xar")]
+mod xar_container;
+#[cfg(feature = "zip")]
+mod zip_archive_container;
+#[cfg(feature = "zip")]
 mod zip_container;
 
 use crate::FileItem;
+use crate::ReadOptions;
+#[cfg(feature = "cfb")]
+use crate::container::cfb_container::CfbContainer;
+#[cfg(feature = "cpio")]
+use crate::container::cpio_container::CpioContainer;
+#[cfg(feature = "email")]
+use crate::container::email_container::EmailContainer;
+#[cfg(feature = "tar")]
 use crate::container::tar_container::TarContainer;
+#[cfg(feature = "warc")]
+use crate::container::warc_container::WarcContainer;
+#[cfg(feature = "xar")]
+use crate::container::xar_container::XarContainer;
+#[cfg(feature = "zip")]
+pub use crate::container::zip_archive_container::ZipArchiveContainer;
+#[cfg(feature = "zip")]
 use crate::container::zip_container::ZipContainer;
-use crate::peekable::Peekable;
+use crate::peekable::{Peekable, PeekableReader};
+use crate::registry::{CodecFormat, ContainerFormat, Detected, Format};
 use crate::stream::StreamKind;
 use std::fmt::{Debug, Formatter};
 use std::io;
-use std::io::Read;
+#[cfg(feature = "zip")]
+use std::io::SeekFrom;
+use std::io::{BufRead, Read, Seek};
+use std::sync::Arc;
 use tracing::trace;
 
 // Annoying: this needs to be quite high to detect tar archives
-const ARCHIVE_BUF_SIZE: usize = 262;
+pub(crate) const ARCHIVE_BUF_SIZE: usize = 262;
 
-pub enum ArchiveKind<T: Read, const N: usize> {
+pub enum ArchiveKind<T: BufRead, const N: usize> {
+    #[cfg(feature = "tar")]
     Tar(TarContainer<StreamKind<T, N>>),
+    #[cfg(feature = "zip")]
     Zip(ZipContainer<StreamKind<T, N>>),
+    #[cfg(feature = "xar")]
+    Xar(XarContainer<StreamKind<T, N>>),
+    #[cfg(feature = "cpio")]
+    Cpio(CpioContainer<StreamKind<T, N>>),
+    #[cfg(feature = "cfb")]
+    Cfb(CfbContainer<StreamKind<T, N>>),
+    #[cfg(feature = "email")]
+    Email(EmailContainer<StreamKind<T, N>>),
+    #[cfg(feature = "warc")]
+    Warc(WarcContainer<StreamKind<T, N>>),
+    // Keeps the type parameters used when every archive format is disabled.
+    #[cfg(not(any(
+        feature = "tar",
+        feature = "zip",
+        feature = "xar",
+        feature = "cpio",
+        feature = "cfb",
+        feature = "email",
+        feature = "warc"
+    )))]
+    #[doc(hidden)]
+    _Unsupported(
+        std::convert::Infallible,
+        std::marker::PhantomData<StreamKind<T, N>>,
+    ),
 }
 
-impl<T: Read, const N: usize> Debug for ArchiveKind<T, N> {
+impl<T: BufRead, const N: usize> ArchiveKind<T, N> {
+    /// The format of the archive.
+    pub fn format(&self) -> Format {
+        match self {
+            #[cfg(feature = "tar")]
+            Self::Tar(_) => Format::Tar,
+            #[cfg(feature = "zip")]
+            Self::Zip(_) => Format::Zip,
+            #[cfg(feature = "xar")]
+            Self::Xar(_) => Format::Xar,
+            #[cfg(feature = "cpio")]
+            Self::Cpio(_) => Format::Cpio,
+            #[cfg(feature = "cfb")]
+            Self::Cfb(_) => Format::Cfb,
+            #[cfg(feature = "email")]
+            Self::Email(_) => Format::Email,
+            #[cfg(feature = "warc")]
+            Self::Warc(_) => Format::Warc,
+            #[cfg(not(any(
+                feature = "tar",
+                feature = "zip",
+                feature = "xar",
+                feature = "cpio",
+                feature = "cfb",
+                feature = "email",
+                feature = "warc"
+            )))]
+            Self::_Unsupported(never, _) => match *never {},
+        }
+    }
+}
+
+impl<T: BufRead, const N: usize> Debug for ArchiveKind<T, N> {
+    #[cfg_attr(
+        not(any(
+            feature = "tar",
+            feature = "zip",
+            feature = "xar",
+            feature = "cpio",
+            feature = "cfb",
+            feature = "email",
+            feature = "warc"
+        )),
+        allow(unused_variables)
+    )]
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "tar")]
             Self::Tar(s) => {
                 write!(f, "ArchiveKind::Tar({s:?})")
             }
+            #[cfg(feature = "zip")]
             ArchiveKind::Zip(s) => {
                 write!(f, "ArchiveKind::Zip({s:?})")
             }
+            #[cfg(feature = "xar")]
+            ArchiveKind::Xar(s) => {
+                write!(f, "ArchiveKind::Xar({s:?})")
+            }
+            #[cfg(feature = "cpio")]
+            ArchiveKind::Cpio(s) => {
+                write!(f, "ArchiveKind::Cpio({s:?})")
+            }
+            #[cfg(feature = "cfb")]
+            ArchiveKind::Cfb(s) => {
+                write!(f, "ArchiveKind::Cfb({s:?})")
+            }
+            #[cfg(feature = "email")]
+            ArchiveKind::Email(s) => {
+                write!(f, "ArchiveKind::Email({s:?})")
+            }
+            #[cfg(feature = "warc")]
+            ArchiveKind::Warc(s) => {
+                write!(f, "ArchiveKind::Warc({s:?})")
+            }
+            #[cfg(not(any(
+                feature = "tar",
+                feature = "zip",
+                feature = "xar",
+                feature = "cpio",
+                feature = "cfb",
+                feature = "email",
+                feature = "warc"
+            )))]
+            ArchiveKind::_Unsupported(never, _) => match *never {},
         }
     }
 }
 
-pub enum ContainerKind<T: Read, const N: usize = ARCHIVE_BUF_SIZE> {
+#[allow(clippy::large_enum_variant)]
+pub enum ContainerKind<T: BufRead, const N: usize = ARCHIVE_BUF_SIZE> {
     Stream(StreamKind<T, N>),
     Archive(ArchiveKind<T, N>),
+    /// A format added to the [`FormatRegistry`](crate::FormatRegistry).
+    Custom(CustomKind<T, N>),
 }
 
-impl<T: Read, const N: usize> Debug for ContainerKind<T, N> {
+impl<T: BufRead, const N: usize> Debug for ContainerKind<T, N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Stream(s) => write!(f, "ContainerKind::Stream({s:?})"),
             Self::Archive(s) => write!(f, "ContainerKind::Archive({s:?})"),
+            Self::Custom(s) => write!(f, "ContainerKind::Custom({s:?})"),
+        }
+    }
+}
+
+impl<T: BufRead, const N: usize> ContainerKind<T, N> {
+    /// The name of the format, or `None` for data that is neither compressed nor an archive.
+    pub(crate) fn format_name(&self) -> Option<String> {
+        match self {
+            Self::Stream(StreamKind::Raw(_)) => None,
+            Self::Stream(StreamKind::Compressed(c)) => Some(c.format().to_string()),
+            Self::Archive(a) => Some(a.format().to_string()),
+            Self::Custom(CustomKind::Codec(c)) => Some(c.name().to_string()),
+            Self::Custom(CustomKind::Container(c)) => Some(c.name().to_string()),
         }
     }
 }
 
-impl<T: Read> ContainerKind<T, ARCHIVE_BUF_SIZE> {
+impl<T: BufRead> ContainerKind<T, ARCHIVE_BUF_SIZE> {
     pub fn from_reader(reader: T) -> io::Result<ContainerKind<T, ARCHIVE_BUF_SIZE>> {
-        let peekable = Peekable::new(reader)?;
-        let kind = StreamKind::from_peekable(peekable)?;
-        match kind {
-            StreamKind::Compressed(c) => Ok(Self::Stream(StreamKind::Compressed(c))),
-            StreamKind::Raw(r) => {
-                let buf = r.peek_buf();
-                if infer::archive::is_tar(buf) {
-                    trace!("tar detected");
-                    Ok(ContainerKind::Archive(ArchiveKind::Tar(TarContainer::new(
-                        StreamKind::Raw(r),
-                    ))))
-                } else if infer::archive::is_zip(buf) {
-                    trace!("zip detected");
-                    Ok(ContainerKind::Archive(ArchiveKind::Zip(ZipContainer::new(
-                        StreamKind::Raw(r),
-                    ))))
-                } else {
-                    trace!("stream detected");
-                    Ok(ContainerKind::Stream(StreamKind::Raw(r)))
-                }
+        Self::from_reader_with(reader, &ReadOptions::default())
+    }
+
+    /// Detects the formats that are enabled in the [`formats`](ReadOptions::formats) of
+    /// `options`.
+    #[cfg_attr(
+        not(any(
+            feature = "tar",
+            feature = "zip",
+            feature = "xar",
+            feature = "cpio",
+            feature = "cfb",
+            feature = "email",
+            feature = "warc"
+        )),
+        allow(unused_variables, unreachable_code)
+    )]
+    pub fn from_reader_with(
+        reader: T,
+        options: &ReadOptions,
+    ) -> io::Result<ContainerKind<T, ARCHIVE_BUF_SIZE>> {
+        let mut peekable = Peekable::new(reader)?;
+        let format = match options.formats.detect(peekable.peek_buf()?) {
+            None => {
+                trace!("stream detected");
+                return Ok(ContainerKind::Stream(StreamKind::Raw(
+                    peekable.into_reader(),
+                )));
             }
+            Some(Detected::Codec(codec)) => {
+                trace!(codec = codec.name(), "custom codec detected");
+                return Ok(ContainerKind::Custom(CustomKind::Codec(CustomCodec {
+                    format: codec.clone(),
+                    reader: peekable.into_reader(),
+                })));
+            }
+            Some(Detected::Container(container)) => {
+                trace!(container = container.name(), "custom container detected");
+                return Ok(ContainerKind::Custom(CustomKind::Container(
+                    CustomContainer {
+                        format: container.clone(),
+                        reader: peekable.into_reader(),
+                    },
+                )));
+            }
+            Some(Detected::Builtin(format)) if format.is_compression() => {
+                return Ok(Self::Stream(StreamKind::from_peekable_with(
+                    peekable, options,
+                )?));
+            }
+            Some(Detected::Builtin(format)) => format,
+        };
+        trace!(%format, "archive detected");
+        let r = StreamKind::Raw(peekable.into_reader());
+        let archive = match format {
+            #[cfg(feature = "tar")]
+            Format::Tar => ArchiveKind::Tar(TarContainer::new(r)),
+            #[cfg(feature = "zip")]
+            Format::Zip => ArchiveKind::Zip(ZipContainer::new(r)),
+            #[cfg(feature = "xar")]
+            Format::Xar => ArchiveKind::Xar(XarContainer::new(r)),
+            #[cfg(feature = "cpio")]
+            Format::Cpio => ArchiveKind::Cpio(CpioContainer::new(r)),
+            #[cfg(feature = "cfb")]
+            Format::Cfb => ArchiveKind::Cfb(CfbContainer::new(r)),
+            #[cfg(feature = "warc")]
+            Format::Warc => ArchiveKind::Warc(WarcContainer::new(r)),
+            #[cfg(feature = "email")]
+            Format::Email => ArchiveKind::Email(EmailContainer::new(r)),
+            format => return Err(format.unsupported()),
+        };
+        Ok(ContainerKind::Archive(archive))
+    }
+}
+
+/// A format added to a [`FormatRegistry`](crate::FormatRegistry), detected but not yet opened.
+pub enum CustomKind<T: BufRead, const N: usize> {
+    Codec(CustomCodec<PeekableReader<T, N>>),
+    Container(CustomContainer<PeekableReader<T, N>>),
+}
+
+impl<T: BufRead, const N: usize> Debug for CustomKind<T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Codec(c) => write!(f, "CustomKind::Codec({})", c.name()),
+            Self::Container(c) => write!(f, "CustomKind::Container({})", c.name()),
         }
     }
 }
 
+/// An input in a compression format from a [`FormatRegistry`](crate::FormatRegistry).
+pub struct CustomCodec<T: Read> {
+    format: Arc<dyn CodecFormat>,
+    reader: T,
+}
+
+impl<T: Read> CustomCodec<T> {
+    pub fn name(&self) -> &str {
+        self.format.name()
+    }
+
+    /// Returns a reader for the decompressed data.
+    pub fn decoder(&mut self) -> io::Result<Box<dyn Read + '_>> {
+        self.format.decoder(Box::new(&mut self.reader))
+    }
+}
+
+/// An input in an archive format from a [`FormatRegistry`](crate::FormatRegistry).
+pub struct CustomContainer<T: Read> {
+    format: Arc<dyn ContainerFormat>,
+    reader: T,
+}
+
+impl<T: Read> CustomContainer<T> {
+    pub fn name(&self) -> &str {
+        self.format.name()
+    }
+
+    /// Opens the archive.
+    pub fn open(&mut self) -> io::Result<Box<dyn DynContainer + '_>> {
+        self.format.open(Box::new(&mut self.reader))
+    }
+}
+
+/// The result of detecting the format of a seekable input.
+///
+/// Zip archives are read through their central directory, which is only possible with random
+/// access. Everything else is detected as by [`ContainerKind::from_reader`].
+#[allow(clippy::large_enum_variant)]
+pub enum SeekableContainerKind<T: BufRead + Seek> {
+    #[cfg(feature = "zip")]
+    Zip(ZipArchiveContainer<T>),
+    Other(ContainerKind<T>),
+}
+
+impl<T: BufRead + Seek> Debug for SeekableContainerKind<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "zip")]
+            Self::Zip(s) => write!(f, "SeekableContainerKind::Zip({s:?})"),
+            Self::Other(s) => write!(f, "SeekableContainerKind::Other({s:?})"),
+        }
+    }
+}
+
+impl<T: BufRead + Seek> SeekableContainerKind<T> {
+    pub fn from_reader(reader: T) -> io::Result<SeekableContainerKind<T>> {
+        Self::from_reader_with(reader, &ReadOptions::default())
+    }
+
+    /// Detects the formats that are enabled in the [`formats`](ReadOptions::formats) of
+    /// `options`.
+    #[cfg(feature = "zip")]
+    pub fn from_reader_with(
+        mut reader: T,
+        options: &ReadOptions,
+    ) -> io::Result<SeekableContainerKind<T>> {
+        let start = reader.stream_position()?;
+        let mut magic = Vec::with_capacity(ARCHIVE_BUF_SIZE);
+        (&mut reader)
+            .take(ARCHIVE_BUF_SIZE as u64)
+            .read_to_end(&mut magic)?;
+        reader.seek(SeekFrom::Start(start))?;
+        // Zip files such as self-extracting executables may not start with a local header, so zip
+        // is also tried when nothing is detected. A format that is checked earlier still wins.
+        let detected = options.formats.detect(&magic);
+        let try_zip = matches!(detected, None | Some(Detected::Builtin(Format::Zip)));
+        if !try_zip || !options.formats.is_enabled(Format::Zip) || !may_be_zip(&magic) {
+            return Ok(Self::Other(ContainerKind::from_reader_with(
+                reader, options,
+            )?));
+        }
+        match ZipArchiveContainer::try_new(reader)? {
+            Ok(zip) => {
+                trace!("zip central directory detected");
+                Ok(Self::Zip(zip))
+            }
+            Err(reader) => Ok(Self::Other(ContainerKind::from_reader_with(
+                reader, options,
+            )?)),
+        }
+    }
+
+    /// Detects the formats that are enabled in the [`formats`](ReadOptions::formats) of
+    /// `options`.
+    #[cfg(not(feature = "zip"))]
+    pub fn from_reader_with(
+        reader: T,
+        options: &ReadOptions,
+    ) -> io::Result<SeekableContainerKind<T>> {
+        Ok(Self::Other(ContainerKind::from_reader_with(
+            reader, options,
+        )?))
+    }
+}
+
+const XAR_MAGIC: &[u8] = b"xar!";
+pub(crate) const ODC_CPIO_MAGIC: &[u8] = b"070707";
+pub(crate) const NEWC_CPIO_MAGIC: &[u8] = b"070701";
+pub(crate) const NEWC_CRC_CPIO_MAGIC: &[u8] = b"070702";
+const CFB_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const WARC_MAGIC: &[u8] = b"WARC/";
+pub(crate) const MBOX_PREFIX: &[u8] = b"From ";
+
+pub(crate) fn is_xar(buf: &[u8]) -> bool {
+    buf.starts_with(XAR_MAGIC)
+}
+
+/// Returns true if the buffer starts with a portable ASCII (odc) or new ASCII (newc) cpio header.
+pub(crate) fn is_cpio(buf: &[u8]) -> bool {
+    buf.starts_with(ODC_CPIO_MAGIC)
+        || buf.starts_with(NEWC_CPIO_MAGIC)
+        || buf.starts_with(NEWC_CRC_CPIO_MAGIC)
+}
+
+/// Returns true for OLE Compound File Binary files, such as `.msi`, `.doc`, `.xls` and `.msg`.
+pub(crate) fn is_cfb(buf: &[u8]) -> bool {
+    buf.starts_with(CFB_MAGIC)
+}
+
+pub(crate) fn is_warc(buf: &[u8]) -> bool {
+    buf.starts_with(WARC_MAGIC)
+}
+
+// Header fields that are accepted as the first line of a message. Restricting this keeps plain
+// text files that happen to start with `Something: value` from being parsed as email.
+const FIRST_HEADERS: &[&str] = &[
+    "return-path",
+    "received",
+    "delivered-to",
+    "from",
+    "to",
+    "cc",
+    "subject",
+    "date",
+    "message-id",
+    "mime-version",
+    "reply-to",
+    "sender",
+    "content-type",
+    "dkim-signature",
+    "arc-seal",
+    "authentication-results",
+];
+
+/// Returns true for RFC 5322 messages (`.eml`) and mbox files.
+pub(crate) fn is_email(buf: &[u8]) -> bool {
+    let mut lines = buf.split(|b| *b == b'\n');
+    let mut first = lines.next().unwrap_or_default();
+    let is_mbox = first.starts_with(MBOX_PREFIX);
+    if is_mbox {
+        first = lines.next().unwrap_or_default();
+    }
+    let Some(name) = header_name(first) else {
+        return false;
+    };
+    if !is_known_header(name) && !name.starts_with("X-") {
+        return false;
+    }
+    // Every complete line up to the end of the headers must be a header or a continuation.
+    let mut lines: Vec<_> = lines.collect();
+    lines.pop();
+    let mut known = vec![name.to_ascii_lowercase()];
+    for line in lines
+        .into_iter()
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+        .take_while(|l| !l.is_empty())
+    {
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            continue;
+        }
+        let Some(name) = header_name(line) else {
+            return false;
+        };
+        known.push(name.to_ascii_lowercase());
+    }
+    // A single header such as `Subject:` is as likely to start a plain text note.
+    known.retain(|name| is_known_header(name));
+    known.sort();
+    known.dedup();
+    is_mbox || known.len() >= 2
+}
+
+fn is_known_header(name: &str) -> bool {
+    FIRST_HEADERS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+fn header_name(line: &[u8]) -> Option<&str> {
+    let end = line.iter().position(|b| *b == b':')?;
+    let name = std::str::from_utf8(&line[..end]).ok()?;
+    let valid = !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic());
+    valid.then_some(name)
+}
+
+#[cfg(feature = "zip")]
+const ZIP_MAGICS: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"];
+
+// Self-extracting archives are executables with the zip appended.
+#[cfg(feature = "zip")]
+const EXECUTABLE_MAGICS: &[&[u8]] = &[
+    b"MZ",
+    b"\x7fELF",
+    &[0xCF, 0xFA, 0xED, 0xFE],
+    &[0xCE, 0xFA, 0xED, 0xFE],
+    &[0xCA, 0xFE, 0xBA, 0xBE],
+    b"#!",
+];
+
+/// Returns true if the input may be a zip archive, possibly with data such as an executable
+/// before it. Only these inputs are checked for a central directory.
+#[cfg(feature = "zip")]
+pub(crate) fn may_be_zip(buf: &[u8]) -> bool {
+    ZIP_MAGICS
+        .iter()
+        .chain(EXECUTABLE_MAGICS)
+        .any(|m| buf.starts_with(m))
+}
+
 pub trait Container {
     fn items(&mut self) -> io::Result<impl Items>;
 }
 
 pub trait Items {
     fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>>;
+
+    /// Returns true if the items after the last error returned by
+    /// [`next_item`](Items::next_item) can still be read. Most archives cannot find the next
+    /// entry after a broken header, so by default the rest of the archive is skipped.
+    fn continues_after_error(&self) -> bool {
+        false
+    }
 }
 
 impl<T, R> Items for T
@@ -91,21 +544,124 @@ where
     }
 }
 
-#[cfg(test)]
+/// An object-safe version of [`Container`], used by formats added to a
+/// [`FormatRegistry`](crate::FormatRegistry). It is implemented for every [`Container`].
+pub trait DynContainer {
+    fn dyn_items(&mut self) -> io::Result<Box<dyn DynItems + '_>>;
+}
+
+/// An object-safe version of [`Items`]. It is implemented for every [`Items`].
+pub trait DynItems {
+    fn next_dyn_item(&mut self) -> Option<io::Result<FileItem<Box<dyn Read + '_>>>>;
+
+    fn dyn_continues_after_error(&self) -> bool;
+}
+
+impl<C: Container> DynContainer for C {
+    fn dyn_items(&mut self) -> io::Result<Box<dyn DynItems + '_>> {
+        Ok(Box::new(self.items()?))
+    }
+}
+
+impl<I: Items> DynItems for I {
+    fn next_dyn_item(&mut self) -> Option<io::Result<FileItem<Box<dyn Read + '_>>>> {
+        let item = self.next_item()?;
+        Some(item.map(|item| FileItem {
+            path: item.path,
+            reader: Box::new(item.reader) as Box<dyn Read + '_>,
+            kind: item.kind,
+            size_hint: item.size_hint,
+            metadata: item.metadata,
+            content_type: item.content_type,
+            checksum: item.checksum,
+            offsets: item.offsets,
+        }))
+    }
+
+    fn dyn_continues_after_error(&self) -> bool {
+        self.continues_after_error()
+    }
+}
+
+impl Container for Box<dyn DynContainer + '_> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        (**self).dyn_items()
+    }
+}
+
+impl Items for Box<dyn DynItems + '_> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        (**self).next_dyn_item()
+    }
+
+    fn continues_after_error(&self) -> bool {
+        (**self).dyn_continues_after_error()
+    }
+}
+
+/// Reads at most `remaining` bytes from an archive entry, keeping track of how many are left so
+/// the container can skip past the rest of the entry.
+#[cfg(any(feature = "cpio", feature = "xar", feature = "warc"))]
+pub(crate) struct BoundedReader<'a, T: Read> {
+    reader: &'a mut T,
+    remaining: &'a mut u64,
+}
+
+#[cfg(any(feature = "cpio", feature = "xar", feature = "warc"))]
+impl<'a, T: Read> BoundedReader<'a, T> {
+    pub fn new(reader: &'a mut T, remaining: &'a mut u64) -> Self {
+        Self { reader, remaining }
+    }
+}
+
+#[cfg(any(feature = "cpio", feature = "xar", feature = "warc"))]
+impl<T: Read> Read for BoundedReader<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if *self.remaining == 0 {
+            return Ok(0);
+        }
+        let max = buf.len().min(*self.remaining as usize);
+        let read = self.reader.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "archive entry is truncated",
+            ));
+        }
+        *self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Discards exactly `len` bytes from the reader.
+#[cfg(any(feature = "cpio", feature = "xar", feature = "warc"))]
+pub(crate) fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, any(all(feature = "gzip", feature = "zstd"), feature = "email")))]
 mod tests {
     use crate::container::ContainerKind;
-    use crate::stream::{CompressionKind, StreamKind};
+    use crate::stream::StreamKind;
     use assert_matches::assert_matches;
-    use std::io::Write;
 
     use tracing_test::traced_test;
 
-    const DATA: &[u8] = b"hello world";
-
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
     #[traced_test]
     #[test]
     fn test_recursive_compression() {
-        let zstd_data = zstd::encode_all(DATA, 1).unwrap();
+        use crate::stream::CompressionKind;
+        use ruzstd::encoding::CompressionLevel;
+        use std::io::{BufReader, Write};
+
+        const DATA: &[u8] = b"hello world";
+
+        let zstd_data = ruzstd::encoding::compress_to_vec(DATA, CompressionLevel::Fastest);
         let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
         encoder.write_all(&zstd_data).unwrap();
         let compressed_data = encoder.finish().unwrap();
@@ -114,10 +670,40 @@ mod tests {
             gzip_file_kind,
             ContainerKind::Stream(StreamKind::Compressed(CompressionKind::Gzip(r))) => r
         );
-        let stream_kind = ContainerKind::from_reader(stream_kind).unwrap();
+        let stream_kind = ContainerKind::from_reader(BufReader::new(stream_kind)).unwrap();
         assert_matches!(
             stream_kind,
             ContainerKind::Stream(StreamKind::Compressed(CompressionKind::Zst(_)))
         );
     }
+
+    #[cfg(feature = "email")]
+    #[traced_test]
+    #[test]
+    fn test_email_detection() {
+        use crate::container::ArchiveKind;
+
+        let message =
+            b"Received: from mail.example.com\r\n\tby mx.example.com\r\nSubject: hi\r\n\r\nbody";
+        assert_matches!(
+            ContainerKind::from_reader(message.as_slice()).unwrap(),
+            ContainerKind::Archive(ArchiveKind::Email(_))
+        );
+        // Text with a header-like first line isn't an email.
+        let text = b"Note: this is not an email\nIt is just text\n";
+        assert_matches!(
+            ContainerKind::from_reader(text.as_slice()).unwrap(),
+            ContainerKind::Stream(StreamKind::Raw(_))
+        );
+        // Nor is a note with a single header-like line, even a known one.
+        for text in [
+            &b"Subject: shopping\n\nmilk\n"[..],
+            b"To: me\nTo: you\n\nhi\n",
+        ] {
+            assert_matches!(
+                ContainerKind::from_reader(text).unwrap(),
+                ContainerKind::Stream(StreamKind::Raw(_))
+            );
+        }
+    }
 }
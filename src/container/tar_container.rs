@@ -1,5 +1,5 @@
 use crate::container::{Container, Items};
-use crate::{FileItem, FileKind, SizeHint};
+use crate::{EntryOffsets, FileItem, FileKind, Metadata, SizeHint};
 use std::fmt::Debug;
 use std::io;
 use std::io::Read;
@@ -32,12 +32,23 @@ impl<T: Read> Container for TarContainer<T> {
                 _ => FileKind::Other,
             };
             let path = item.path()?.to_path_buf();
-            let size_hint = item.header().size().map_or(SizeHint::Unknown, SizeHint::Exact);
+            let size_hint = item
+                .header()
+                .size()
+                .map_or(SizeHint::Unknown, SizeHint::Exact);
+            let offsets = EntryOffsets {
+                header: item.raw_header_position(),
+                data: item.raw_file_position(),
+            };
             Ok(FileItem {
                 path,
                 reader: item,
                 kind,
                 size_hint,
+                metadata: Metadata::default(),
+                content_type: None,
+                checksum: None,
+                offsets: Some(offsets),
             })
         }))
     }
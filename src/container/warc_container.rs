@@ -0,0 +1,278 @@
+use crate::container::{BoundedReader, Container, Items, is_warc, skip};
+use crate::{FileItem, FileKind, Metadata, SizeHint};
+use std::fmt::Debug;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+
+type Headers = Vec<(String, String)>;
+
+/// A WARC web archive. Each record's payload is yielded with its `WARC-Target-URI` as the path
+/// and the record headers as [`Metadata`].
+///
+/// HTTP headers are removed from `application/http` records, so the payload is the response
+/// or request body. Records without a target URI are named `record-<n>`.
+pub struct WarcContainer<T: Read> {
+    reader: BufReader<T>,
+}
+
+impl<T: Read> Debug for WarcContainer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Warc").finish()
+    }
+}
+
+impl<T: Read> WarcContainer<T> {
+    pub fn new(reader: T) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
+}
+
+impl<T: Read> Container for WarcContainer<T> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        Ok(WarcFileIter {
+            reader: &mut self.reader,
+            remaining: 0,
+            count: 0,
+            done: false,
+        })
+    }
+}
+
+pub struct WarcFileIter<'a, T: Read> {
+    reader: &'a mut BufReader<T>,
+    // Unread bytes of the previous record's block.
+    remaining: u64,
+    count: usize,
+    done: bool,
+}
+
+fn invalid_record(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid WARC record: {msg}"),
+    )
+}
+
+/// The longest header line that is read, so a file without line breaks is not read into memory.
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+/// The largest block of WARC or HTTP headers that is read.
+const MAX_HEADER_SIZE: u64 = 1024 * 1024;
+
+/// Reads a line into `line`, including its line break. Returns the number of bytes read, which
+/// is 0 at the end of the input.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> io::Result<usize> {
+    line.clear();
+    let read = reader.take(MAX_LINE_LENGTH).read_until(b'\n', line)?;
+    if read as u64 == MAX_LINE_LENGTH && !line.ends_with(b"\n") {
+        return Err(invalid_record("header line too long"));
+    }
+    Ok(read)
+}
+
+/// Reads `Name: value` header lines up to and including the blank line that ends them, without
+/// reading more than `limit` bytes. The number of bytes read is added to `consumed`, also when
+/// the headers are invalid.
+///
+/// Headers are usually ASCII, but other bytes are kept as replacement characters rather than
+/// failing the record.
+fn read_headers(reader: &mut impl BufRead, limit: u64, consumed: &mut u64) -> io::Result<Headers> {
+    let mut reader = reader.take(limit.min(MAX_HEADER_SIZE));
+    let mut headers: Headers = Vec::new();
+    let mut total = 0;
+    let mut line = Vec::new();
+    loop {
+        let read = match read_line(&mut reader, &mut line) {
+            Ok(read) => read,
+            Err(e) => {
+                *consumed += line.len() as u64;
+                return Err(e);
+            }
+        };
+        *consumed += read as u64;
+        total += read as u64;
+        if read == 0 && total == MAX_HEADER_SIZE {
+            return Err(invalid_record("headers too long"));
+        }
+        let line = String::from_utf8_lossy(&line);
+        let trimmed = line.trim_end_matches(['\r', '\n']);
+        if read == 0 || trimmed.is_empty() {
+            return Ok(headers);
+        }
+        if trimmed.starts_with([' ', '\t']) {
+            // Folded continuation of the previous header.
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(trimmed.trim());
+            }
+        } else if let Some((name, value)) = trimmed.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        } else if headers.is_empty() {
+            // The status or request line of an HTTP message.
+            continue;
+        } else {
+            return Err(invalid_record("malformed header line"));
+        }
+    }
+}
+
+impl<T: Read> WarcFileIter<'_, T> {
+    fn next_record(&mut self) -> io::Result<Option<(Headers, u64)>> {
+        skip(&mut self.reader, self.remaining)?;
+        self.remaining = 0;
+
+        // Records are separated by blank lines.
+        let mut version = Vec::new();
+        while version.trim_ascii().is_empty() {
+            if read_line(&mut self.reader, &mut version)? == 0 {
+                return Ok(None);
+            }
+        }
+        if !is_warc(&version) {
+            return Err(invalid_record("missing WARC version line"));
+        }
+        let headers = read_headers(&mut self.reader, MAX_HEADER_SIZE, &mut 0)?;
+        let length = header(&headers, "Content-Length")
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or_else(|| invalid_record("missing Content-Length"))?;
+        Ok(Some((headers, length)))
+    }
+}
+
+impl<T: Read> Items for WarcFileIter<'_, T> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        if self.done {
+            return None;
+        }
+        let (headers, length) = match self.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        self.count += 1;
+        self.remaining = length;
+
+        let path = match header(&headers, "WARC-Target-URI") {
+            Some(uri) => PathBuf::from(uri.trim_start_matches('<').trim_end_matches('>')),
+            None => PathBuf::from(format!("record-{}", self.count)),
+        };
+        let is_http = header(&headers, "Content-Type")
+            .is_some_and(|v| v.to_ascii_lowercase().starts_with("application/http"));
+
+        let mut chunked = false;
+        let mut size_hint = SizeHint::Exact(length);
+        if is_http {
+            let mut consumed = 0;
+            let http_headers = read_headers(&mut self.reader, length, &mut consumed);
+            // The rest of the block is skipped before the next record, even if the HTTP
+            // headers are invalid.
+            self.remaining -= consumed;
+            match http_headers {
+                Ok(http_headers) => {
+                    size_hint = SizeHint::Exact(self.remaining);
+                    chunked = header(&http_headers, "Transfer-Encoding")
+                        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+                    if chunked {
+                        size_hint = SizeHint::Unknown;
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let block = BoundedReader::new(self.reader, &mut self.remaining);
+        let reader: Box<dyn Read + '_> = if chunked {
+            Box::new(ChunkedReader::new(BufReader::new(block)))
+        } else {
+            Box::new(block)
+        };
+        Some(Ok(FileItem {
+            path,
+            reader,
+            kind: FileKind::File,
+            size_hint,
+            metadata: Metadata::from(headers),
+            content_type: None,
+            checksum: None,
+            offsets: None,
+        }))
+    }
+
+    // Records say how long they are, so the next one can be found after invalid HTTP headers.
+    fn continues_after_error(&self) -> bool {
+        !self.done
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Decodes an HTTP body sent with `Transfer-Encoding: chunked`.
+struct ChunkedReader<R: BufRead> {
+    reader: R,
+    // Bytes left in the current chunk, or None when the next chunk size must be read.
+    remaining: Option<u64>,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            remaining: None,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            match self.remaining {
+                None => {
+                    let mut line = Vec::new();
+                    read_line(&mut self.reader, &mut line)?;
+                    // Chunk extensions follow a semicolon.
+                    let line = String::from_utf8_lossy(&line);
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    let size = u64::from_str_radix(size, 16)
+                        .map_err(|_| invalid_record("invalid HTTP chunk size"))?;
+                    if size == 0 {
+                        self.done = true;
+                        return Ok(0);
+                    }
+                    self.remaining = Some(size);
+                }
+                Some(0) => {
+                    // The CRLF after the chunk data.
+                    read_line(&mut self.reader, &mut Vec::new())?;
+                    self.remaining = None;
+                }
+                Some(remaining) => {
+                    let max = buf.len().min(remaining as usize);
+                    let read = self.reader.read(&mut buf[..max])?;
+                    if read == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    self.remaining = Some(remaining - read as u64);
+                    return Ok(read);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+#[cfg(not(all(feature = "bzip2", feature = "xz")))]
+use crate::Format;
+use crate::container::{BoundedReader, Container, Items, is_xar, skip};
+#[cfg(feature = "xz")]
+use crate::stream::{LzmaDecoder, XzDecoder};
+use crate::{FileItem, FileKind, Metadata, SizeHint};
+use flate2::read::ZlibDecoder;
+use quick_xml::events::Event;
+use std::fmt::Debug;
+use std::io;
+#[cfg(feature = "xz")]
+use std::io::BufReader;
+use std::io::Read;
+use std::path::PathBuf;
+use tracing::trace;
+
+const XAR_HEADER_SIZE: usize = 28;
+
+/// A XAR archive, as used by macOS installer packages (`.pkg`).
+///
+/// The table of contents is a zlib compressed XML document that precedes the heap. Entries are
+/// read from the heap in offset order, so the archive can be read without seeking.
+pub struct XarContainer<T: Read> {
+    reader: T,
+}
+
+impl<T: Read> Debug for XarContainer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Xar").finish()
+    }
+}
+
+impl<T: Read> XarContainer<T> {
+    pub fn new(reader: T) -> Self {
+        Self { reader }
+    }
+}
+
+impl<T: Read> Container for XarContainer<T> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        let toc = read_toc(&mut self.reader)?;
+        let mut entries = parse_toc(&toc)?;
+        // Reading must happen in heap order. Entries without data, such as directories, come first.
+        entries.sort_by_key(|e| e.data.as_ref().map(|d| d.offset));
+        Ok(XarFileIter {
+            reader: &mut self.reader,
+            entries: entries.into_iter(),
+            position: 0,
+            remaining: 0,
+        })
+    }
+}
+
+fn read_toc(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0; XAR_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if !is_xar(&header) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid xar header magic",
+        ));
+    }
+    let header_size = u16::from_be_bytes([header[4], header[5]]) as u64;
+    let toc_compressed = u64::from_be_bytes(header[8..16].try_into().unwrap());
+    let toc_uncompressed = u64::from_be_bytes(header[16..24].try_into().unwrap());
+    // Custom checksum names are stored after the fixed header.
+    let extra = header_size.saturating_sub(XAR_HEADER_SIZE as u64);
+    skip(reader, extra)?;
+
+    let mut toc = Vec::with_capacity(toc_uncompressed.min(1 << 24) as usize);
+    ZlibDecoder::new(reader.take(toc_compressed)).read_to_end(&mut toc)?;
+    Ok(toc)
+}
+
+#[derive(Debug, Default)]
+struct XarData {
+    offset: u64,
+    length: u64,
+    size: u64,
+    encoding: String,
+}
+
+#[derive(Debug)]
+struct XarEntry {
+    path: PathBuf,
+    kind: FileKind,
+    data: Option<XarData>,
+}
+
+#[derive(Default)]
+struct PartialEntry {
+    name: String,
+    kind: Option<FileKind>,
+    data: Option<XarData>,
+}
+
+fn invalid_toc(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid xar table of contents: {e}"),
+    )
+}
+
+fn parse_toc(toc: &[u8]) -> io::Result<Vec<XarEntry>> {
+    let mut reader = quick_xml::Reader::from_reader(toc);
+    let mut buf = Vec::new();
+    // Names of the open elements, used to tell a file's <data> apart from e.g. extended attributes.
+    let mut elements: Vec<Vec<u8>> = Vec::new();
+    let mut files: Vec<PartialEntry> = Vec::new();
+    let mut entries = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(invalid_toc)? {
+            Event::Start(e) => {
+                let name = e.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"file" => files.push(PartialEntry::default()),
+                    b"data" if elements.last().is_some_and(|p| p == b"file") => {
+                        if let Some(file) = files.last_mut() {
+                            file.data = Some(XarData::default());
+                        }
+                    }
+                    _ => {}
+                }
+                elements.push(name);
+            }
+            Event::Empty(e) => {
+                let in_data = elements.last().is_some_and(|p| p == b"data")
+                    && elements.iter().rev().nth(1).is_some_and(|p| p == b"file");
+                if e.name().as_ref() == b"encoding" && in_data {
+                    let style = e
+                        .try_get_attribute("style")
+                        .map_err(invalid_toc)?
+                        .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                    if let Some(data) = files.last_mut().and_then(|f| f.data.as_mut()) {
+                        data.encoding = style.unwrap_or_default();
+                    }
+                }
+            }
+            Event::Text(t) => {
+                let text = t.unescape().map_err(invalid_toc)?;
+                let Some(file) = files.last_mut() else {
+                    continue;
+                };
+                let parent = elements.iter().rev().nth(1).map(Vec::as_slice);
+                match (parent, elements.last().map(Vec::as_slice)) {
+                    (Some(b"file"), Some(b"name")) => file.name = text.into_owned(),
+                    (Some(b"file"), Some(b"type")) => {
+                        file.kind = Some(match text.as_ref() {
+                            "file" => FileKind::File,
+                            "directory" => FileKind::Directory,
+                            _ => FileKind::Other,
+                        })
+                    }
+                    (Some(b"data"), Some(field))
+                        if elements.iter().rev().nth(2).is_some_and(|p| p == b"file") =>
+                    {
+                        if let Some(data) = file.data.as_mut() {
+                            let value = || text.trim().parse::<u64>().map_err(invalid_toc);
+                            match field {
+                                b"offset" => data.offset = value()?,
+                                b"length" => data.length = value()?,
+                                b"size" => data.size = value()?,
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                elements.pop();
+                if e.name().as_ref() == b"file" {
+                    let file = files.pop().expect("unbalanced xar file element");
+                    let path: PathBuf = files
+                        .iter()
+                        .map(|f| f.name.as_str())
+                        .chain([file.name.as_str()])
+                        .collect();
+                    entries.push(XarEntry {
+                        path,
+                        kind: file.kind.unwrap_or(FileKind::Other),
+                        data: file.data,
+                    });
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(entries)
+}
+
+pub struct XarFileIter<'a, T: Read> {
+    reader: &'a mut T,
+    entries: std::vec::IntoIter<XarEntry>,
+    // Current offset within the heap, and the unread heap bytes of the previous entry.
+    position: u64,
+    remaining: u64,
+}
+
+impl<T: Read> Items for XarFileIter<'_, T> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        // Decoders may stop before the end of their input, or the caller may not have read the
+        // whole entry, so skip what is left of it.
+        if let Err(e) = skip(&mut self.reader, self.remaining) {
+            return Some(Err(e));
+        }
+        self.remaining = 0;
+
+        loop {
+            let entry = self.entries.next()?;
+            let Some(data) = entry.data else {
+                return Some(Ok(FileItem {
+                    path: entry.path,
+                    reader: Box::new(io::empty()) as Box<dyn Read + '_>,
+                    kind: entry.kind,
+                    size_hint: SizeHint::Exact(0),
+                    metadata: Metadata::default(),
+                    content_type: None,
+                    checksum: None,
+                    offsets: None,
+                }));
+            };
+            if data.offset < self.position {
+                // Hard links share heap data with an entry that has already been read.
+                trace!(path = ?entry.path, "skipping xar entry with already consumed data");
+                continue;
+            }
+            if let Err(e) = skip(&mut self.reader, data.offset - self.position) {
+                return Some(Err(e));
+            }
+            self.position = data.offset + data.length;
+            self.remaining = data.length;
+
+            let raw = BoundedReader::new(&mut self.reader, &mut self.remaining);
+            let reader: Box<dyn Read + '_> = match data.encoding.as_str() {
+                // Despite its name, this is zlib rather than gzip.
+                "application/x-gzip" => Box::new(ZlibDecoder::new(raw)),
+                #[cfg(feature = "bzip2")]
+                "application/x-bzip2" => Box::new(bzip2::read::BzDecoder::new(raw)),
+                #[cfg(not(feature = "bzip2"))]
+                "application/x-bzip2" => return Some(Err(Format::Bzip2.unsupported())),
+                // Legacy `.lzma` data, without the xz container.
+                #[cfg(feature = "xz")]
+                "application/x-lzma" => match LzmaDecoder::new(BufReader::new(raw)) {
+                    Ok(decoder) => Box::new(decoder),
+                    Err(e) => return Some(Err(e)),
+                },
+                #[cfg(feature = "xz")]
+                "application/x-xz" => Box::new(XzDecoder::new_multi(BufReader::new(raw))),
+                #[cfg(not(feature = "xz"))]
+                "application/x-lzma" | "application/x-xz" => {
+                    return Some(Err(Format::Xz.unsupported()));
+                }
+                _ => Box::new(raw),
+            };
+            return Some(Ok(FileItem {
+                path: entry.path,
+                reader,
+                kind: entry.kind,
+                size_hint: SizeHint::Exact(data.size),
+                metadata: Metadata::default(),
+                content_type: None,
+                checksum: None,
+                offsets: None,
+            }));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::container::{Container, Items};
+use crate::{Checksum, EntryOffsets, FileItem, FileKind, Metadata, SizeHint};
+use std::fmt::Debug;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use tracing::trace;
+use zip::ZipArchive;
+
+/// A zip archive read through its central directory, which needs a seekable input.
+///
+/// Unlike reading the local headers as a stream, entries that were deleted or replaced after
+/// the archive was written are not yielded, sizes are exact even for entries written with data
+/// descriptors, and the archive may be preceded by other data.
+pub struct ZipArchiveContainer<T: Read + Seek> {
+    archive: ZipArchive<T>,
+}
+
+impl<T: Read + Seek> Debug for ZipArchiveContainer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZipArchive")
+            .field("len", &self.archive.len())
+            .field("offset", &self.archive.offset())
+            .finish()
+    }
+}
+
+impl<T: Read + Seek> ZipArchiveContainer<T> {
+    /// Reads the central directory, returning the reader unchanged if it isn't a zip archive.
+    pub fn try_new(mut reader: T) -> io::Result<Result<Self, T>> {
+        let start = reader.stream_position()?;
+        // `ZipArchive::new` drops the reader on failure, so the probe borrows it.
+        let probe = ZipArchive::new(&mut reader).map(drop);
+        reader.seek(SeekFrom::Start(start))?;
+        if let Err(e) = probe {
+            trace!("no zip central directory: {e}");
+            return Ok(Err(reader));
+        }
+        let archive = ZipArchive::new(reader)?;
+        Ok(Ok(Self { archive }))
+    }
+}
+
+impl<T: Read + Seek> Container for ZipArchiveContainer<T> {
+    fn items(&mut self) -> io::Result<impl Items> {
+        Ok(ZipArchiveFileIter {
+            archive: &mut self.archive,
+            index: 0,
+        })
+    }
+}
+
+pub struct ZipArchiveFileIter<'a, T: Read + Seek> {
+    archive: &'a mut ZipArchive<T>,
+    index: usize,
+}
+
+impl<T: Read + Seek> Items for ZipArchiveFileIter<'_, T> {
+    fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
+        while self.index < self.archive.len() {
+            let index = self.index;
+            self.index += 1;
+            // The entry reader borrows the archive, so the name is checked with a separate lookup.
+            let is_enclosed = match self.archive.by_index_raw(index) {
+                Ok(item) => item.enclosed_name().is_some(),
+                Err(e) => return Some(Err(e.into())),
+            };
+            if !is_enclosed {
+                trace!(index, "skipping zip entry with an unsafe path");
+                continue;
+            }
+            let item = match self.archive.by_index(index) {
+                Ok(item) => item,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let path = item.enclosed_name().unwrap_or_default();
+            let kind = if item.is_file() {
+                FileKind::File
+            } else if item.is_dir() {
+                FileKind::Directory
+            } else {
+                FileKind::Other
+            };
+            let size_hint = SizeHint::Exact(item.size());
+            let checksum = item.is_file().then(|| Checksum::Crc32(item.crc32()));
+            let offsets = EntryOffsets {
+                header: item.header_start(),
+                data: item.data_start(),
+            };
+            return Some(Ok(FileItem {
+                path,
+                reader: item,
+                kind,
+                size_hint,
+                metadata: Metadata::default(),
+                content_type: None,
+                checksum,
+                offsets: Some(offsets),
+            }));
+        }
+        None
+    }
+}
@@ -1,5 +1,5 @@
 use crate::container::{Container, Items};
-use crate::{FileItem, FileKind, SizeHint};
+use crate::{Checksum, EntryOffsets, FileItem, FileKind, Metadata, SizeHint};
 use std::fmt::Debug;
 use std::io;
 use std::io::Read;
@@ -18,39 +18,94 @@ impl<T: Read> ZipContainer<T> {
     pub fn new(reader: T) -> Self {
         Self { reader }
     }
+
+    pub fn into_inner(self) -> T {
+        self.reader
+    }
 }
 
 impl<T: Read> Container for ZipContainer<T> {
     fn items(&mut self) -> io::Result<impl Items> {
         Ok(ZipFileIter {
-            reader: &mut self.reader,
+            reader: CountingReader {
+                reader: &mut self.reader,
+                position: 0,
+            },
+            done: false,
         })
     }
 }
 
 pub struct ZipFileIter<'a, T: Read> {
-    reader: &'a mut T,
+    reader: CountingReader<&'a mut T>,
+    done: bool,
+}
+
+// Entries read from a stream do not know where they start, so the bytes read are counted
+// instead. Each entry reads the rest of its data when it is dropped, so the count is at the
+// start of the next header when it is read.
+struct CountingReader<R> {
+    reader: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
 }
 
 impl<T: Read> Items for ZipFileIter<'_, T> {
     fn next_item(&mut self) -> Option<io::Result<FileItem<impl Read>>> {
-        if let Ok(Some(item)) = zip::read::read_zipfile_from_stream(&mut self.reader) {
-            let path = item.enclosed_name().unwrap().to_path_buf();
-            let kind = if item.is_file() {
-                FileKind::File
-            } else if item.is_dir() {
-                FileKind::Directory
-            } else {
-                FileKind::Other
-            };
-            let size_hint = SizeHint::Exact(item.size());
-            return Some(Ok(FileItem {
-                path,
-                reader: item,
-                kind,
-                size_hint,
-            }));
+        if self.done {
+            return None;
         }
-        None
+        let header = self.reader.position;
+        let item = match zip::read::read_zipfile_from_stream(&mut self.reader) {
+            Ok(Some(item)) => item,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            // The next header cannot be found after a broken one.
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+        };
+        let Some(path) = item.enclosed_name() else {
+            self.done = true;
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "zip entry name leaves the archive",
+            )));
+        };
+        let kind = if item.is_file() {
+            FileKind::File
+        } else if item.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::Other
+        };
+        let size_hint = SizeHint::Exact(item.size());
+        let checksum = item.is_file().then(|| Checksum::Crc32(item.crc32()));
+        // A local header is 30 bytes, followed by the name and the extra field.
+        let extra_len = item.extra_data().map_or(0, <[u8]>::len);
+        let offsets = EntryOffsets {
+            header,
+            data: header + 30 + (item.name_raw().len() + extra_len) as u64,
+        };
+        Some(Ok(FileItem {
+            path,
+            reader: item,
+            kind,
+            size_hint,
+            metadata: Metadata::default(),
+            content_type: None,
+            checksum,
+            offsets: Some(offsets),
+        }))
     }
 }
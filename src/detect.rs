@@ -0,0 +1,109 @@
+//! Identifying formats without reading their contents.
+
+use crate::container::ARCHIVE_BUF_SIZE;
+use crate::peekable::Peekable;
+use crate::registry::{Detected, Format, FormatRegistry};
+use crate::stream::StreamKind;
+use std::io;
+use std::io::{BufReader, Read};
+
+/// The format found at the start of a buffer by [`detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetectedFormat {
+    /// A compression format, which wraps another layer of data.
+    Compressed(Format),
+    /// An archive format.
+    Archive(Format),
+    /// None of the built-in formats.
+    Unknown,
+}
+
+impl DetectedFormat {
+    /// The built-in format, if one was detected.
+    pub fn format(&self) -> Option<Format> {
+        match self {
+            Self::Compressed(f) | Self::Archive(f) => Some(*f),
+            Self::Unknown => None,
+        }
+    }
+}
+
+/// A layer found by [`detect_layers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedLayer {
+    pub format: Format,
+    /// The number of bytes read from this layer's input to detect it and every layer inside it,
+    /// including any read ahead by decoders.
+    pub bytes_read: u64,
+}
+
+/// Returns the format that `buf`, the first bytes of an input, is in. Formats are detected even if
+/// their cargo feature is disabled. Archives such as tar need up to 262 bytes to be detected.
+///
+/// ```rust
+/// use anyreader::{DetectedFormat, Format, detect};
+///
+/// assert_eq!(detect(b"\x1f\x8b\x08\x00"), DetectedFormat::Compressed(Format::Gzip));
+/// assert_eq!(detect(b"hello world"), DetectedFormat::Unknown);
+/// ```
+pub fn detect(buf: &[u8]) -> DetectedFormat {
+    match FormatRegistry::default().detect(buf) {
+        Some(Detected::Builtin(format)) if format.is_compression() => {
+            DetectedFormat::Compressed(format)
+        }
+        Some(Detected::Builtin(format)) => DetectedFormat::Archive(format),
+        _ => DetectedFormat::Unknown,
+    }
+}
+
+/// Detects the chain of formats that `reader` is in, such as `[Gzip, Tar]` for a `.tar.gz` file.
+///
+/// Compression layers are decoded just far enough to detect the layer inside them, and the chain
+/// stops at the first archive. The entries of an archive are not read, and a plain file returns
+/// no layers. A compression format whose cargo feature is disabled returns an
+/// [`Unsupported`](io::ErrorKind::Unsupported) error, as the layer inside it cannot be detected.
+pub fn detect_layers(mut reader: impl Read) -> io::Result<Vec<DetectedLayer>> {
+    let mut layers = vec![];
+    detect_layers_inner(&mut reader, &mut layers)?;
+    Ok(layers)
+}
+
+fn detect_layers_inner(reader: &mut dyn Read, layers: &mut Vec<DetectedLayer>) -> io::Result<()> {
+    let mut bytes_read = 0;
+    let counting = CountingReader {
+        reader,
+        count: &mut bytes_read,
+    };
+    let reader = BufReader::with_capacity(ARCHIVE_BUF_SIZE, counting);
+    let mut peekable: Peekable<_, ARCHIVE_BUF_SIZE> = Peekable::new(reader)?;
+    match detect(peekable.peek_buf()?) {
+        DetectedFormat::Compressed(format) => {
+            let index = layers.len();
+            layers.push(DetectedLayer {
+                format,
+                bytes_read: 0,
+            });
+            {
+                let mut stream = StreamKind::from_peekable(peekable)?;
+                detect_layers_inner(&mut stream, layers)?;
+            }
+            layers[index].bytes_read = bytes_read;
+        }
+        DetectedFormat::Archive(format) => layers.push(DetectedLayer { format, bytes_read }),
+        DetectedFormat::Unknown => {}
+    }
+    Ok(())
+}
+
+struct CountingReader<'a> {
+    reader: &'a mut dyn Read,
+    count: &'a mut u64,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        *self.count += read as u64;
+        Ok(read)
+    }
+}
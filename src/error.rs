@@ -0,0 +1,215 @@
+//! Errors that record where in a nested input they happened.
+
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The broad cause of an [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The data is not valid for its format.
+    Corrupt,
+    /// The data ended before the format said it would.
+    Truncated,
+    /// The format, or a feature of it, is not supported.
+    Unsupported,
+    /// A configured limit, such as [`SpoolOptions::max_size`](crate::SpoolOptions::max_size), was
+    /// exceeded.
+    LimitExceeded,
+    /// The callback returned an error.
+    Callback,
+    /// Any other I/O error, such as failing to read the input itself.
+    Io,
+}
+
+impl ErrorCategory {
+    fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Truncated,
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => Self::Corrupt,
+            io::ErrorKind::Unsupported => Self::Unsupported,
+            io::ErrorKind::FileTooLarge | io::ErrorKind::QuotaExceeded => Self::LimitExceeded,
+            _ => Self::Io,
+        }
+    }
+}
+
+/// An error from reading an input, with the path of the entry that failed.
+///
+/// The callbacks passed to functions such as [`recursive_read`](crate::recursive_read) return
+/// [`io::Error`], so errors converted into one keep this error as their inner error:
+///
+/// ```rust
+/// use anyreader::{Error, recursive_read};
+/// use std::io;
+/// use std::path::Path;
+///
+/// fn read(data: &[u8]) -> io::Result<()> {
+///     recursive_read(Path::new("input.gz"), data, &mut |item| {
+///         io::copy(item.reader, &mut io::sink())?;
+///         Ok(())
+///     })?;
+///     Ok(())
+/// }
+///
+/// // A truncated gzip stream.
+/// let err = read(&[0x1f, 0x8b, 0x08, 0x00]).unwrap_err();
+/// let err = err.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+/// assert_eq!(err.path(), Path::new("input.gz"));
+/// assert_eq!(err.layers(), ["gzip"]);
+/// ```
+#[derive(Debug)]
+pub struct Error {
+    category: ErrorCategory,
+    path: PathBuf,
+    layers: Vec<String>,
+    source: io::Error,
+    // Set once the error should stop reading, rather than skip an entry.
+    fatal: bool,
+}
+
+impl Error {
+    /// The cause of the error.
+    pub fn category(&self) -> ErrorCategory {
+        self.category
+    }
+
+    /// The full path of the entry that failed, such as `input.zip/nested.tar.gz/file`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The formats that were being read when the error happened, outermost first, such as
+    /// `["zip", "xz"]` for an xz stream inside a zip archive.
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// The kind of the underlying [`io::Error`].
+    pub fn kind(&self) -> io::ErrorKind {
+        self.source.kind()
+    }
+
+    /// The error returned by the format or the callback.
+    pub fn io_error(&self) -> &io::Error {
+        &self.source
+    }
+
+    /// Converts an error returned while reading `path`, taking the context from it if it has
+    /// any.
+    pub(crate) fn from_io(err: io::Error, path: &Path) -> Self {
+        if has_context(&err) {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+        Self::new(ErrorCategory::from_io(&err), path, &[], err)
+    }
+
+    fn new(category: ErrorCategory, path: &Path, layers: &[String], source: io::Error) -> Self {
+        Self {
+            category,
+            path: path.to_path_buf(),
+            layers: layers.to_vec(),
+            source,
+            fatal: false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if !self.layers.is_empty() {
+            write!(f, " ({})", self.layers.join(" > "))?;
+        }
+        write!(f, ": {}", self.source)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        io::Error::new(value.source.kind(), value)
+    }
+}
+
+fn has_context(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|e| e.is::<Error>())
+}
+
+/// Records that an error without context happened while reading `path` inside `layers`.
+pub(crate) fn with_context(err: io::Error, path: &Path, layers: &[String]) -> io::Error {
+    if has_context(&err) {
+        return err;
+    }
+    Error::new(ErrorCategory::from_io(&err), path, layers, err).into()
+}
+
+/// Records that an error returned for `path` came from the callback, unless it came from
+/// reading the entry. Errors from the callback stop reading.
+pub(crate) fn from_callback(err: io::Error, path: &Path, layers: &[String]) -> io::Error {
+    let mut err = if has_context(&err) {
+        err
+    } else {
+        Error::new(ErrorCategory::Callback, path, layers, err).into()
+    };
+    if let Some(inner) = err.get_mut().and_then(|e| e.downcast_mut::<Error>())
+        && inner.category == ErrorCategory::Callback
+    {
+        inner.fatal = true;
+    }
+    err
+}
+
+/// Marks an error as one that stops reading, rather than one that skips an entry.
+pub(crate) fn fatal(err: io::Error, path: &Path, layers: &[String]) -> io::Error {
+    let mut err = from_callback(err, path, layers);
+    if let Some(inner) = err.get_mut().and_then(|e| e.downcast_mut::<Error>()) {
+        inner.fatal = true;
+    }
+    err
+}
+
+/// Returns true if the error should stop reading, rather than skip an entry.
+pub(crate) fn is_fatal(err: &io::Error) -> bool {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<Error>())
+        .is_some_and(|e| e.fatal)
+}
+
+/// Adds the path of an entry to errors from reading it, so they are not mistaken for errors
+/// from the callback that is reading it. Also counts the bytes read through it.
+pub(crate) struct ContextReader<'a> {
+    pub(crate) reader: &'a mut dyn Read,
+    pub(crate) path: &'a Path,
+    pub(crate) layers: &'a [String],
+    pub(crate) read: u64,
+}
+
+impl<'a> ContextReader<'a> {
+    pub(crate) fn new(reader: &'a mut dyn Read, path: &'a Path, layers: &'a [String]) -> Self {
+        Self {
+            reader,
+            path,
+            layers,
+            read: 0,
+        }
+    }
+}
+
+impl Read for ContextReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::Interrupted => e,
+            _ => with_context(e, self.path, self.layers),
+        })?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
@@ -0,0 +1,212 @@
+//! Include and exclude patterns for the paths of files, checked before entries are read.
+
+use std::path::Path;
+
+/// The patterns set with [`ReadOptions::include`](crate::ReadOptions::include) and
+/// [`ReadOptions::exclude`](crate::ReadOptions::exclude).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub(crate) fn include(&mut self, pattern: &str) {
+        self.include.push(Pattern::new(pattern));
+    }
+
+    pub(crate) fn exclude(&mut self, pattern: &str) {
+        self.exclude.push(Pattern::new(pattern));
+    }
+
+    /// Returns false if no file at or below `path` can be yielded, so it can be skipped without
+    /// reading it.
+    pub(crate) fn allows(&self, path: &Path) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+        let names = names(path);
+        !self.is_excluded(&names)
+            && (self.include.is_empty()
+                || self
+                    .include
+                    .iter()
+                    .any(|p| p.matches_within(&names) || p.matches(&names, true)))
+    }
+
+    /// Returns true if the file at `path` should be yielded.
+    pub(crate) fn yields(&self, path: &Path) -> bool {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+        let names = names(path);
+        !self.is_excluded(&names)
+            && (self.include.is_empty() || self.include.iter().any(|p| p.matches_within(&names)))
+    }
+
+    fn is_excluded(&self, names: &[String]) -> bool {
+        self.exclude.iter().any(|p| p.matches_within(names))
+    }
+}
+
+fn names(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// `**`, which matches any number of path components.
+    AnyDepth,
+    /// A pattern for a single path component.
+    Name(Vec<char>),
+}
+
+/// A glob pattern, matched one path component at a time.
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        let mut segments: Vec<_> = pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| match s {
+                "**" => Segment::AnyDepth,
+                s => Segment::Name(s.chars().collect()),
+            })
+            .collect();
+        // Like `.gitignore`, a pattern without a separator matches a name at any depth.
+        if !pattern.contains('/') {
+            segments.insert(0, Segment::AnyDepth);
+        }
+        Self { segments }
+    }
+
+    /// Returns true if the pattern matches `names` or one of its parents.
+    fn matches_within(&self, names: &[String]) -> bool {
+        (1..=names.len()).any(|len| self.matches(&names[..len], false))
+    }
+
+    /// Returns true if the pattern matches `names`, or with `partial` if it could match
+    /// something below it.
+    fn matches(&self, names: &[String], partial: bool) -> bool {
+        match_segments(&self.segments, names, partial)
+    }
+}
+
+fn match_segments(segments: &[Segment], names: &[String], partial: bool) -> bool {
+    match (segments.split_first(), names.split_first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(_), None) => partial || segments.iter().all(|s| *s == Segment::AnyDepth),
+        (Some((Segment::AnyDepth, rest)), Some((_, tail))) => {
+            match_segments(rest, names, partial) || match_segments(segments, tail, partial)
+        }
+        (Some((Segment::Name(pattern), rest)), Some((name, tail))) => {
+            let name: Vec<char> = name.chars().collect();
+            match_name(pattern, &name) && match_segments(rest, tail, partial)
+        }
+    }
+}
+
+/// Matches a single path component against `*`, `?` and `[...]` wildcards.
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_name(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some((&c, tail)) = name.split_first() else {
+                return false;
+            };
+            match match_class(rest, c) {
+                Some((matched, rest)) => matched && match_name(rest, tail),
+                // An unclosed bracket is matched literally.
+                None => c == '[' && match_name(rest, tail),
+            }
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && match_name(rest, &name[1..]),
+    }
+}
+
+/// Matches `c` against a class such as `[a-z]` or `[!0-9]`, starting after the `[`. Returns
+/// whether it matched and the rest of the pattern, or `None` if the class is not closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut class) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match class {
+            [] => return None,
+            [']', rest @ ..] if !first => return Some((matched != negated, rest)),
+            [lo, '-', hi, rest @ ..] if *hi != ']' => {
+                matched |= (*lo..=*hi).contains(&c);
+                class = rest;
+            }
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                class = rest;
+            }
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> Filter {
+        let mut filter = Filter::default();
+        include.iter().for_each(|p| filter.include(p));
+        exclude.iter().for_each(|p| filter.exclude(p));
+        filter
+    }
+
+    #[test]
+    fn test_match_name() {
+        let matches = |p: &str, n: &str| {
+            match_name(
+                &p.chars().collect::<Vec<_>>(),
+                &n.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("*.txt", "a.txt"));
+        assert!(!matches("*.txt", "a.txt.gz"));
+        assert!(matches("file?.[ch]", "file1.c"));
+        assert!(!matches("file?.[ch]", "file1.o"));
+        assert!(matches("[!a-c]*", "def"));
+        assert!(!matches("[!a-c]*", "abc"));
+        assert!(matches("[x", "[x"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let f = filter(&[], &["**/node_modules/**"]);
+        assert!(f.allows(Path::new("in.tar/app/src")));
+        assert!(!f.allows(Path::new("in.tar/app/node_modules")));
+        assert!(!f.yields(Path::new("in.tar/app/node_modules/x/index.js")));
+
+        // Patterns without a separator match names at any depth.
+        let f = filter(&["*.txt"], &["secret.txt"]);
+        assert!(f.yields(Path::new("in.tar/a/b.txt")));
+        assert!(!f.yields(Path::new("in.tar/a/secret.txt")));
+        assert!(!f.yields(Path::new("in.tar/a/b.md")));
+        // Anything could hold a matching file.
+        assert!(f.allows(Path::new("in.tar/a/b.md")));
+
+        let f = filter(&["in.tar/pkg/**/*.class"], &[]);
+        assert!(f.allows(Path::new("in.tar")));
+        assert!(f.allows(Path::new("in.tar/pkg/lib.jar")));
+        assert!(f.yields(Path::new("in.tar/pkg/lib.jar/a/B.class")));
+        assert!(!f.allows(Path::new("in.tar/docs")));
+        assert!(!f.yields(Path::new("in.tar/pkg/lib.jar/a/B.java")));
+    }
+}
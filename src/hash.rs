@@ -0,0 +1,131 @@
+//! Hashing the contents of every file as it is read.
+
+use sha2::Digest as _;
+use std::fmt::{Display, Formatter, Write};
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// An algorithm to hash files with, set with [`ReadOptions::hash`](crate::ReadOptions::hash).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+/// The hash of a file's contents.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    pub algorithm: HashAlgorithm,
+    pub bytes: Vec<u8>,
+}
+
+impl Digest {
+    /// The hash as lowercase hexadecimal.
+    pub fn to_hex(&self) -> String {
+        self.bytes.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.to_hex())
+    }
+}
+
+/// The hashes of a file, passed to [`Visitor::hashed`](crate::Visitor::hashed) once all of it
+/// has been read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    /// The full path of the file.
+    pub path: PathBuf,
+    /// The size of the file, after any decompression.
+    pub size: u64,
+    /// A digest for each configured algorithm, in the order they were configured.
+    pub digests: Vec<Digest>,
+}
+
+impl FileHashes {
+    /// Returns the digest for `algorithm`, if it was configured.
+    pub fn get(&self, algorithm: HashAlgorithm) -> Option<&Digest> {
+        self.digests.iter().find(|d| d.algorithm == algorithm)
+    }
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+/// Hashes the data read through it with every configured algorithm.
+pub(crate) struct HashingReader<'a> {
+    reader: &'a mut dyn Read,
+    hashers: Vec<Hasher>,
+    size: u64,
+}
+
+impl<'a> HashingReader<'a> {
+    pub(crate) fn new(reader: &'a mut dyn Read, algorithms: &[HashAlgorithm]) -> Self {
+        let hashers = algorithms
+            .iter()
+            .map(|algorithm| match algorithm {
+                HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+                HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            })
+            .collect();
+        Self {
+            reader,
+            hashers,
+            size: 0,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.hashers.is_empty()
+    }
+
+    /// Reads the rest of the file, and returns its hashes.
+    pub(crate) fn finish(mut self, path: PathBuf) -> io::Result<FileHashes> {
+        io::copy(&mut self, &mut io::sink())?;
+        let digests = self
+            .hashers
+            .into_iter()
+            .map(|hasher| match hasher {
+                Hasher::Sha256(h) => Digest {
+                    algorithm: HashAlgorithm::Sha256,
+                    bytes: h.finalize().to_vec(),
+                },
+                Hasher::Blake3(h) => Digest {
+                    algorithm: HashAlgorithm::Blake3,
+                    bytes: h.finalize().as_bytes().to_vec(),
+                },
+            })
+            .collect();
+        Ok(FileHashes {
+            path,
+            size: self.size,
+            digests,
+        })
+    }
+}
+
+impl Read for HashingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        for hasher in &mut self.hashers {
+            match hasher {
+                Hasher::Sha256(h) => h.update(&buf[..read]),
+                Hasher::Blake3(h) => {
+                    h.update(&buf[..read]);
+                }
+            }
+        }
+        self.size += read as u64;
+        Ok(read)
+    }
+}
@@ -0,0 +1,757 @@
+//! An index of the entries of a compressed tar archive, for reading single entries without
+//! decompressing everything before them.
+
+#[cfg(feature = "gzip")]
+mod inflate;
+#[cfg(feature = "xz")]
+mod xz;
+
+use crate::error::Error;
+#[cfg(feature = "gzip")]
+use crate::index::inflate::Inflate;
+use crate::registry::{Format, FormatRegistry};
+#[cfg(feature = "gzip")]
+use crate::stream::STREAM_BUF_SIZE;
+use crate::stream::StreamKind;
+#[cfg(feature = "xz")]
+use crate::stream::XzDecoder;
+#[cfg(feature = "zstd")]
+use crate::stream::ZstdFrameDecoder;
+use crate::{EntryOffsets, FileItem};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use strum::IntoEnumIterator;
+
+const MAGIC: &[u8] = b"anyreader-index\0";
+const VERSION: u8 = 2;
+/// The most data that a deflate block can refer back to.
+const DEFLATE_WINDOW_SIZE: usize = 32 * 1024;
+// Checkpoints closer together than this are not recorded, so inputs made of many small gzip
+// members or xz blocks, such as BGZF files, do not need a checkpoint for every one of them.
+const CHECKPOINT_SPACING: u64 = 1024 * 1024;
+// How much of the end of an xz stream is kept while decoding it, to read the index of its blocks
+// from once it ends. Streams with larger indexes only get a checkpoint at their start.
+#[cfg(feature = "xz")]
+const XZ_INDEX_LIMIT: usize = 1024 * 1024;
+
+/// A position in a compressed input where decoding can start: the start of a gzip member, zstd
+/// frame, xz stream or xz block, or the end of a deflate block inside a gzip member.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// The offset in the compressed input. A deflate block can start part way through this byte.
+    pub compressed: u64,
+    /// The offset in the decompressed data.
+    pub uncompressed: u64,
+    resume: Resume,
+}
+
+/// What is needed besides the offsets to start decoding at a checkpoint.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Resume {
+    /// Decoding starts from scratch.
+    Start,
+    /// A deflate block starting at bit `bit` of the byte, which can copy from `window`, the
+    /// output before it.
+    Deflate { bit: u8, window: Vec<u8> },
+    /// The xz block numbered `block` of the stream whose index is at `index`.
+    XzBlock { index: u64, block: u64 },
+}
+
+impl Debug for Resume {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Start => f.write_str("Start"),
+            Self::Deflate { bit, window } => f
+                .debug_struct("Deflate")
+                .field("bit", bit)
+                .field("window", &window.len())
+                .finish(),
+            Self::XzBlock { index, block } => f
+                .debug_struct("XzBlock")
+                .field("index", index)
+                .field("block", block)
+                .finish(),
+        }
+    }
+}
+
+impl Checkpoint {
+    fn start(compressed: u64, uncompressed: u64) -> Self {
+        Self {
+            compressed,
+            uncompressed,
+            resume: Resume::Start,
+        }
+    }
+}
+
+/// An entry of an indexed tar archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The path of the entry within the archive.
+    pub path: PathBuf,
+    /// The offsets of the entry's header and data within the decompressed archive.
+    pub offsets: EntryOffsets,
+    /// The size of the entry's data.
+    pub size: u64,
+}
+
+/// The checkpoints and entries of a tar archive, built by
+/// [`ReadOptions::recursive_read_indexed`](crate::ReadOptions::recursive_read_indexed) and used
+/// by [`open_entry`] to read a single entry.
+///
+/// Checkpoints are recorded about every MiB of decompressed data where the format allows it:
+///
+/// - Gzip at the end of any deflate block, keeping the 32 KiB of data before it that later
+///   blocks can refer back to, so that even a file written as a single member has many. This
+///   makes the index about 3% of the size of the decompressed data.
+/// - Xz at the start of every block, as listed by the index at the end of each stream. Files
+///   written by `xz -T0` or with `--block-size` have many blocks, while a stream written as a
+///   single block only has a checkpoint at its start.
+/// - Zstd at the start of every frame. Files written by `pzstd` have many frames, while a file
+///   written as a single frame only has a checkpoint at its start.
+///
+/// Other compression formats only have a checkpoint at the start of the input, and opening an
+/// entry decompresses everything before it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    compression: Option<Format>,
+    checkpoints: Vec<Checkpoint>,
+    entries: Vec<IndexEntry>,
+}
+
+impl Index {
+    /// The compression format of the archive, if it is compressed.
+    pub fn compression(&self) -> Option<Format> {
+        self.compression
+    }
+
+    /// The positions where decoding can start, in order.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// The entries of the archive, in the order they are stored.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the last entry stored at `path`, as later entries replace earlier ones when a tar
+    /// archive is extracted.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&IndexEntry> {
+        let path = path.as_ref();
+        self.entries.iter().rev().find(|e| e.path == path)
+    }
+
+    /// Writes the index in a compact binary format, which [`read_from`](Self::read_from) reads.
+    /// Paths that are not valid UTF-8 are written lossily.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        let compression = self.compression.map(|f| f.to_string()).unwrap_or_default();
+        write_bytes(&mut writer, compression.as_bytes())?;
+        write_u64(&mut writer, self.checkpoints.len() as u64)?;
+        for checkpoint in &self.checkpoints {
+            write_u64(&mut writer, checkpoint.compressed)?;
+            write_u64(&mut writer, checkpoint.uncompressed)?;
+            match &checkpoint.resume {
+                Resume::Start => writer.write_all(&[0])?,
+                Resume::Deflate { bit, window } => {
+                    writer.write_all(&[1, *bit])?;
+                    write_bytes(&mut writer, window)?;
+                }
+                Resume::XzBlock { index, block } => {
+                    writer.write_all(&[2])?;
+                    write_u64(&mut writer, *index)?;
+                    write_u64(&mut writer, *block)?;
+                }
+            }
+        }
+        write_u64(&mut writer, self.entries.len() as u64)?;
+        for entry in &self.entries {
+            write_bytes(&mut writer, entry.path.to_string_lossy().as_bytes())?;
+            write_u64(&mut writer, entry.offsets.header)?;
+            write_u64(&mut writer, entry.offsets.data)?;
+            write_u64(&mut writer, entry.size)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [`write_to`](Self::write_to).
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut magic)?;
+        if &magic[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not an anyreader index"));
+        }
+        if magic[MAGIC.len()] != VERSION {
+            return Err(invalid("unsupported index version"));
+        }
+        let compression = match read_string(&mut reader)?.as_str() {
+            "" => None,
+            name => Some(
+                Format::iter()
+                    .find(|f| f.to_string() == name)
+                    .ok_or_else(|| invalid("unknown compression format"))?,
+            ),
+        };
+        let count = read_u64(&mut reader)?;
+        let mut checkpoints = Vec::new();
+        for _ in 0..count {
+            let compressed = read_u64(&mut reader)?;
+            let uncompressed = read_u64(&mut reader)?;
+            let resume = match read_u8(&mut reader)? {
+                0 => Resume::Start,
+                1 => {
+                    let bit = read_u8(&mut reader)?;
+                    let window = read_bytes(&mut reader)?;
+                    if bit >= 8 || window.len() > DEFLATE_WINDOW_SIZE {
+                        return Err(invalid("invalid deflate checkpoint"));
+                    }
+                    Resume::Deflate { bit, window }
+                }
+                2 => {
+                    let index = read_u64(&mut reader)?;
+                    if compressed > index {
+                        return Err(invalid("xz block checkpoint is after its index"));
+                    }
+                    Resume::XzBlock {
+                        index,
+                        block: read_u64(&mut reader)?,
+                    }
+                }
+                _ => return Err(invalid("unknown checkpoint kind")),
+            };
+            if checkpoints.last().is_some_and(|last: &Checkpoint| {
+                compressed < last.compressed || uncompressed < last.uncompressed
+            }) {
+                return Err(invalid("checkpoints are out of order"));
+            }
+            checkpoints.push(Checkpoint {
+                compressed,
+                uncompressed,
+                resume,
+            });
+        }
+        let count = read_u64(&mut reader)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(IndexEntry {
+                path: PathBuf::from(read_string(&mut reader)?),
+                offsets: EntryOffsets {
+                    header: read_u64(&mut reader)?,
+                    data: read_u64(&mut reader)?,
+                },
+                size: read_u64(&mut reader)?,
+            });
+        }
+        Ok(Self {
+            compression,
+            checkpoints,
+            entries,
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_u64(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    // Read through `take`, so a corrupt length fails at the end of the input rather than
+    // allocating it up front.
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid("path is not valid UTF-8"))
+}
+
+/// Opens the entry at `path` of the archive that `index` was built from, seeking `reader` to the
+/// last checkpoint before it and decompressing from there.
+///
+/// ```rust
+/// use anyreader::{ReadOptions, open_entry};
+/// use std::fs::File;
+/// use std::io::{self, BufReader, Read};
+/// use std::path::Path;
+///
+/// fn main() -> io::Result<()> {
+///     let reader = BufReader::new(File::open("tests/data/archive.tar.gz")?);
+///     let index = ReadOptions::new().recursive_read_indexed(
+///         Path::new("archive.tar.gz"),
+///         reader,
+///         &mut |_| Ok(()),
+///     )?;
+///
+///     let file = File::open("tests/data/archive.tar.gz")?;
+///     let mut contents = String::new();
+///     open_entry(&index, file, "src/lib.rs")?.read_to_string(&mut contents)?;
+///     Ok(())
+/// }
+/// ```
+pub fn open_entry<'a, R: Read + Seek + 'a>(
+    index: &Index,
+    mut reader: R,
+    path: impl AsRef<Path>,
+) -> Result<Box<dyn Read + 'a>, Error> {
+    let path = path.as_ref();
+    let Some(entry) = index.get(path) else {
+        let err = io::Error::new(io::ErrorKind::NotFound, "entry is not in the index");
+        return Err(Error::from_io(err, path));
+    };
+    let size = entry.size;
+    let opened = match index.compression {
+        None => reader
+            .seek(SeekFrom::Start(entry.offsets.data))
+            .map(|_| Box::new(reader.take(size)) as Box<dyn Read>),
+        Some(_) => {
+            let start = Checkpoint::start(0, 0);
+            let checkpoint = index
+                .checkpoints
+                .iter()
+                .rev()
+                .find(|c| c.uncompressed <= entry.offsets.data)
+                .unwrap_or(&start);
+            resume(reader, checkpoint).and_then(|mut decoder| {
+                let skip = entry.offsets.data - checkpoint.uncompressed;
+                let skipped = io::copy(&mut (&mut decoder).take(skip), &mut io::sink())?;
+                if skipped != skip {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(Box::new(decoder.take(size)) as Box<dyn Read>)
+            })
+        }
+    };
+    opened.map_err(|e| Error::from_io(e, path))
+}
+
+/// Decodes the input from `checkpoint` on.
+fn resume<'a, R: Read + Seek + 'a>(
+    mut reader: R,
+    checkpoint: &Checkpoint,
+) -> io::Result<Box<dyn Read + 'a>> {
+    match &checkpoint.resume {
+        Resume::Start => {
+            reader.seek(SeekFrom::Start(checkpoint.compressed))?;
+            Ok(Box::new(StreamKind::from_reader(BufReader::new(reader))?))
+        }
+        #[cfg(feature = "gzip")]
+        Resume::Deflate { bit, window } => {
+            reader.seek(SeekFrom::Start(checkpoint.compressed))?;
+            let member = Inflate::resume(BufReader::new(reader), *bit, window)?;
+            Ok(Box::new(ResumedMember {
+                member: Some(member),
+                rest: None,
+            }))
+        }
+        #[cfg(not(feature = "gzip"))]
+        Resume::Deflate { .. } => Err(Format::Gzip.unsupported()),
+        #[cfg(feature = "xz")]
+        Resume::XzBlock { index, block } => {
+            let stream = xz::resume(reader, checkpoint.compressed, *index, *block)?;
+            Ok(Box::new(StreamKind::from_reader(BufReader::new(stream))?))
+        }
+        #[cfg(not(feature = "xz"))]
+        Resume::XzBlock { .. } => Err(Format::Xz.unsupported()),
+    }
+}
+
+/// Decodes the rest of a gzip member from a deflate block inside it, and then the members after
+/// it.
+#[cfg(feature = "gzip")]
+struct ResumedMember<R: BufRead> {
+    member: Option<Inflate<R>>,
+    rest: Option<StreamKind<io::Chain<io::Cursor<Vec<u8>>, R>, STREAM_BUF_SIZE>>,
+}
+
+#[cfg(feature = "gzip")]
+impl<R: BufRead> Read for ResumedMember<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(member) = &mut self.member {
+            let read = member.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let (reader, unused) = self.member.take().unwrap().into_inner();
+            self.rest = Some(StreamKind::from_reader(
+                io::Cursor::new(unused).chain(reader),
+            )?);
+        }
+        match &mut self.rest {
+            Some(rest) => rest.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Records the entries of the archive at the top of the input.
+pub(crate) struct IndexBuilder {
+    layers: usize,
+    entries: Vec<IndexEntry>,
+}
+
+impl IndexBuilder {
+    /// Records an entry read at `depth` archives deep, inside `layers`.
+    pub(crate) fn record<T: Read>(&mut self, layers: &[String], depth: usize, item: &FileItem<T>) {
+        let is_top_tar = depth == 1
+            && layers.len() == self.layers
+            && layers.last().is_some_and(|l| *l == Format::Tar.to_string());
+        let (true, Some(offsets), Some(size)) = (is_top_tar, item.offsets, item.size_hint.exact())
+        else {
+            return;
+        };
+        self.entries.push(IndexEntry {
+            path: item.path.clone(),
+            offsets,
+            size,
+        });
+    }
+}
+
+/// Decompresses the input one gzip member, zstd frame or xz stream at a time, recording where
+/// each one starts, where deflate blocks end inside gzip members, and where xz blocks start.
+/// Other inputs are passed through unchanged.
+pub(crate) struct Segments<R: Read> {
+    compression: Option<Format>,
+    source: Option<Source<R>>,
+    segment: Option<Segment<R>>,
+    // Where the current segment starts.
+    start: Checkpoint,
+    checkpoints: Vec<Checkpoint>,
+    uncompressed: u64,
+}
+
+impl<R: Read> Segments<R> {
+    pub(crate) fn new(reader: R, formats: &FormatRegistry) -> io::Result<Self> {
+        let mut source = Source {
+            inner: BufReader::new(reader),
+            position: 0,
+            pending: vec![],
+            tail: None,
+        };
+        let buf = source.fill_buf()?;
+        // Formats added to the registry are decoded as usual.
+        let compression = formats.detect(buf).and_then(|d| d.compression());
+        Ok(Self {
+            compression,
+            source: Some(source),
+            segment: None,
+            start: Checkpoint::start(0, 0),
+            checkpoints: vec![],
+            uncompressed: 0,
+        })
+    }
+
+    /// Returns a builder for the entries of the tar archive inside the input.
+    pub(crate) fn builder(&self) -> IndexBuilder {
+        // Compression that is not split into segments is a layer of its own.
+        let layers = match self.compression {
+            Some(format) if !is_segmented(format) => 2,
+            _ => 1,
+        };
+        IndexBuilder {
+            layers,
+            entries: vec![],
+        }
+    }
+
+    #[cfg_attr(not(feature = "xz"), allow(unused_mut))]
+    pub(crate) fn finish(mut self, builder: IndexBuilder) -> Index {
+        // Reading stops at the end of the tar archive, but the blocks of an xz stream are only
+        // known once its index at the very end is read. The entries have all been read by now,
+        // so a corrupt end only loses the checkpoints that were not recorded yet.
+        #[cfg(feature = "xz")]
+        if self.compression == Some(Format::Xz) {
+            let _ = io::copy(&mut self, &mut io::sink());
+        }
+        let checkpoints = match self.compression {
+            Some(format) if is_segmented(format) => self.checkpoints,
+            Some(_) => vec![Checkpoint::start(0, 0)],
+            None => vec![],
+        };
+        Index {
+            compression: self.compression,
+            checkpoints,
+            entries: builder.entries,
+        }
+    }
+
+    /// Returns true if a checkpoint at `uncompressed` would be far enough from the last one.
+    fn is_spaced(&self, uncompressed: u64) -> bool {
+        self.checkpoints
+            .last()
+            .is_none_or(|last| uncompressed - last.uncompressed >= CHECKPOINT_SPACING)
+    }
+
+    fn start_segment(&mut self) -> io::Result<bool> {
+        let Some(mut source) = self.source.take() else {
+            return Ok(false);
+        };
+        let format = self.compression.filter(|f| is_segmented(*f));
+        // xz streams can be followed by padding made of zeros.
+        if format == Some(Format::Xz) {
+            loop {
+                let buf = source.fill_buf()?;
+                let zeros = buf.iter().take_while(|b| **b == 0).count();
+                if zeros == 0 {
+                    break;
+                }
+                source.consume(zeros);
+            }
+        }
+        if source.fill_buf()?.is_empty() {
+            self.source = Some(source);
+            return Ok(false);
+        }
+        let checkpoint = Checkpoint::start(source.position, self.uncompressed);
+        self.start = checkpoint.clone();
+        let is_spaced = self.is_spaced(checkpoint.uncompressed);
+        match self.checkpoints.last_mut() {
+            // An empty segment, such as a skippable zstd frame, moves the checkpoint past it.
+            Some(last) if last.uncompressed == checkpoint.uncompressed => *last = checkpoint,
+            _ if is_spaced => self.checkpoints.push(checkpoint),
+            _ => {}
+        }
+        self.segment = Some(match format {
+            #[cfg(feature = "gzip")]
+            Some(Format::Gzip) => Segment::Gzip(Inflate::new(source)),
+            #[cfg(feature = "zstd")]
+            Some(Format::Zstd) => Segment::Zstd(ZstdFrameDecoder::new(source)?),
+            #[cfg(feature = "xz")]
+            Some(Format::Xz) => {
+                source.tail = Some(vec![]);
+                Segment::Xz(XzDecoder::new(source))
+            }
+            _ => Segment::Raw(source),
+        });
+        Ok(true)
+    }
+
+    /// Records a checkpoint at the end of the deflate block that was just read, if there is one.
+    #[cfg(feature = "gzip")]
+    fn end_deflate_block(&mut self) {
+        let Some(Segment::Gzip(inflate)) = &self.segment else {
+            return;
+        };
+        if !self.is_spaced(self.uncompressed) {
+            return;
+        }
+        let Some((unread, window)) = inflate.boundary() else {
+            return;
+        };
+        // The block starts `unread` bits before the end of the bytes read so far.
+        let position = inflate.get_ref().position * 8 - u64::from(unread);
+        let checkpoint = Checkpoint {
+            compressed: position / 8,
+            uncompressed: self.uncompressed,
+            resume: Resume::Deflate {
+                bit: (position % 8) as u8,
+                window: window.to_vec(),
+            },
+        };
+        self.checkpoints.push(checkpoint);
+    }
+
+    /// Records a checkpoint at the start of every block of the xz stream that just ended, from
+    /// the index at its end.
+    #[cfg(feature = "xz")]
+    fn end_xz_stream(&mut self) {
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        let Some(tail) = source.tail.take() else {
+            return;
+        };
+        let Some((records, index_size)) = xz::read_index(&tail) else {
+            return;
+        };
+        let index = source.position - xz::HEADER_SIZE - index_size;
+        let mut compressed = self.start.compressed + xz::HEADER_SIZE;
+        let mut uncompressed = self.start.uncompressed;
+        for (block, record) in records.iter().enumerate() {
+            if block > 0 && self.is_spaced(uncompressed) {
+                self.checkpoints.push(Checkpoint {
+                    compressed,
+                    uncompressed,
+                    resume: Resume::XzBlock {
+                        index,
+                        block: block as u64,
+                    },
+                });
+            }
+            compressed += record.size();
+            uncompressed += record.uncompressed;
+        }
+    }
+}
+
+impl<R: Read> Read for Segments<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            #[cfg(feature = "gzip")]
+            self.end_deflate_block();
+            if let Some(segment) = &mut self.segment {
+                let read = segment.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    self.uncompressed += read as u64;
+                    return Ok(read);
+                }
+                self.source = self.segment.take().map(Segment::into_inner);
+                #[cfg(feature = "xz")]
+                self.end_xz_stream();
+            }
+            if !self.start_segment()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+fn is_segmented(format: Format) -> bool {
+    match format {
+        #[cfg(feature = "gzip")]
+        Format::Gzip => true,
+        #[cfg(feature = "zstd")]
+        Format::Zstd => true,
+        #[cfg(feature = "xz")]
+        Format::Xz => true,
+        _ => false,
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Segment<R: Read> {
+    #[cfg(feature = "gzip")]
+    Gzip(Inflate<Source<R>>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdFrameDecoder<Source<R>>),
+    #[cfg(feature = "xz")]
+    Xz(XzDecoder<Source<R>>),
+    Raw(Source<R>),
+}
+
+impl<R: Read> Segment<R> {
+    fn into_inner(self) -> Source<R> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(d) => {
+                let (mut source, unused) = d.into_inner();
+                source.unread(unused);
+                source
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd(d) => d.into_inner(),
+            #[cfg(feature = "xz")]
+            Self::Xz(d) => d.into_inner(),
+            Self::Raw(source) => source,
+        }
+    }
+}
+
+impl<R: Read> Read for Segment<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(d) => d.read(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(d) => d.read(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(d) => d.read(buf),
+            Self::Raw(source) => source.read(buf),
+        }
+    }
+}
+
+/// The compressed input, counting the bytes consumed from it.
+struct Source<R: Read> {
+    inner: BufReader<R>,
+    position: u64,
+    // Bytes that were handed back, which are read again before the rest of the input.
+    pending: Vec<u8>,
+    // The last bytes consumed, while they are being kept.
+    tail: Option<Vec<u8>>,
+}
+
+impl<R: Read> Source<R> {
+    /// Hands back `bytes`, the last ones consumed, so that they are read again.
+    #[cfg(feature = "gzip")]
+    fn unread(&mut self, mut bytes: Vec<u8>) {
+        self.position -= bytes.len() as u64;
+        bytes.append(&mut self.pending);
+        self.pending = bytes;
+    }
+
+    fn keep(&mut self, consumed: usize) {
+        let Some(tail) = &mut self.tail else {
+            return;
+        };
+        let available = match self.pending.is_empty() {
+            true => self.inner.buffer(),
+            false => &self.pending,
+        };
+        tail.extend_from_slice(&available[..consumed]);
+        #[cfg(feature = "xz")]
+        if tail.len() > 2 * XZ_INDEX_LIMIT {
+            tail.drain(..tail.len() - XZ_INDEX_LIMIT);
+        }
+    }
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads go through the buffer, so that the bytes read can be kept.
+        let available = self.fill_buf()?;
+        let read = buf.len().min(available.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: Read> BufRead for Source<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self.pending.is_empty() {
+            true => self.inner.fill_buf(),
+            false => Ok(&self.pending),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.keep(amount);
+        self.position += amount as u64;
+        match self.pending.is_empty() {
+            true => self.inner.consume(amount),
+            false => drop(self.pending.drain(..amount)),
+        }
+    }
+}
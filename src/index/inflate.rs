@@ -0,0 +1,635 @@
+//! A deflate decoder that can stop between two blocks and start again from there later, as
+//! zlib's `zran.c` example does. Starting part way through a stream needs the bit the next block
+//! starts at, and the output before it that later blocks can copy from.
+
+use std::io;
+use std::io::{BufRead, Read};
+
+/// How far back deflate blocks can copy earlier output from.
+const WINDOW_SIZE: usize = 32 * 1024;
+/// Output is decoded in chunks of about this size.
+const CHUNK_SIZE: usize = 32 * 1024;
+/// Codes up to this long are decoded with a single table lookup.
+const FAST_BITS: u32 = 9;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order the lengths of the code length code are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Decodes a gzip member, or the rest of one from a block boundary inside it.
+///
+/// The input is taken several bytes at a time, so the bytes after the end of the member that were
+/// already taken are handed back by [`into_inner`](Self::into_inner).
+pub(crate) struct Inflate<R: BufRead> {
+    reader: R,
+    // Bits read from the input but not used yet, the next one lowest.
+    bits: u64,
+    bit_count: u32,
+    // The output, keeping at least the last `WINDOW_SIZE` bytes of it once they are handed out.
+    history: Vec<u8>,
+    position: usize,
+    state: State,
+    last_block: bool,
+    // The checksum and size of the output, which are checked against the trailer. `None` when
+    // decoding started part way through the member.
+    check: Option<(crc32fast::Hasher, u32)>,
+}
+
+enum State {
+    Header,
+    BlockHeader,
+    Stored(u32),
+    Codes(Box<Codes>),
+    Trailer,
+    Done,
+}
+
+struct Codes {
+    literal: Huffman,
+    distance: Huffman,
+}
+
+impl<R: BufRead> Inflate<R> {
+    /// Decodes the gzip member at the start of `reader`.
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            bits: 0,
+            bit_count: 0,
+            history: vec![],
+            position: 0,
+            state: State::Header,
+            last_block: false,
+            check: Some((crc32fast::Hasher::new(), 0)),
+        }
+    }
+
+    /// Decodes the rest of a gzip member from a block that starts at bit `bit` of the first byte
+    /// of `reader`, where `window` is the output before it. The trailer is skipped unchecked.
+    pub(crate) fn resume(reader: R, bit: u8, window: &[u8]) -> io::Result<Self> {
+        if bit >= 8 {
+            return Err(invalid("invalid deflate checkpoint"));
+        }
+        let mut inflate = Self {
+            reader,
+            bits: 0,
+            bit_count: 0,
+            history: window.to_vec(),
+            position: window.len(),
+            state: State::BlockHeader,
+            last_block: false,
+            check: None,
+        };
+        inflate.take_bits(u32::from(bit))?;
+        Ok(inflate)
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the input, and the bytes taken from it but not used, which follow the member once
+    /// it has ended.
+    pub(crate) fn into_inner(self) -> (R, Vec<u8>) {
+        let unused = self.bits.to_le_bytes()[..(self.bit_count / 8) as usize].to_vec();
+        (self.reader, unused)
+    }
+
+    /// Returns the number of bits taken from the input but not used yet, and the output before
+    /// the next block, when all the output so far has been read and the next block has not been
+    /// started. The next block starts that many bits before the end of what was taken.
+    pub(crate) fn boundary(&self) -> Option<(u32, &[u8])> {
+        let at_boundary = matches!(self.state, State::BlockHeader)
+            && self.position == self.history.len()
+            && !self.history.is_empty();
+        let window = &self.history[self.history.len().saturating_sub(WINDOW_SIZE)..];
+        at_boundary.then_some((self.bit_count, window))
+    }
+
+    /// Makes sure at least `count` bits are buffered, returning false if the input ends first.
+    /// As many whole bytes as fit are taken at once.
+    fn fill(&mut self, count: u32) -> io::Result<bool> {
+        while self.bit_count < count {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok(false);
+            }
+            let len = available.len().min(((64 - self.bit_count) / 8) as usize);
+            for &byte in &available[..len] {
+                self.bits |= u64::from(byte) << self.bit_count;
+                self.bit_count += 8;
+            }
+            self.reader.consume(len);
+        }
+        Ok(true)
+    }
+
+    fn take_bits(&mut self, count: u32) -> io::Result<u32> {
+        if !self.fill(count)? {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn take_byte(&mut self) -> io::Result<u8> {
+        Ok(self.take_bits(8)? as u8)
+    }
+
+    /// Drops the bits up to the next byte boundary.
+    fn align(&mut self) {
+        let count = self.bit_count % 8;
+        self.bits >>= count;
+        self.bit_count -= count;
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> io::Result<u16> {
+        if self.fill(FAST_BITS)? {
+            let (symbol, len) = huffman.fast[(self.bits & ((1 << FAST_BITS) - 1)) as usize];
+            if len > 0 {
+                self.bits >>= len;
+                self.bit_count -= u32::from(len);
+                return Ok(symbol);
+            }
+        }
+        // Longer codes, and codes near the end of the input, are decoded a bit at a time.
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for len in 1..16 {
+            code |= self.take_bits(1)? as usize;
+            let count = usize::from(huffman.counts[len]);
+            if code - first < count {
+                return Ok(huffman.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid deflate code"))
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        let mut header = [0; 10];
+        for byte in &mut header {
+            *byte = self.take_byte()?;
+        }
+        if header[..3] != [0x1f, 0x8b, 8] {
+            return Err(invalid("invalid gzip header"));
+        }
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let len = u16::from_le_bytes([self.take_byte()?, self.take_byte()?]);
+            for _ in 0..len {
+                self.take_byte()?;
+            }
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while self.take_byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.take_bits(16)?;
+        }
+        Ok(())
+    }
+
+    fn block_header(&mut self) -> io::Result<State> {
+        self.last_block = self.take_bits(1)? == 1;
+        match self.take_bits(2)? {
+            0 => {
+                self.align();
+                let len = self.take_bits(16)?;
+                if self.take_bits(16)? != !len & 0xffff {
+                    return Err(invalid("invalid stored block length"));
+                }
+                Ok(State::Stored(len))
+            }
+            1 => Ok(State::Codes(Box::new(fixed_codes()?))),
+            2 => Ok(State::Codes(Box::new(self.dynamic_codes()?))),
+            _ => Err(invalid("invalid deflate block type")),
+        }
+    }
+
+    fn dynamic_codes(&mut self) -> io::Result<Codes> {
+        let literals = self.take_bits(5)? as usize + 257;
+        let distances = self.take_bits(5)? as usize + 1;
+        let code_lengths = self.take_bits(4)? as usize + 4;
+        if literals > 286 || distances > 30 {
+            return Err(invalid("too many deflate codes"));
+        }
+        let mut lengths = [0; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[symbol] = self.take_bits(3)? as u8;
+        }
+        let code = Huffman::new(&lengths)?;
+        let mut lengths = vec![0; literals + distances];
+        let mut index = 0;
+        while index < lengths.len() {
+            let (len, repeat) = match self.decode(&code)? {
+                len @ 0..16 => (len as u8, 1),
+                16 if index == 0 => return Err(invalid("repeated length with no first length")),
+                16 => (lengths[index - 1], 3 + self.take_bits(2)? as usize),
+                17 => (0, 3 + self.take_bits(3)? as usize),
+                _ => (0, 11 + self.take_bits(7)? as usize),
+            };
+            let lengths = lengths
+                .get_mut(index..index + repeat)
+                .ok_or_else(|| invalid("too many deflate code lengths"))?;
+            lengths.fill(len);
+            index += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("missing end of block code"));
+        }
+        Ok(Codes {
+            literal: Huffman::new(&lengths[..literals])?,
+            distance: Huffman::new(&lengths[literals..])?,
+        })
+    }
+
+    /// Copies out of a stored block, returning how much of it is left.
+    fn stored(&mut self, mut remaining: u32) -> io::Result<u32> {
+        while remaining > 0 && self.bit_count >= 8 {
+            let byte = self.take_byte()?;
+            self.history.push(byte);
+            remaining -= 1;
+        }
+        let available = self.reader.fill_buf()?;
+        if remaining > 0 && available.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let len = available.len().min(remaining as usize).min(CHUNK_SIZE);
+        self.history.extend_from_slice(&available[..len]);
+        self.reader.consume(len);
+        Ok(remaining - len as u32)
+    }
+
+    /// Decodes a chunk of a compressed block, returning true if the block ended.
+    fn codes(&mut self, codes: &Codes) -> io::Result<bool> {
+        let end = self.history.len() + CHUNK_SIZE;
+        while self.history.len() < end {
+            let symbol = usize::from(self.decode(&codes.literal)?);
+            if symbol < 256 {
+                self.history.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(true);
+            }
+            let symbol = symbol - 257;
+            let (Some(&base), Some(&extra)) = (LENGTH_BASE.get(symbol), LENGTH_EXTRA.get(symbol))
+            else {
+                return Err(invalid("invalid deflate length code"));
+            };
+            let len = usize::from(base) + self.take_bits(u32::from(extra))? as usize;
+            let symbol = usize::from(self.decode(&codes.distance)?);
+            let (Some(&base), Some(&extra)) =
+                (DISTANCE_BASE.get(symbol), DISTANCE_EXTRA.get(symbol))
+            else {
+                return Err(invalid("invalid deflate distance code"));
+            };
+            let distance = usize::from(base) + self.take_bits(u32::from(extra))? as usize;
+            if distance > self.history.len() {
+                return Err(invalid("deflate distance too far back"));
+            }
+            let start = self.history.len() - distance;
+            if distance >= len {
+                self.history.extend_from_within(start..start + len);
+            } else {
+                for i in start..start + len {
+                    self.history.push(self.history[i]);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        self.align();
+        let mut trailer = [0; 8];
+        for byte in &mut trailer {
+            *byte = self.take_byte()?;
+        }
+        if let Some((hasher, size)) = self.check.take() {
+            let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+            let isize = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+            if hasher.finalize() != crc || size != isize {
+                return Err(invalid("corrupt gzip member"));
+            }
+        }
+        Ok(())
+    }
+
+    fn end_of_block(&self) -> State {
+        match self.last_block {
+            true => State::Trailer,
+            false => State::BlockHeader,
+        }
+    }
+
+    /// Decodes more output, stopping at the end of a block.
+    fn step(&mut self) -> io::Result<()> {
+        // Drop the output that is no longer needed, keeping the window.
+        let unused = self.history.len().saturating_sub(WINDOW_SIZE);
+        if self.position == self.history.len() && unused >= 4 * CHUNK_SIZE {
+            self.history.drain(..unused);
+            self.position = self.history.len();
+        }
+        self.state = match std::mem::replace(&mut self.state, State::Done) {
+            State::Header => {
+                self.header()?;
+                State::BlockHeader
+            }
+            State::BlockHeader => self.block_header()?,
+            State::Stored(remaining) => match self.stored(remaining)? {
+                0 => self.end_of_block(),
+                remaining => State::Stored(remaining),
+            },
+            State::Codes(codes) => match self.codes(&codes)? {
+                true => self.end_of_block(),
+                false => State::Codes(codes),
+            },
+            State::Trailer => {
+                self.trailer()?;
+                State::Done
+            }
+            State::Done => State::Done,
+        };
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Inflate<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.history.len() && !matches!(self.state, State::Done) {
+            self.step()?;
+        }
+        let available = &self.history[self.position..];
+        let read = buf.len().min(available.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        if let Some((hasher, size)) = &mut self.check {
+            hasher.update(&buf[..read]);
+            *size = size.wrapping_add(read as u32);
+        }
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// A canonical Huffman code, as deflate uses.
+struct Huffman {
+    // The number of codes of each length.
+    counts: [u16; 16],
+    // The symbols, ordered by their codes.
+    symbols: Vec<u16>,
+    // The symbol and length of the code at the start of every `FAST_BITS` bits, or a length of
+    // zero when that code is longer.
+    fast: Vec<(u16, u8)>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err(invalid("oversubscribed deflate code"));
+            }
+        }
+        let mut offsets = [0; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + usize::from(counts[len]);
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[usize::from(len)]] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        let mut fast = vec![(0, 0); 1 << FAST_BITS];
+        let mut code = 0u32;
+        let mut index = 0;
+        for len in 1..=FAST_BITS {
+            for _ in 0..counts[len as usize] {
+                // Codes are stored from their highest bit, and the input is read from the lowest.
+                let mut entry = (code.reverse_bits() >> (32 - len)) as usize;
+                while entry < fast.len() {
+                    fast[entry] = (symbols[index], len as u8);
+                    entry += 1 << len;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+        Ok(Self {
+            counts,
+            symbols,
+            fast,
+        })
+    }
+}
+
+fn fixed_codes() -> io::Result<Codes> {
+    let mut lengths = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok(Codes {
+        literal: Huffman::new(&lengths)?,
+        distance: Huffman::new(&[5; 30])?,
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_resume() {
+        // Random letters, so that the output is made of many blocks.
+        let mut state = 1u32;
+        let data: Vec<u8> = (0..400_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                b'a' + (state % 16) as u8
+            })
+            .collect();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut reader = io::Cursor::new(compressed.as_slice());
+        let mut inflate = Inflate::new(&mut reader);
+        let mut decoded = vec![];
+        let mut boundaries = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            if let Some((unread, window)) = inflate.boundary() {
+                let position = inflate.get_ref().position() * 8 - u64::from(unread);
+                boundaries.push((position, decoded.len(), window.to_vec()));
+            }
+            match inflate.read(&mut buf).unwrap() {
+                0 => break,
+                read => decoded.extend_from_slice(&buf[..read]),
+            }
+        }
+        assert_eq!(decoded, data);
+        assert_eq!(reader.position() as usize, compressed.len());
+        assert!(boundaries.len() > 1);
+
+        for (position, offset, window) in boundaries {
+            let reader = &compressed[(position / 8) as usize..];
+            let mut inflate = Inflate::resume(reader, (position % 8) as u8, &window).unwrap();
+            let mut rest = vec![];
+            inflate.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, &data[offset..]);
+            let (reader, unused) = inflate.into_inner();
+            assert!(reader.is_empty() && unused.is_empty());
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoded = vec![];
+        Inflate::new(input).read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    /// A gzip member made of `fields` of `(value, length)` bits, written from their lowest bit
+    /// as deflate stores them. Huffman codes are passed with their bits reversed.
+    fn member(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        let mut bits = 0;
+        for &(value, len) in fields {
+            for i in 0..len {
+                if bits % 8 == 0 {
+                    out.push(0);
+                }
+                *out.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (bits % 8);
+                bits += 1;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_members() {
+        let mut input = gzip(b"hello ");
+        input.extend(gzip(b"world"));
+        let mut inflate = Inflate::new(input.as_slice());
+        let mut decoded = vec![];
+        inflate.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"hello ");
+        // The next member was partly taken from the input, and is handed back.
+        let (reader, unused) = inflate.into_inner();
+        let next = [unused.as_slice(), reader].concat();
+        assert_eq!(next, gzip(b"world"));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = b"hello world, hello deflate\n".repeat(100);
+        let input = gzip(&data);
+        for len in 0..input.len() {
+            let err = decode(&input[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{len}");
+        }
+        assert_eq!(decode(&input).unwrap(), data);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let input = gzip(&b"hello world, hello deflate\n".repeat(100));
+        let trailer = input.len() - 8;
+        let mut crc = input.clone();
+        crc[trailer] ^= 1;
+        let mut size = input.clone();
+        size[trailer + 4] ^= 1;
+        let mut header = input.clone();
+        header[0] = 0;
+        let cases = [
+            (crc, "corrupt gzip member"),
+            (size, "corrupt gzip member"),
+            (header, "invalid gzip header"),
+            // The last block, of the reserved type.
+            (member(&[(1, 1), (3, 2)]), "invalid deflate block type"),
+            // A stored block whose length does not match its complement.
+            (
+                member(&[(1, 1), (0, 2), (0, 5), (5, 16), (5, 16)]),
+                "invalid stored block length",
+            ),
+            // A block of fixed codes that starts by copying from before the start.
+            (
+                member(&[(1, 1), (1, 2), (0b1000000, 7), (0, 5)]),
+                "deflate distance too far back",
+            ),
+            // Dynamic codes with more distance codes than there are.
+            (
+                member(&[(1, 1), (2, 2), (0, 5), (31, 5), (0, 4)]),
+                "too many deflate codes",
+            ),
+        ];
+        for (input, message) in cases {
+            let err = decode(&input).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{message}");
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_garbage() {
+        // Decoding a damaged member fails or gives the wrong output, but does not panic.
+        let data = b"hello world, hello deflate\n".repeat(100);
+        let input = gzip(&data);
+        for position in 10..input.len() {
+            for mask in [0x01, 0x10, 0xff] {
+                let mut damaged = input.clone();
+                damaged[position] ^= mask;
+                let _ = decode(&damaged);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Reading the index at the end of an xz stream, and decoding a stream from one of its blocks.
+//!
+//! Every block of an xz stream can be decoded on its own, but the decoders only read whole
+//! streams. To start at a block, the blocks from there on are given a new stream header, and an
+//! index and footer that list only them.
+
+use std::io;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+const HEADER_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+const FOOTER_MAGIC: &[u8] = b"YZ";
+/// The size of the stream header, and of the stream footer.
+pub(crate) const HEADER_SIZE: u64 = 12;
+
+/// A block of an xz stream, as listed by its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Record {
+    /// The size of the block without the padding after it.
+    pub(crate) unpadded: u64,
+    pub(crate) uncompressed: u64,
+}
+
+impl Record {
+    /// The size of the block, including the padding after it.
+    pub(crate) fn size(&self) -> u64 {
+        self.unpadded.next_multiple_of(4)
+    }
+}
+
+/// Reads the index of the stream whose last bytes are `tail`, returning the blocks it lists and
+/// the size of the index. Returns `None` if `tail` does not hold the whole index and footer.
+pub(crate) fn read_index(tail: &[u8]) -> Option<(Vec<Record>, u64)> {
+    let footer = tail.get(tail.len().checked_sub(HEADER_SIZE as usize)?..)?;
+    let (index_size, _) = read_footer(footer).ok()?;
+    let start = tail
+        .len()
+        .checked_sub((HEADER_SIZE + index_size) as usize)?;
+    let mut index = &tail[start..tail.len() - HEADER_SIZE as usize];
+    let (records, _) = read_records(&mut index).ok()?;
+    Some((records, index_size))
+}
+
+/// Decodes the stream that the index at `index_offset` belongs to, starting at the block
+/// numbered `block` at `block_offset`, followed by the rest of the input after that stream.
+pub(crate) fn resume<R: Read + Seek>(
+    mut reader: R,
+    block_offset: u64,
+    index_offset: u64,
+    block: u64,
+) -> io::Result<impl Read> {
+    let blocks_size = index_offset
+        .checked_sub(block_offset)
+        .ok_or_else(|| invalid("xz block is after its index"))?;
+    reader.seek(SeekFrom::Start(index_offset))?;
+    let mut buffered = BufReader::new(&mut reader);
+    let (records, index_size) = read_records(&mut buffered)?;
+    let mut footer = [0; HEADER_SIZE as usize];
+    buffered.read_exact(&mut footer)?;
+    let (size, flags) = read_footer(&footer)?;
+    if size != index_size {
+        return Err(invalid("xz index size does not match its footer"));
+    }
+    let records = records
+        .get(block as usize..)
+        .ok_or_else(|| invalid("xz index has fewer blocks than expected"))?;
+
+    let mut header = HEADER_MAGIC.to_vec();
+    header.extend_from_slice(&flags);
+    header.extend_from_slice(&crc32fast::hash(&flags).to_le_bytes());
+    let mut index = vec![0];
+    write_varint(&mut index, records.len() as u64);
+    for record in records {
+        write_varint(&mut index, record.unpadded);
+        write_varint(&mut index, record.uncompressed);
+    }
+    index.resize(index.len().next_multiple_of(4), 0);
+    index.extend_from_slice(&crc32fast::hash(&index).to_le_bytes());
+    let mut footer = ((index.len() / 4 - 1) as u32).to_le_bytes().to_vec();
+    footer.extend_from_slice(&flags);
+    index.extend_from_slice(&crc32fast::hash(&footer).to_le_bytes());
+    index.extend_from_slice(&footer);
+    index.extend_from_slice(FOOTER_MAGIC);
+
+    reader.seek(SeekFrom::Start(block_offset))?;
+    Ok(Resumed {
+        header: Cursor::new(header),
+        blocks: reader.take(blocks_size),
+        index: Cursor::new(index),
+        skip: index_size + HEADER_SIZE,
+    })
+}
+
+/// A stream made of the blocks of another, followed by the input after that stream.
+struct Resumed<R> {
+    header: Cursor<Vec<u8>>,
+    blocks: io::Take<R>,
+    index: Cursor<Vec<u8>>,
+    // The size of the original index and footer, which are skipped.
+    skip: u64,
+}
+
+impl<R: Read> Read for Resumed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for part in [
+            &mut self.header as &mut dyn Read,
+            &mut self.blocks,
+            &mut self.index,
+        ] {
+            let read = part.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+        }
+        let reader = self.blocks.get_mut();
+        if self.skip > 0 {
+            let skipped = io::copy(&mut reader.take(self.skip), &mut io::sink())?;
+            if skipped != self.skip {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.skip = 0;
+        }
+        reader.read(buf)
+    }
+}
+
+/// Reads a stream footer, returning the size of the index before it and the stream flags.
+fn read_footer(footer: &[u8]) -> io::Result<(u64, [u8; 2])> {
+    if footer.len() != HEADER_SIZE as usize || &footer[10..] != FOOTER_MAGIC {
+        return Err(invalid("invalid xz stream footer"));
+    }
+    if crc32fast::hash(&footer[4..10]).to_le_bytes() != footer[..4] {
+        return Err(invalid("corrupt xz stream footer"));
+    }
+    let backward_size = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+    Ok(((u64::from(backward_size) + 1) * 4, [footer[8], footer[9]]))
+}
+
+/// Reads an index, from its indicator byte to its CRC32, returning the blocks it lists and its
+/// size.
+fn read_records(reader: &mut impl Read) -> io::Result<(Vec<Record>, u64)> {
+    let mut index = Checked {
+        reader,
+        hasher: crc32fast::Hasher::new(),
+        count: 0,
+    };
+    if read_byte(&mut index)? != 0 {
+        return Err(invalid("invalid xz index"));
+    }
+    let count = read_varint(&mut index)?;
+    let mut records = vec![];
+    for _ in 0..count {
+        records.push(Record {
+            unpadded: read_varint(&mut index)?,
+            uncompressed: read_varint(&mut index)?,
+        });
+    }
+    while !index.count.is_multiple_of(4) {
+        if read_byte(&mut index)? != 0 {
+            return Err(invalid("invalid xz index padding"));
+        }
+    }
+    let crc = index.hasher.clone().finalize();
+    let mut expected = [0; 4];
+    index.reader.read_exact(&mut expected)?;
+    if crc.to_le_bytes() != expected {
+        return Err(invalid("corrupt xz index"));
+    }
+    Ok((records, index.count + 4))
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_varint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0;
+    for i in 0..9 {
+        let byte = read_byte(reader)?;
+        value |= u64::from(byte & 0x7f) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("invalid xz index"))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Counts and checksums the bytes read through it.
+struct Checked<'a, R> {
+    reader: &'a mut R,
+    hasher: crc32fast::Hasher,
+    count: u64,
+}
+
+impl<R: Read> Read for Checked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.count += read as u64;
+        Ok(read)
+    }
+}
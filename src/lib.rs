@@ -1,16 +1,75 @@
 #![doc = include_str!("../README.md")]
+#![forbid(unsafe_code)]
 
+use crate::checksum::ChecksumReader;
+use crate::error::ContextReader;
+#[cfg(feature = "hash")]
+use crate::hash::HashingReader;
+use crate::peekable::{Buffer, Buffered};
+use crate::visitor::Walk;
 use std::io;
-use std::io::Read;
+use std::io::{BufRead, Read, Seek};
 use std::path::{Path, PathBuf};
 
+mod checksum;
 mod container;
+mod detect;
+mod error;
+mod filter;
+#[cfg(feature = "hash")]
+mod hash;
+#[cfg(feature = "index")]
+mod index;
+#[cfg(feature = "oci")]
+mod oci;
+mod options;
+mod parallel;
 mod peekable;
+mod registry;
+mod spool;
 mod stream;
+mod visitor;
 
-pub use crate::container::{ArchiveKind, Container, ContainerKind, Items};
+pub use crate::checksum::{Checksum, Verification};
+#[cfg(feature = "zip")]
+pub use crate::container::ZipArchiveContainer;
+pub use crate::container::{
+    ArchiveKind, Container, ContainerKind, CustomCodec, CustomContainer, CustomKind, DynContainer,
+    DynItems, Items, SeekableContainerKind,
+};
+pub use crate::detect::{DetectedFormat, DetectedLayer, detect, detect_layers};
+pub use crate::error::{Error, ErrorCategory};
+#[cfg(feature = "hash")]
+pub use crate::hash::{Digest, FileHashes, HashAlgorithm};
+#[cfg(feature = "index")]
+pub use crate::index::{Checkpoint, Index, IndexEntry, open_entry};
+#[cfg(feature = "oci")]
+pub use crate::oci::{OciView, read_oci_image};
+pub use crate::options::ReadOptions;
+pub use crate::parallel::ParallelOptions;
+pub use crate::registry::{CodecFormat, ContainerFormat, Format, FormatRegistry};
+pub use crate::spool::{Spool, SpoolOptions};
+#[cfg(feature = "gzip")]
+pub use crate::stream::BgzfDecoder;
 pub use crate::stream::CompressionKind;
+#[cfg(feature = "gzip")]
+pub use crate::stream::GzipDecoder;
+#[cfg(feature = "pbzx")]
+pub use crate::stream::PbzxDecoder;
 pub use crate::stream::StreamKind;
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+pub use crate::stream::ThreadedXzDecoder;
+#[cfg(feature = "xz")]
+pub use crate::stream::XzDecoder;
+#[cfg(feature = "zstd")]
+pub use crate::stream::ZstdDecoder;
+pub use crate::visitor::{ContainerStats, ReadSummary, Visitor};
+
+#[cfg(all(
+    any(feature = "gzip", feature = "zstd", feature = "xz", feature = "xar"),
+    not(any(feature = "c-backend", feature = "pure-rust"))
+))]
+compile_error!("either the `c-backend` or the `pure-rust` feature must be enabled");
 
 #[derive(Debug, strum::EnumIs)]
 pub enum FileKind {
@@ -26,6 +85,13 @@ pub enum SizeHint {
     Exact(u64),
     /// Only the compressed size is known (after transparent decompression).
     CompressedSize(u64),
+    /// The uncompressed size recorded by a compression format, such as the content size in a
+    /// zstd frame header. It may be wrong for concatenated or corrupt streams, so it should only
+    /// be used for things like preallocating buffers.
+    Estimated(u64),
+    /// A lower bound on the uncompressed size, from the size in a gzip trailer. The trailer only
+    /// records the size of the last member, modulo 4 GiB, so the data may be larger.
+    AtLeast(u64),
     /// No size information is available.
     #[default]
     Unknown,
@@ -48,10 +114,29 @@ impl SizeHint {
         }
     }
 
-    /// Returns any known size (exact or compressed).
+    /// Returns the estimated size if that's what is known.
+    pub fn estimated(&self) -> Option<u64> {
+        match self {
+            SizeHint::Estimated(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the lower bound on the size if that's what is known.
+    pub fn at_least(&self) -> Option<u64> {
+        match self {
+            SizeHint::AtLeast(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns any known size (exact, compressed, estimated or a lower bound).
     pub fn any_known(&self) -> Option<u64> {
         match self {
-            SizeHint::Exact(n) | SizeHint::CompressedSize(n) => Some(*n),
+            SizeHint::Exact(n)
+            | SizeHint::CompressedSize(n)
+            | SizeHint::Estimated(n)
+            | SizeHint::AtLeast(n) => Some(*n),
             SizeHint::Unknown => None,
         }
     }
@@ -65,6 +150,65 @@ impl SizeHint {
     pub fn is_unknown(&self) -> bool {
         matches!(self, SizeHint::Unknown)
     }
+
+    /// The size hint of the data inside a compressed stream with this hint, when the stream
+    /// does not record the size itself.
+    fn decompressed(self) -> SizeHint {
+        match self {
+            SizeHint::Exact(n) => SizeHint::CompressedSize(n),
+            // An estimate of the compressed size says nothing about the data inside it.
+            SizeHint::Estimated(_) | SizeHint::AtLeast(_) => SizeHint::Unknown,
+            other => other,
+        }
+    }
+}
+
+/// Format specific metadata attached to an entry, such as the record headers of a WARC archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata(Vec<(String, String)>);
+
+impl Metadata {
+    /// Returns the first value for `key`. Keys are compared case-insensitively.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all key/value pairs, in the order they were read.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns true if there is no metadata.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<(String, String)>> for Metadata {
+    fn from(value: Vec<(String, String)>) -> Self {
+        Self(value)
+    }
+}
+
+/// The type of a file, detected from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentType {
+    /// The MIME type, such as `image/png`.
+    pub mime_type: &'static str,
+    /// The usual file extension for the type, without a leading dot, such as `png`.
+    pub extension: &'static str,
+}
+
+impl From<infer::Type> for ContentType {
+    fn from(value: infer::Type) -> Self {
+        Self {
+            mime_type: value.mime_type(),
+            extension: value.extension(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -73,72 +217,290 @@ pub struct FileItem<T: Read> {
     pub reader: T,
     pub kind: FileKind,
     pub size_hint: SizeHint,
+    pub metadata: Metadata,
+    /// The detected type of a file that is not an archive or compressed. Only set when enabled with
+    /// [`ReadOptions::content_types`].
+    pub content_type: Option<ContentType>,
+    /// The checksum of the entry's data stored by the archive holding it: the CRC-32 of a zip
+    /// entry or the sum of a `070702` cpio entry. Not set for entries of other archives, or for
+    /// files that were decompressed, as it covers the compressed data.
+    pub checksum: Option<Checksum>,
+    /// Where the entry is stored in the archive holding it, for tar and zip archives. For files
+    /// that were decompressed, this is where the compressed data is stored.
+    pub offsets: Option<EntryOffsets>,
 }
 
-pub fn recursive_read<F>(path: &Path, mut reader: impl Read, callback: &mut F) -> io::Result<()>
-where
-    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
-{
-    read_recursive_inner(
-        path,
-        FileKind::File,
-        SizeHint::Unknown,
-        &mut reader as &mut dyn Read,
-        callback,
-    )?;
-    Ok(())
+/// The byte offsets of an entry within the decompressed data of the archive holding it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryOffsets {
+    /// The offset of the entry's header, such as a tar header or a zip local file header.
+    pub header: u64,
+    /// The offset of the entry's data, which is compressed for zip entries that use compression.
+    pub data: u64,
 }
 
-fn handle_container<F>(path: &Path, mut archive: impl Container, callback: &mut F) -> io::Result<()>
+pub fn recursive_read<F>(path: &Path, reader: impl Read, callback: &mut F) -> Result<(), Error>
 where
     F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
 {
-    let mut items = archive.items()?;
-    while let Some(x) = items.next_item() {
-        let mut x = x?;
-        let reader = &mut x.reader as &mut dyn Read;
-        read_recursive_inner(
-            path.join(x.path).as_path(),
-            x.kind,
-            x.size_hint,
-            reader,
-            callback,
-        )?;
+    ReadOptions::default().recursive_read(path, reader, callback)
+}
+
+/// Reads the file at `path` inside `reader`, as with [`ReadOptions::open_path`].
+pub fn open_path(reader: impl Read, path: impl AsRef<Path>) -> Result<Spool, Error> {
+    ReadOptions::default().open_path(reader, path)
+}
+
+fn handle_container<V: Visitor>(
+    path: &Path,
+    mut archive: impl Container,
+    walk: &mut Walk<V>,
+) -> io::Result<()> {
+    let mut items = archive.items().map_err(|e| walk.context(e, path))?;
+    walk.enter_container(path)?;
+    // The entries are read one at a time, so they share one buffer.
+    let mut buffer = Buffer::default();
+    // The entries after a broken header usually cannot be found, so the rest of the archive is
+    // skipped.
+    let mut broken = false;
+    while !broken || items.continues_after_error() {
+        let Some(x) = items.next_item() else {
+            break;
+        };
+        let mut x = match x {
+            Ok(x) => x,
+            Err(e) => {
+                walk.report(e, path)?;
+                broken = true;
+                continue;
+            }
+        };
+        walk.count_entry();
+        #[cfg(feature = "index")]
+        walk.index_entry(&x);
+        let path = path.join(x.path);
+        if !walk.wants(&path) {
+            continue;
+        }
+        let is_directory = x.kind.is_directory();
+        let mut reader = ChecksumReader::new(&mut x.reader, x.checksum);
+        walk.entry_checksum = x.checksum;
+        walk.entry_offsets = x.offsets;
+        let result = match walk.dispatcher(x.size_hint) {
+            Some(dispatcher) => {
+                dispatcher.dispatch(&path, x.kind, x.size_hint, x.metadata, &mut reader, walk)
+            }
+            None => read_recursive_inner(
+                &path,
+                x.kind,
+                x.size_hint,
+                x.metadata,
+                &mut Buffered::new(&mut reader, &mut buffer),
+                walk,
+            ),
+        }
+        .and_then(|()| walk.verify(&path, reader));
+        if let Err(e) = result {
+            walk.report(e, &path)?;
+        }
+        // When looking for a single file, the entry holding it has now been read.
+        if walk.target.is_some() && !is_directory {
+            break;
+        }
     }
-    Ok(())
+    walk.leave_container(path)
 }
 
-fn read_recursive_inner<F>(
+pub(crate) fn read_recursive_inner<V: Visitor>(
     path: &Path,
     kind: FileKind,
     size_hint: SizeHint,
-    reader: &mut dyn Read,
-    callback: &mut F,
+    metadata: Metadata,
+    reader: &mut dyn BufRead,
+    walk: &mut Walk<V>,
+) -> io::Result<()> {
+    let container =
+        ContainerKind::from_reader_with(reader, walk.options).map_err(|e| walk.context(e, path))?;
+    read_container(
+        path,
+        kind,
+        size_hint,
+        metadata,
+        SizeHint::Unknown,
+        container,
+        walk,
+    )
+}
+
+fn read_container<T, V>(
+    path: &Path,
+    kind: FileKind,
+    size_hint: SizeHint,
+    metadata: Metadata,
+    decoded_size: SizeHint,
+    container: ContainerKind<T>,
+    walk: &mut Walk<V>,
 ) -> io::Result<()>
 where
-    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    T: BufRead,
+    V: Visitor,
 {
-    let container = ContainerKind::from_reader(reader)?;
-    match container {
-        ContainerKind::Stream(StreamKind::Raw(mut r)) => callback(FileItem {
-            path: path.to_path_buf(),
-            reader: &mut r as &mut dyn Read,
-            kind,
-            size_hint,
-        }),
+    let format = container.format_name();
+    walk.in_layer(format, path, |walk| match container {
+        ContainerKind::Stream(StreamKind::Raw(mut r)) => {
+            if !walk.options.filter.yields(path) {
+                return Ok(());
+            }
+            let content_type = if walk.options.content_types {
+                infer::get(r.peek_buf()?).map(ContentType::from)
+            } else {
+                None
+            };
+            let checksum = walk.entry_checksum.take();
+            let offsets = walk.entry_offsets.take();
+            #[cfg(feature = "hash")]
+            let mut r = HashingReader::new(&mut r, &walk.options.hashes);
+            let mut reader = ContextReader::new(&mut r, path, &walk.layers);
+            let result = walk
+                .visitor
+                .visit_file(FileItem {
+                    path: path.to_path_buf(),
+                    reader: &mut reader as &mut dyn Read,
+                    kind,
+                    size_hint,
+                    metadata,
+                    content_type,
+                    checksum,
+                    offsets,
+                })
+                .map_err(|e| error::from_callback(e, path, &walk.layers));
+            let read = reader.read;
+            walk.count_file(read);
+            result?;
+            #[cfg(feature = "hash")]
+            walk.hash(path, r)?;
+            Ok(())
+        }
         ContainerKind::Stream(StreamKind::Compressed(mut c)) => {
-            // When decompressing, convert Exact to CompressedSize
-            let new_hint = match size_hint {
-                SizeHint::Exact(n) => SizeHint::CompressedSize(n),
-                other => other,
+            // When decompressing, convert Exact to CompressedSize, unless the size of the
+            // decompressed data is known.
+            let new_hint = match (decoded_size, c.size_hint()) {
+                (SizeHint::Unknown, SizeHint::Unknown) => size_hint.decompressed(),
+                (SizeHint::Unknown, hint) | (hint, _) => hint,
             };
-            read_recursive_inner(path, kind, new_hint, &mut c as &mut dyn Read, callback)
+            walk.entry_checksum = None;
+            let mut buffer = Buffer::default();
+            read_recursive_inner(
+                path,
+                kind,
+                new_hint,
+                metadata,
+                &mut Buffered::new(&mut c, &mut buffer),
+                walk,
+            )
+        }
+        #[cfg(feature = "tar")]
+        ContainerKind::Archive(ArchiveKind::Tar(r)) => handle_container(path, r, walk),
+        #[cfg(feature = "zip")]
+        ContainerKind::Archive(ArchiveKind::Zip(r)) => match &walk.options.spool {
+            Some(spool) => {
+                let spooled = Spool::new(r.into_inner(), spool)?;
+                read_seekable(path, kind, size_hint, metadata, spooled, walk)
+            }
+            None => handle_container(path, r, walk),
+        },
+        #[cfg(feature = "xar")]
+        ContainerKind::Archive(ArchiveKind::Xar(r)) => handle_container(path, r, walk),
+        #[cfg(feature = "cpio")]
+        ContainerKind::Archive(ArchiveKind::Cpio(r)) => handle_container(path, r, walk),
+        #[cfg(feature = "cfb")]
+        ContainerKind::Archive(ArchiveKind::Cfb(r)) => {
+            let r = match &walk.options.spool {
+                Some(spool) => r.with_spool(spool.clone()),
+                None => r,
+            };
+            handle_container(path, r, walk)
+        }
+        #[cfg(feature = "email")]
+        ContainerKind::Archive(ArchiveKind::Email(r)) => {
+            let r = match walk.options.spool.as_ref().and_then(|s| s.max_size) {
+                Some(max) => r.with_max_size(max),
+                None => r,
+            };
+            handle_container(path, r, walk)
+        }
+        #[cfg(feature = "warc")]
+        ContainerKind::Archive(ArchiveKind::Warc(r)) => handle_container(path, r, walk),
+        ContainerKind::Custom(CustomKind::Codec(mut c)) => {
+            let new_hint = size_hint.decompressed();
+            walk.entry_checksum = None;
+            let mut buffer = Buffer::default();
+            read_recursive_inner(
+                path,
+                kind,
+                new_hint,
+                metadata,
+                &mut Buffered::new(c.decoder()?, &mut buffer),
+                walk,
+            )
+        }
+        ContainerKind::Custom(CustomKind::Container(mut c)) => {
+            handle_container(path, c.open()?, walk)
+        }
+    })
+}
+
+pub(crate) fn read_seekable<V: Visitor>(
+    path: &Path,
+    kind: FileKind,
+    size_hint: SizeHint,
+    metadata: Metadata,
+    mut reader: impl BufRead + Seek,
+    walk: &mut Walk<V>,
+) -> io::Result<()> {
+    // Compressed inputs that can be seeked may record their uncompressed size at the end.
+    let decoded_size = stream::trailer_size(&mut reader, &walk.options.formats)
+        .map_err(|e| walk.context(e, path))?;
+    let container = SeekableContainerKind::from_reader_with(reader, walk.options)
+        .map_err(|e| walk.context(e, path))?;
+    match container {
+        #[cfg(feature = "zip")]
+        SeekableContainerKind::Zip(r) => {
+            walk.in_layer(Some(Format::Zip.to_string()), path, |walk| {
+                handle_container(path, r, walk)
+            })
+        }
+        // A zip without a central directory, such as a truncated one, is read from its local
+        // headers instead.
+        #[cfg(feature = "zip")]
+        SeekableContainerKind::Other(ContainerKind::Archive(ArchiveKind::Zip(r))) => {
+            walk.in_layer(Some(Format::Zip.to_string()), path, |walk| {
+                handle_container(path, r, walk)
+            })
+        }
+        SeekableContainerKind::Other(c) => {
+            read_container(path, kind, size_hint, metadata, decoded_size, c, walk)
         }
-        ContainerKind::Archive(ArchiveKind::Tar(r)) => handle_container(path, r, callback),
-        ContainerKind::Archive(ArchiveKind::Zip(r)) => handle_container(path, r, callback),
     }
 }
 
+/// Like [`recursive_read`], but for seekable inputs such as a [`File`](std::fs::File).
+///
+/// A zip archive at the top level is read through its central directory, which gives exact
+/// sizes for every entry, skips entries that were deleted or replaced, and allows data such
+/// as a self-extracting executable to come before the archive. Nested entries are read the
+/// same way as with [`recursive_read`].
+pub fn recursive_read_seekable<F>(
+    path: &Path,
+    reader: impl Read + Seek,
+    callback: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    ReadOptions::default().recursive_read_seekable(path, reader, callback)
+}
+
 /// Unwraps compression layers and iterates archive entries without recursion.
 ///
 /// This function decompresses outer layers (gzip, zstd, bzip2, xz) to reach the archive,
@@ -147,20 +509,30 @@ where
 /// their raw bytes.
 ///
 /// Returns an error if the input is not an archive after decompression.
-pub fn iterate_archive<R, F>(mut reader: R, mut callback: F) -> io::Result<()>
+pub fn iterate_archive<R, F>(reader: R, callback: F) -> Result<(), Error>
 where
     R: Read,
     F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
 {
-    iterate_archive_inner(&mut reader as &mut dyn Read, &mut callback)
+    ReadOptions::default().iterate_archive(reader, callback)
+}
+
+pub(crate) fn iterate_archive_inner<V: Visitor>(
+    reader: &mut dyn BufRead,
+    walk: &mut Walk<V>,
+) -> io::Result<()> {
+    let container = ContainerKind::from_reader_with(reader, walk.options)
+        .map_err(|e| walk.context(e, Path::new("")))?;
+    iterate_container(container, walk)
 }
 
-fn iterate_archive_inner<F>(reader: &mut dyn Read, callback: &mut F) -> io::Result<()>
+fn iterate_container<T, V>(container: ContainerKind<T>, walk: &mut Walk<V>) -> io::Result<()>
 where
-    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    T: BufRead,
+    V: Visitor,
 {
-    let container = ContainerKind::from_reader(reader)?;
-    match container {
+    let format = container.format_name();
+    walk.in_layer(format, Path::new(""), |walk| match container {
         // Raw data after decompression - not an archive
         ContainerKind::Stream(StreamKind::Raw(_)) => Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -168,27 +540,121 @@ where
         )),
         // Compressed - recurse through compression layer
         ContainerKind::Stream(StreamKind::Compressed(mut c)) => {
-            iterate_archive_inner(&mut c as &mut dyn Read, callback)
+            let mut buffer = Buffer::default();
+            iterate_archive_inner(&mut Buffered::new(&mut c, &mut buffer), walk)
         }
         // Archive found - iterate entries without recursively decompressing
-        ContainerKind::Archive(ArchiveKind::Tar(mut r)) => iterate_entries(&mut r, callback),
-        ContainerKind::Archive(ArchiveKind::Zip(mut r)) => iterate_entries(&mut r, callback),
+        #[cfg(feature = "tar")]
+        ContainerKind::Archive(ArchiveKind::Tar(mut r)) => iterate_entries(&mut r, walk),
+        #[cfg(feature = "zip")]
+        ContainerKind::Archive(ArchiveKind::Zip(mut r)) => match &walk.options.spool {
+            Some(spool) => {
+                let spooled = Spool::new(r.into_inner(), spool)?;
+                iterate_seekable(spooled, walk)
+            }
+            None => iterate_entries(&mut r, walk),
+        },
+        #[cfg(feature = "xar")]
+        ContainerKind::Archive(ArchiveKind::Xar(mut r)) => iterate_entries(&mut r, walk),
+        #[cfg(feature = "cpio")]
+        ContainerKind::Archive(ArchiveKind::Cpio(mut r)) => iterate_entries(&mut r, walk),
+        #[cfg(feature = "cfb")]
+        ContainerKind::Archive(ArchiveKind::Cfb(r)) => {
+            let mut r = match &walk.options.spool {
+                Some(spool) => r.with_spool(spool.clone()),
+                None => r,
+            };
+            iterate_entries(&mut r, walk)
+        }
+        #[cfg(feature = "email")]
+        ContainerKind::Archive(ArchiveKind::Email(r)) => {
+            let mut r = match walk.options.spool.as_ref().and_then(|s| s.max_size) {
+                Some(max) => r.with_max_size(max),
+                None => r,
+            };
+            iterate_entries(&mut r, walk)
+        }
+        #[cfg(feature = "warc")]
+        ContainerKind::Archive(ArchiveKind::Warc(mut r)) => iterate_entries(&mut r, walk),
+        ContainerKind::Custom(CustomKind::Codec(mut c)) => {
+            let mut buffer = Buffer::default();
+            iterate_archive_inner(&mut Buffered::new(c.decoder()?, &mut buffer), walk)
+        }
+        ContainerKind::Custom(CustomKind::Container(mut c)) => {
+            iterate_entries(&mut c.open()?, walk)
+        }
+    })
+}
+
+pub(crate) fn iterate_seekable<V: Visitor>(
+    reader: impl BufRead + Seek,
+    walk: &mut Walk<V>,
+) -> io::Result<()> {
+    let container = SeekableContainerKind::from_reader_with(reader, walk.options)
+        .map_err(|e| walk.context(e, Path::new("")))?;
+    match container {
+        #[cfg(feature = "zip")]
+        SeekableContainerKind::Zip(mut r) => {
+            walk.in_layer(Some(Format::Zip.to_string()), Path::new(""), |walk| {
+                iterate_entries(&mut r, walk)
+            })
+        }
+        #[cfg(feature = "zip")]
+        SeekableContainerKind::Other(ContainerKind::Archive(ArchiveKind::Zip(mut r))) => walk
+            .in_layer(Some(Format::Zip.to_string()), Path::new(""), |walk| {
+                iterate_entries(&mut r, walk)
+            }),
+        SeekableContainerKind::Other(c) => iterate_container(c, walk),
     }
 }
 
-fn iterate_entries<F>(archive: &mut impl Container, callback: &mut F) -> io::Result<()>
+/// Like [`iterate_archive`], but for seekable inputs. Zip archives are read through their
+/// central directory, as with [`recursive_read_seekable`].
+pub fn iterate_archive_seekable<R, F>(reader: R, callback: F) -> Result<(), Error>
 where
+    R: Read + Seek,
     F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
 {
-    let mut items = archive.items()?;
-    while let Some(item) = items.next_item() {
-        let mut item = item?;
-        callback(FileItem {
-            path: item.path,
-            reader: &mut item.reader as &mut dyn Read,
-            kind: item.kind,
-            size_hint: item.size_hint,
-        })?;
+    ReadOptions::default().iterate_archive_seekable(reader, callback)
+}
+
+fn iterate_entries<V: Visitor>(archive: &mut impl Container, walk: &mut Walk<V>) -> io::Result<()> {
+    let mut items = archive
+        .items()
+        .map_err(|e| walk.context(e, Path::new("")))?;
+    let mut broken = false;
+    while !broken || items.continues_after_error() {
+        let Some(item) = items.next_item() else {
+            break;
+        };
+        let mut item = match item {
+            Ok(item) => item,
+            Err(e) => {
+                walk.report(e, Path::new(""))?;
+                broken = true;
+                continue;
+            }
+        };
+        let path = item.path;
+        let mut checked = ChecksumReader::new(&mut item.reader, item.checksum);
+        let mut reader = ContextReader::new(&mut checked, &path, &walk.layers);
+        let result = walk
+            .visitor
+            .visit_file(FileItem {
+                path: path.clone(),
+                reader: &mut reader as &mut dyn Read,
+                kind: item.kind,
+                size_hint: item.size_hint,
+                metadata: item.metadata,
+                content_type: item.content_type,
+                checksum: item.checksum,
+                offsets: item.offsets,
+            })
+            .map_err(|e| error::from_callback(e, &path, &walk.layers))
+            .and_then(|()| walk.verify(&path, checked));
+        if let Err(e) = result {
+            walk.report(e, &path)?;
+        }
     }
     Ok(())
 }
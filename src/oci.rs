@@ -0,0 +1,338 @@
+//! Reading container images produced by `docker save` or stored as an OCI image layout.
+
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::peekable::{Buffer, Buffered};
+use crate::visitor::Walk;
+use crate::{
+    FileItem, ReadOptions, Spool, SpoolOptions, iterate_archive_inner, read_recursive_inner,
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use tracing::trace;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// The most blob data that is kept in memory. Blobs past it are spooled to temporary files.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// How the layers of an image are presented by [`read_oci_image`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OciView {
+    /// The filesystem that results from applying every layer in order, including whiteouts.
+    #[default]
+    Merged,
+    /// Each layer's entries under `<image>/<layer digest>/`, exactly as stored.
+    PerLayer,
+}
+
+struct Image {
+    name: String,
+    layers: Vec<String>,
+}
+
+/// Recursively reads a container image archive, yielding files by their path within the image.
+///
+/// The input is a (possibly compressed) tar produced by `docker save`, or an OCI image layout
+/// such as the output of `skopeo copy oci-archive:...`. Each image is yielded under a directory
+/// named after its first tag, or its manifest digest if it has no tags. With [`OciView::Merged`]
+/// only the files that are visible in the final image are yielded.
+///
+/// The blobs of the image are buffered, as the manifest that names the layers may come after
+/// them. Up to 64 MiB is kept in memory, and larger images are spooled to temporary files. Each
+/// layer is then read once.
+///
+/// This uses the default options. See [`ReadOptions::read_oci_image`] to set them.
+pub fn read_oci_image<F>(
+    path: &Path,
+    reader: impl Read,
+    view: OciView,
+    callback: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    ReadOptions::default().read_oci_image(path, reader, view, callback)
+}
+
+pub(crate) fn read_image<F>(
+    options: &ReadOptions,
+    path: &Path,
+    reader: impl Read,
+    view: OciView,
+    callback: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    let mut blobs: HashMap<PathBuf, Spool> = HashMap::new();
+    let mut in_memory = 0;
+    let archive_options = archive_options(options);
+    let mut collect = |item: FileItem<&mut dyn Read>| {
+        if item.kind.is_file() {
+            let spool_options = SpoolOptions::new().memory_limit(MEMORY_LIMIT - in_memory);
+            let spool = Spool::new(item.reader, &spool_options)?;
+            if spool.is_in_memory() {
+                in_memory += spool.len() as usize;
+            }
+            blobs.insert(normalize(&item.path), spool);
+        }
+        Ok(())
+    };
+    let mut buffer = Buffer::default();
+    iterate_archive_inner(
+        &mut Buffered::new(reader, &mut buffer),
+        &mut Walk::new(&archive_options, &mut collect),
+    )?;
+
+    for image in images(&mut blobs)? {
+        trace!(
+            image = image.name,
+            layers = image.layers.len(),
+            "reading image"
+        );
+        if let Some(missing) = image
+            .layers
+            .iter()
+            .find(|l| !blobs.contains_key(&normalize(Path::new(l))))
+        {
+            return Err(invalid_image(format!("missing layer {missing}")));
+        }
+        let root = path.join(&image.name);
+        match view {
+            OciView::Merged => read_merged(options, &root, &image.layers, &mut blobs, callback)?,
+            OciView::PerLayer => {
+                for name in &image.layers {
+                    let layer_root = root.join(layer_name(name));
+                    read_layer(options, blob(&mut blobs, name)?, |entry_path, item| {
+                        read_entry(options, &layer_root.join(entry_path), item, callback)
+                    })?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The options for reading the image archive and its layers. Their entries are not yielded
+/// themselves, so path filters and hashes only apply to the files read from the layers.
+fn archive_options(options: &ReadOptions) -> ReadOptions {
+    let mut options = options.clone();
+    options.filter = Filter::default();
+    #[cfg(feature = "hash")]
+    options.hashes.clear();
+    options
+}
+
+fn invalid_image(msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid container image: {msg}"),
+    )
+}
+
+/// Returns the blob at `path`, positioned at its start. Blobs can be shared between images.
+fn blob<'a>(blobs: &'a mut HashMap<PathBuf, Spool>, path: &str) -> io::Result<&'a mut Spool> {
+    let blob = blobs
+        .get_mut(&normalize(Path::new(path)))
+        .ok_or_else(|| invalid_image(format!("missing {path}")))?;
+    blob.seek(SeekFrom::Start(0))?;
+    Ok(blob)
+}
+
+fn parse_json(blobs: &mut HashMap<PathBuf, Spool>, path: &Path) -> io::Result<Value> {
+    let blob = blob(blobs, &path.to_string_lossy())?;
+    serde_json::from_reader(blob).map_err(invalid_image)
+}
+
+/// Lists the images in the archive, with their layers in the order they are applied.
+fn images(blobs: &mut HashMap<PathBuf, Spool>) -> io::Result<Vec<Image>> {
+    if blobs.contains_key(Path::new("manifest.json")) {
+        // `docker save` format, which newer Docker versions write alongside an OCI layout.
+        let manifest = parse_json(blobs, Path::new("manifest.json"))?;
+        let entries = manifest
+            .as_array()
+            .ok_or_else(|| invalid_image("manifest.json is not an array"))?;
+        return entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let name = entry["RepoTags"][0]
+                    .as_str()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("image-{idx}"));
+                let layers = entry["Layers"]
+                    .as_array()
+                    .ok_or_else(|| invalid_image("manifest.json entry has no layers"))?
+                    .iter()
+                    .filter_map(|l| l.as_str().map(String::from))
+                    .collect();
+                Ok(Image { name, layers })
+            })
+            .collect();
+    }
+
+    let index = parse_json(blobs, Path::new("index.json"))?;
+    let mut images = Vec::new();
+    collect_oci_images(blobs, &index, None, &mut images)?;
+    Ok(images)
+}
+
+fn blob_path(digest: &str) -> io::Result<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .ok_or_else(|| invalid_image(format!("invalid digest {digest}")))?;
+    Ok(Path::new("blobs").join(algorithm).join(hex))
+}
+
+fn collect_oci_images(
+    blobs: &mut HashMap<PathBuf, Spool>,
+    index: &Value,
+    name: Option<&str>,
+    images: &mut Vec<Image>,
+) -> io::Result<()> {
+    let manifests = index["manifests"]
+        .as_array()
+        .ok_or_else(|| invalid_image("index has no manifests"))?;
+    for descriptor in manifests {
+        let digest = descriptor["digest"]
+            .as_str()
+            .ok_or_else(|| invalid_image("manifest descriptor has no digest"))?;
+        let name = descriptor["annotations"]["org.opencontainers.image.ref.name"]
+            .as_str()
+            .or(name)
+            .unwrap_or(digest);
+        let manifest = parse_json(blobs, &blob_path(digest)?)?;
+        if manifest.get("manifests").is_some() {
+            // A nested index, e.g. for a multi-platform image.
+            collect_oci_images(blobs, &manifest, Some(name), images)?;
+            continue;
+        }
+        let layers = manifest["layers"]
+            .as_array()
+            .ok_or_else(|| invalid_image("image manifest has no layers"))?
+            .iter()
+            .map(|l| {
+                l["digest"]
+                    .as_str()
+                    .ok_or_else(|| invalid_image("layer descriptor has no digest"))
+                    .and_then(blob_path)
+                    .map(|p| p.to_string_lossy().into_owned())
+            })
+            .collect::<io::Result<_>>()?;
+        let name = match images.iter().any(|i| i.name == name) {
+            // Platform variants of one image share a name.
+            true => format!("{name}@{digest}"),
+            false => name.to_string(),
+        };
+        images.push(Image { name, layers });
+    }
+    Ok(())
+}
+
+fn layer_name(path: &str) -> String {
+    // `blobs/sha256/<hex>` for OCI layouts and `<hex>/layer.tar` for older `docker save` output.
+    let path = Path::new(path);
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some("layer.tar") => path.parent().and_then(|p| p.file_name()),
+        _ => path.file_name(),
+    };
+    name.map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy())
+        .into_owned()
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+fn read_layer<F>(options: &ReadOptions, data: &mut Spool, mut callback: F) -> io::Result<()>
+where
+    F: FnMut(&Path, FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    let options = archive_options(options);
+    let mut visit = |item: FileItem<&mut dyn Read>| {
+        let path = normalize(&item.path);
+        callback(&path, item)
+    };
+    iterate_archive_inner(data, &mut Walk::new(&options, &mut visit))
+}
+
+fn read_entry<F>(
+    options: &ReadOptions,
+    path: &Path,
+    item: FileItem<&mut dyn Read>,
+    callback: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    read_recursive_inner(
+        path,
+        item.kind,
+        item.size_hint,
+        item.metadata,
+        &mut Buffered::new(item.reader, &mut Buffer::default()),
+        &mut Walk::new(options, callback),
+    )
+}
+
+/// Yields each path from the topmost layer that contains it, applying whiteouts.
+///
+/// The layers are read from the top down, so that everything needed to decide whether an entry is
+/// visible is known when it is reached, and each layer is read once.
+fn read_merged<F>(
+    options: &ReadOptions,
+    root: &Path,
+    layers: &[String],
+    blobs: &mut HashMap<PathBuf, Spool>,
+    callback: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    // Paths provided by a higher layer.
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    // Paths hidden, along with everything below them, from lower layers by whiteouts.
+    let mut removed: HashSet<PathBuf> = HashSet::new();
+    // Paths whose contents are hidden from lower layers, by opaque whiteouts or by a higher layer
+    // replacing a directory with a file.
+    let mut replaced: HashSet<PathBuf> = HashSet::new();
+    for name in layers.iter().rev() {
+        let mut layer_removed = Vec::new();
+        let mut layer_replaced = Vec::new();
+        let mut layer_seen = Vec::new();
+        read_layer(options, blob(blobs, name)?, |path, item| {
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let parent = path.parent().unwrap_or(Path::new(""));
+            if file_name == OPAQUE_WHITEOUT {
+                layer_replaced.push(parent.to_path_buf());
+                return Ok(());
+            }
+            if let Some(name) = file_name.strip_prefix(WHITEOUT_PREFIX) {
+                layer_removed.push(parent.join(name));
+                return Ok(());
+            }
+            let hidden = seen.contains(path)
+                || path.ancestors().any(|p| removed.contains(p))
+                || path.ancestors().skip(1).any(|p| replaced.contains(p));
+            if hidden {
+                return Ok(());
+            }
+            if !item.kind.is_directory() {
+                layer_replaced.push(path.to_path_buf());
+            }
+            layer_seen.push(path.to_path_buf());
+            read_entry(options, &root.join(path), item, callback)
+        })?;
+        // Whiteouts only apply to the layers below them.
+        removed.extend(layer_removed);
+        replaced.extend(layer_replaced);
+        seen.extend(layer_seen);
+    }
+    Ok(())
+}
@@ -0,0 +1,482 @@
+use crate::error::Error;
+use crate::filter::Filter;
+#[cfg(feature = "hash")]
+use crate::hash::{FileHashes, HashAlgorithm};
+#[cfg(feature = "index")]
+use crate::index::{Index, Segments};
+#[cfg(feature = "oci")]
+use crate::oci::{OciView, read_image};
+use crate::parallel::{ParallelOptions, read_parallel};
+use crate::peekable::{Buffer, Buffered};
+use crate::registry::FormatRegistry;
+use crate::spool::{Spool, SpoolOptions};
+use crate::visitor::{Find, Lenient, ReadSummary, Visitor, Walk};
+use crate::{
+    FileItem, FileKind, Metadata, SizeHint, iterate_archive_inner, iterate_seekable,
+    read_recursive_inner, read_seekable,
+};
+use std::io;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+/// Configures how inputs are read. The free functions such as
+/// [`recursive_read`](crate::recursive_read) use the default options.
+///
+/// ```rust
+/// use anyreader::{ReadOptions, SpoolOptions};
+/// use std::fs::File;
+/// use std::io::{self, BufReader};
+/// use std::path::Path;
+///
+/// fn main() -> io::Result<()> {
+///     let reader = BufReader::new(File::open("tests/data/archive.tar.gz")?);
+///     ReadOptions::new()
+///         .spool(SpoolOptions::new().memory_limit(1024 * 1024))
+///         .recursive_read(Path::new("archive.tar.gz"), reader, &mut |item| {
+///             println!("{}", item.path.display());
+///             Ok(())
+///         })?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub(crate) spool: Option<SpoolOptions>,
+    pub(crate) formats: FormatRegistry,
+    pub(crate) content_types: bool,
+    pub(crate) verify_checksums: bool,
+    pub(crate) filter: Filter,
+    #[cfg(feature = "hash")]
+    pub(crate) hashes: Vec<HashAlgorithm>,
+    // Zero, the derived default, means one thread.
+    decode_threads: usize,
+}
+
+impl ReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffers nested archives that need random access, such as zip archives, so they can be
+    /// read through a seekable view. Without this, nested zip archives are read from their
+    /// local headers, and compound files are spooled with the default options.
+    ///
+    /// Email messages are always buffered in memory, and the spool's
+    /// [`max_size`](SpoolOptions::max_size) also limits their size.
+    pub fn spool(mut self, options: SpoolOptions) -> Self {
+        self.spool = Some(options);
+        self
+    }
+
+    /// Sets the formats that are detected. By default every built-in format is.
+    pub fn formats(mut self, formats: FormatRegistry) -> Self {
+        self.formats = formats;
+        self
+    }
+
+    /// Detects the MIME type and extension of files that are not archives or compressed, setting
+    /// [`FileItem::content_type`]. Detection uses the bytes already peeked to find the format, so
+    /// it does not read any more of the file.
+    pub fn content_types(mut self, enabled: bool) -> Self {
+        self.content_types = enabled;
+        self
+    }
+
+    /// Only yields files whose path matches one of `patterns`. Entries that cannot hold a
+    /// matching file are skipped before they are decompressed or checked for nested archives.
+    ///
+    /// Patterns are globs matched against the full path passed to the callback, including the
+    /// name of the input, one component at a time: `*` and `?` match within a component, `[a-z]`
+    /// matches a class of characters and `**` matches any number of components. A pattern
+    /// without a `/`, such as `*.class`, matches a name at any depth. A pattern matching a
+    /// directory or archive matches everything inside it.
+    ///
+    /// ```rust
+    /// use anyreader::ReadOptions;
+    ///
+    /// let options = ReadOptions::new()
+    ///     .include(["*.py", "*.pyi"])
+    ///     .exclude(["**/node_modules/**", "**/.git"]);
+    /// ```
+    pub fn include(mut self, patterns: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        for pattern in patterns {
+            self.filter.include(pattern.as_ref());
+        }
+        self
+    }
+
+    /// Skips files, directories and archives whose path matches one of `patterns`, without
+    /// decompressing or reading them, even if they match [`include`](Self::include). Patterns
+    /// are matched as they are for `include`, so `**/node_modules/**` skips everything inside
+    /// any `node_modules` directory or archive.
+    pub fn exclude(mut self, patterns: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        for pattern in patterns {
+            self.filter.exclude(pattern.as_ref());
+        }
+        self
+    }
+
+    /// Checks every entry that has a stored [`Checksum`](crate::Checksum) against its data,
+    /// reading the rest of the entry after the callback returns, and fails with a corrupt error
+    /// if they do not match. Without this, entries are only checked if the callback reads them to
+    /// the end, and a mismatch is only reported to [`Visitor::verified`].
+    pub fn verify_checksums(mut self, enabled: bool) -> Self {
+        self.verify_checksums = enabled;
+        self
+    }
+
+    /// Hashes the contents of every file with each of `algorithms`, passing the results to
+    /// [`Visitor::hashed`](crate::Visitor::hashed). Files are read to the end after the callback
+    /// returns, so their hashes cover all of them even if the callback did not read them.
+    #[cfg(feature = "hash")]
+    pub fn hash(mut self, algorithms: impl IntoIterator<Item = HashAlgorithm>) -> Self {
+        self.hashes = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Recursively reads `reader` and returns the hashes of every file, with the algorithms set
+    /// with [`hash`](Self::hash).
+    ///
+    /// ```rust
+    /// use anyreader::{HashAlgorithm, ReadOptions};
+    /// use std::fs::File;
+    /// use std::io::{self, BufReader};
+    /// use std::path::Path;
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let reader = BufReader::new(File::open("tests/data/archive.tar.gz")?);
+    ///     let files = ReadOptions::new()
+    ///         .hash([HashAlgorithm::Sha256])
+    ///         .hash_files(Path::new("archive.tar.gz"), reader)?;
+    ///     for file in files {
+    ///         println!("{} {}", file.digests[0].to_hex(), file.path.display());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "hash")]
+    pub fn hash_files(&self, path: &Path, reader: impl Read) -> Result<Vec<FileHashes>, Error> {
+        struct Collect(Vec<FileHashes>);
+
+        impl Visitor for Collect {
+            fn visit_file(&mut self, _: FileItem<&mut dyn Read>) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn hashed(&mut self, hashes: &FileHashes) -> io::Result<()> {
+                self.0.push(hashes.clone());
+                Ok(())
+            }
+        }
+
+        let mut collect = Collect(vec![]);
+        self.visit(path, reader, &mut collect)?;
+        Ok(collect.0)
+    }
+
+    /// Recursively reads `reader`, as with [`recursive_read`](crate::recursive_read).
+    pub fn recursive_read<F>(
+        &self,
+        path: &Path,
+        reader: impl Read,
+        callback: &mut F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        self.visit(path, reader, callback)
+    }
+
+    /// Sets how many threads decode a single compressed stream. Defaults to 1.
+    ///
+    /// Gzip made of BGZF members, as written by `bgzip`, has batches of members inflated on
+    /// several threads. With the `c-backend` feature, xz uses liblzma's multi-threaded decoder,
+    /// which decodes the blocks of streams written by `xz -T` in parallel. Other streams,
+    /// including gzip written as a single member, are decoded on the reading thread.
+    pub fn decode_threads(mut self, threads: usize) -> Self {
+        self.decode_threads = threads.max(1);
+        self
+    }
+
+    #[cfg(any(feature = "gzip", all(feature = "xz", feature = "c-backend")))]
+    pub(crate) fn decode_thread_count(&self) -> usize {
+        self.decode_threads.max(1)
+    }
+
+    /// Recursively reads `reader`, reading the entries of its outermost archive on a pool of
+    /// threads.
+    ///
+    /// The input is read on the calling thread. Each entry of the archive it holds is buffered
+    /// in memory and handed to a worker thread, which decompresses it, reads any archives nested
+    /// inside it and calls `callback` with its files, so `callback` is called from several
+    /// threads at once and files arrive in no particular order. Entries whose size is not known
+    /// up front, or that are larger than [`ParallelOptions::memory_budget`], are read on the
+    /// calling thread, which also waits while the buffered entries would exceed the budget.
+    ///
+    /// Reading stops at the first error, on any thread, and returns it.
+    ///
+    /// ```rust
+    /// use anyreader::{ParallelOptions, ReadOptions};
+    /// use std::fs::File;
+    /// use std::io::{self, BufReader};
+    /// use std::path::Path;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let reader = BufReader::new(File::open("tests/data/archive.tar.gz")?);
+    ///     let total = AtomicU64::new(0);
+    ///     ReadOptions::new().recursive_read_parallel(
+    ///         Path::new("archive.tar.gz"),
+    ///         reader,
+    ///         &ParallelOptions::new().threads(4),
+    ///         &|item| {
+    ///             let size = io::copy(item.reader, &mut io::sink())?;
+    ///             total.fetch_add(size, Ordering::Relaxed);
+    ///             Ok(())
+    ///         },
+    ///     )?;
+    ///     println!("{} bytes", total.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn recursive_read_parallel<F>(
+        &self,
+        path: &Path,
+        mut reader: impl Read,
+        parallel: &ParallelOptions,
+        callback: &F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(FileItem<&mut dyn Read>) -> io::Result<()> + Sync,
+    {
+        read_parallel(self, parallel, path, &mut reader, callback)
+    }
+
+    /// Recursively reads `reader`, skipping entries that cannot be read instead of stopping.
+    ///
+    /// Each skipped entry is passed to `on_error` as it happens. Data read from an entry before
+    /// it failed has already been passed to `callback`, so a truncated file yields everything up
+    /// to the point it ends. Errors returned by `callback` still stop reading.
+    ///
+    /// ```rust
+    /// use anyreader::ReadOptions;
+    /// use std::io;
+    /// use std::path::Path;
+    ///
+    /// // A truncated gzip stream.
+    /// let data: &[u8] = &[0x1f, 0x8b, 0x08, 0x00];
+    /// let summary = ReadOptions::new()
+    ///     .recursive_read_lenient(
+    ///         Path::new("input.gz"),
+    ///         data,
+    ///         &mut |item| {
+    ///             io::copy(item.reader, &mut io::sink())?;
+    ///             Ok(())
+    ///         },
+    ///         |err| eprintln!("skipped {err}"),
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(summary.skipped.len(), 1);
+    /// assert_eq!(summary.skipped[0].path(), Path::new("input.gz"));
+    /// ```
+    pub fn recursive_read_lenient<F, E>(
+        &self,
+        path: &Path,
+        reader: impl Read,
+        callback: &mut F,
+        on_error: E,
+    ) -> Result<ReadSummary, Error>
+    where
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+        E: FnMut(&Error),
+    {
+        let mut visitor = Lenient {
+            callback,
+            on_error,
+            summary: ReadSummary::default(),
+        };
+        self.visit(path, reader, &mut visitor)?;
+        Ok(visitor.summary)
+    }
+
+    /// Recursively reads `reader`, as with [`recursive_read`](Self::recursive_read), and
+    /// returns an [`Index`] of the tar archive it holds, which
+    /// [`open_entry`](crate::open_entry) uses to read single entries from it later.
+    ///
+    /// The index records each entry of the tar archive and, for gzip, zstd and xz compressed
+    /// archives, the positions where decompression can start. Entries of archives nested inside
+    /// it are not indexed, and the index is empty if the input is not a tar archive.
+    #[cfg(feature = "index")]
+    pub fn recursive_read_indexed<F>(
+        &self,
+        path: &Path,
+        reader: impl Read,
+        callback: &mut F,
+    ) -> Result<Index, Error>
+    where
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        let mut segments =
+            Segments::new(reader, &self.formats).map_err(|e| Error::from_io(e, path))?;
+        let mut walk = Walk::new(self, callback);
+        walk.index = Some(segments.builder());
+        let mut buffer = Buffer::default();
+        let result = read_recursive_inner(
+            path,
+            FileKind::File,
+            SizeHint::Unknown,
+            Metadata::default(),
+            &mut Buffered::new(&mut segments, &mut buffer),
+            &mut walk,
+        );
+        walk.finish(result, path)?;
+        let builder = walk.index.take().unwrap();
+        Ok(segments.finish(builder))
+    }
+
+    /// Reads the single file at `path` inside `reader`, such as
+    /// `release.tar.gz/pkg/lib.jar/META-INF/MANIFEST.MF`, where the first component names the
+    /// input itself as in the paths passed to callbacks.
+    ///
+    /// Only the archives and compressed files on the way to the file are read. Other entries are
+    /// skipped without decompressing or detecting them, and reading stops once the file is found.
+    /// The file is buffered in a [`Spool`], with the options set by [`spool`](Self::spool) or
+    /// the default ones, and an error with the kind [`io::ErrorKind::NotFound`] is returned if
+    /// there is no file at `path`.
+    ///
+    /// ```rust
+    /// use anyreader::ReadOptions;
+    /// use std::fs::File;
+    /// use std::io::{self, BufReader, Read};
+    ///
+    /// fn main() -> io::Result<()> {
+    ///     let reader = BufReader::new(File::open("tests/data/archive.tar.gz")?);
+    ///     let mut file = ReadOptions::new().open_path(reader, "archive.tar.gz/src/lib.rs")?;
+    ///     let mut contents = String::new();
+    ///     file.read_to_string(&mut contents)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn open_path(&self, reader: impl Read, path: impl AsRef<Path>) -> Result<Spool, Error> {
+        let target = path.as_ref();
+        let root = match target.components().next() {
+            Some(component) => Path::new(component.as_os_str()),
+            None => target,
+        };
+        let mut find = Find {
+            target,
+            spool: self.spool.clone().unwrap_or_default(),
+            found: None,
+        };
+        let mut walk = Walk::new(self, &mut find);
+        walk.target = Some(target.to_path_buf());
+        let mut buffer = Buffer::default();
+        let result = read_recursive_inner(
+            root,
+            FileKind::File,
+            SizeHint::Unknown,
+            Metadata::default(),
+            &mut Buffered::new(reader, &mut buffer),
+            &mut walk,
+        );
+        walk.finish(result, root)?;
+        find.found.ok_or_else(|| {
+            let err = io::Error::new(io::ErrorKind::NotFound, "no file at this path");
+            Error::from_io(err, target)
+        })
+    }
+
+    /// Recursively reads `reader`, passing files and errors to `visitor`.
+    pub fn visit<V: Visitor>(
+        &self,
+        path: &Path,
+        reader: impl Read,
+        visitor: &mut V,
+    ) -> Result<(), Error> {
+        let mut walk = Walk::new(self, visitor);
+        let mut buffer = Buffer::default();
+        let result = read_recursive_inner(
+            path,
+            FileKind::File,
+            SizeHint::Unknown,
+            Metadata::default(),
+            &mut Buffered::new(reader, &mut buffer),
+            &mut walk,
+        );
+        walk.finish(result, path)
+    }
+
+    /// Recursively reads a seekable input, as with
+    /// [`recursive_read_seekable`](crate::recursive_read_seekable).
+    pub fn recursive_read_seekable<F>(
+        &self,
+        path: &Path,
+        reader: impl Read + Seek,
+        callback: &mut F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        self.visit_seekable(path, reader, callback)
+    }
+
+    /// Recursively reads a seekable input, passing files and errors to `visitor`.
+    pub fn visit_seekable<V: Visitor>(
+        &self,
+        path: &Path,
+        reader: impl Read + Seek,
+        visitor: &mut V,
+    ) -> Result<(), Error> {
+        let mut walk = Walk::new(self, visitor);
+        let result = read_seekable(
+            path,
+            FileKind::File,
+            SizeHint::Unknown,
+            Metadata::default(),
+            BufReader::new(reader),
+            &mut walk,
+        );
+        walk.finish(result, path)
+    }
+
+    /// Recursively reads a container image archive, as with
+    /// [`read_oci_image`](crate::read_oci_image). The options apply to the files read from the
+    /// layers, and the formats to the image archive and the layers themselves.
+    #[cfg(feature = "oci")]
+    pub fn read_oci_image<F>(
+        &self,
+        path: &Path,
+        reader: impl Read,
+        view: OciView,
+        callback: &mut F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        read_image(self, path, reader, view, callback).map_err(|e| Error::from_io(e, path))
+    }
+
+    /// Iterates the entries of an archive, as with [`iterate_archive`](crate::iterate_archive).
+    pub fn iterate_archive<R, F>(&self, reader: R, mut callback: F) -> Result<(), Error>
+    where
+        R: Read,
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        let mut walk = Walk::new(self, &mut callback);
+        let mut buffer = Buffer::default();
+        let result = iterate_archive_inner(&mut Buffered::new(reader, &mut buffer), &mut walk);
+        walk.finish(result, Path::new(""))
+    }
+
+    /// Iterates the entries of a seekable archive, as with
+    /// [`iterate_archive_seekable`](crate::iterate_archive_seekable).
+    pub fn iterate_archive_seekable<R, F>(&self, reader: R, mut callback: F) -> Result<(), Error>
+    where
+        R: Read + Seek,
+        F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    {
+        let mut walk = Walk::new(self, &mut callback);
+        let result = iterate_seekable(BufReader::new(reader), &mut walk);
+        walk.finish(result, Path::new(""))
+    }
+}
@@ -0,0 +1,281 @@
+//! Reading the entries of an archive on a pool of threads.
+
+use crate::error::Error;
+use crate::peekable::{Buffer, Buffered};
+use crate::visitor::{Visitor, Walk};
+use crate::{
+    Checksum, EntryOffsets, FileItem, FileKind, Metadata, ReadOptions, SizeHint, error,
+    read_recursive_inner,
+};
+use std::io;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+
+const DEFAULT_MEMORY_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// Controls how [`ReadOptions::recursive_read_parallel`] uses threads and memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParallelOptions {
+    threads: Option<usize>,
+    memory_budget: u64,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+        }
+    }
+}
+
+impl ParallelOptions {
+    /// Uses one worker thread per CPU, and buffers up to 256 MiB of entries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of worker threads. Defaults to the number of CPUs.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Sets the most data that is buffered for the worker threads at once. Entries larger than
+    /// this, and entries whose size is not known up front, are read on the calling thread
+    /// instead.
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    fn thread_count(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+    }
+}
+
+/// An entry buffered for a worker thread.
+struct Job {
+    path: PathBuf,
+    kind: FileKind,
+    size_hint: SizeHint,
+    metadata: Metadata,
+    checksum: Option<Checksum>,
+    offsets: Option<EntryOffsets>,
+    layers: Vec<String>,
+    data: Vec<u8>,
+    /// The part of the memory budget reserved for the entry.
+    reserved: u64,
+}
+
+/// Hands entries to the worker threads, keeping the data buffered for them within the memory
+/// budget.
+pub(crate) struct Dispatcher {
+    jobs: Mutex<Option<Sender<Job>>>,
+    budget: u64,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    buffered: u64,
+    error: Option<Error>,
+}
+
+impl Dispatcher {
+    /// Returns true if an entry of `size_hint` can be buffered for a worker thread.
+    pub(crate) fn accepts(&self, size_hint: SizeHint) -> bool {
+        size_hint.exact().is_some_and(|size| size <= self.budget)
+    }
+
+    /// Buffers an entry and queues it for a worker thread, waiting until it fits in the memory
+    /// budget.
+    pub(crate) fn dispatch<V: Visitor>(
+        &self,
+        path: &Path,
+        kind: FileKind,
+        size_hint: SizeHint,
+        metadata: Metadata,
+        reader: &mut dyn Read,
+        walk: &mut Walk<V>,
+    ) -> io::Result<()> {
+        let size = size_hint.exact().unwrap_or_default();
+        {
+            let mut state = self.state.lock().unwrap();
+            while state.error.is_none() && state.buffered + size > self.budget {
+                state = self.released.wait(state).unwrap();
+            }
+            if state.error.is_some() {
+                let err = io::Error::other("stopped after an error on another thread");
+                return Err(error::fatal(err, path, &walk.layers));
+            }
+            state.buffered += size;
+        }
+        let mut data = Vec::with_capacity(size as usize);
+        let result = reader.read_to_end(&mut data);
+        let job = Job {
+            path: path.to_path_buf(),
+            kind,
+            size_hint,
+            metadata,
+            checksum: walk.entry_checksum.take(),
+            offsets: walk.entry_offsets.take(),
+            layers: walk.layers.clone(),
+            data,
+            reserved: size,
+        };
+        match result {
+            Ok(_) => {
+                if let Some(jobs) = &*self.jobs.lock().unwrap() {
+                    let _ = jobs.send(job);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.release(size, None);
+                Err(e)
+            }
+        }
+    }
+
+    fn release(&self, size: u64, error: Option<Error>) {
+        let mut state = self.state.lock().unwrap();
+        state.buffered -= size;
+        if state.error.is_none() {
+            state.error = error;
+        }
+        self.released.notify_all();
+    }
+}
+
+/// Releases a job's part of the memory budget when dropped, so that a panic in the callback does
+/// not leave the calling thread waiting for it.
+struct Reservation<'a> {
+    dispatcher: &'a Dispatcher,
+    size: u64,
+    path: PathBuf,
+    layers: Vec<String>,
+    error: Option<Error>,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut error = self.error.take();
+        if std::thread::panicking() {
+            let err = io::Error::other("the callback panicked");
+            let err = error::fatal(err, &self.path, &self.layers);
+            error = Some(Error::from_io(err, &self.path));
+        }
+        self.dispatcher.release(self.size, error);
+    }
+}
+
+/// Passes files to a callback that is shared between threads.
+struct Shared<'a, F>(&'a F);
+
+impl<F> Visitor for Shared<'_, F>
+where
+    F: Fn(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> io::Result<()> {
+        (self.0)(item)
+    }
+}
+
+fn work<F>(
+    options: &ReadOptions,
+    callback: &F,
+    jobs: &Mutex<Receiver<Job>>,
+    dispatcher: &Dispatcher,
+) where
+    F: Fn(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    loop {
+        let Ok(job) = jobs.lock().unwrap().recv() else {
+            return;
+        };
+        let mut reservation = Reservation {
+            dispatcher,
+            size: job.reserved,
+            path: job.path.clone(),
+            layers: job.layers.clone(),
+            error: None,
+        };
+        let failed = dispatcher.state.lock().unwrap().error.is_some();
+        let result = if failed {
+            Ok(())
+        } else {
+            let mut visitor = Shared(callback);
+            let mut walk = Walk::new(options, &mut visitor);
+            walk.layers = job.layers;
+            walk.entry_checksum = job.checksum;
+            walk.entry_offsets = job.offsets;
+            let result = read_recursive_inner(
+                &job.path,
+                job.kind,
+                job.size_hint,
+                job.metadata,
+                &mut Cursor::new(job.data),
+                &mut walk,
+            );
+            walk.finish(result, &job.path)
+        };
+        reservation.error = result.err();
+    }
+}
+
+/// Reads `reader` on the calling thread, handing the entries of its outermost archive to a pool
+/// of worker threads.
+pub(crate) fn read_parallel<F>(
+    options: &ReadOptions,
+    parallel: &ParallelOptions,
+    path: &Path,
+    reader: &mut dyn Read,
+    callback: &F,
+) -> Result<(), Error>
+where
+    F: Fn(FileItem<&mut dyn Read>) -> io::Result<()> + Sync,
+{
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let jobs = Mutex::new(receiver);
+    let dispatcher = Dispatcher {
+        jobs: Mutex::new(Some(sender)),
+        budget: parallel.memory_budget,
+        state: Mutex::new(State::default()),
+        released: Condvar::new(),
+    };
+    let result = std::thread::scope(|scope| {
+        for _ in 0..parallel.thread_count() {
+            scope.spawn(|| work(options, callback, &jobs, &dispatcher));
+        }
+        let mut visitor = Shared(callback);
+        let mut walk = Walk::new(options, &mut visitor);
+        walk.parallel = Some(&dispatcher);
+        let mut buffer = Buffer::default();
+        let result = read_recursive_inner(
+            path,
+            FileKind::File,
+            SizeHint::Unknown,
+            Metadata::default(),
+            &mut Buffered::new(reader, &mut buffer),
+            &mut walk,
+        );
+        let result = walk.finish(result, path);
+        // Closing the queue lets the workers finish once it is empty.
+        dispatcher.jobs.lock().unwrap().take();
+        result
+    });
+    // An error on a worker thread stops the calling thread with a placeholder error, so the
+    // worker's error is the one to return.
+    match dispatcher.state.into_inner().unwrap().error {
+        Some(err) => Err(err),
+        None => result,
+    }
+}
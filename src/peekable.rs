@@ -1,5 +1,8 @@
 use std::io;
-use std::io::{Chain, Read};
+use std::io::{BufRead, Read};
+
+/// The size of a [`Buffer`] once it is first filled.
+const BUF_CAPACITY: usize = 32 * 1024;
 
 pub struct Buf<const N: usize> {
     inner: [u8; N],
@@ -18,12 +21,15 @@ impl<const N: usize> Buf<N> {
         &self.inner[..self.len]
     }
 
-    pub fn append_from_reader(&mut self, reader: &mut impl Read) -> io::Result<usize> {
+    /// Copies as much of `reader`'s buffer as fits, returning how many bytes were copied.
+    pub fn append_from_buffer(&mut self, reader: &mut impl BufRead) -> io::Result<usize> {
         let len = self.len;
-        // let remaining = N - len;
-        let read = reader.read(&mut self.inner[len..])?;
-        self.len += read;
-        Ok(read)
+        let available = reader.fill_buf()?;
+        let copied = available.len().min(N - len);
+        self.inner[len..len + copied].copy_from_slice(&available[..copied]);
+        reader.consume(copied);
+        self.len += copied;
+        Ok(copied)
     }
 }
 
@@ -33,51 +39,208 @@ impl<const N: usize> AsRef<[u8]> for Buf<N> {
     }
 }
 
+/// Yields the peeked bytes and then the rest of the input.
+///
+/// The input is peeked at in its own buffer, which is read through directly. Only when that
+/// buffer held fewer than `N` bytes are the peeked bytes copied out of it, and then they are
+/// handed out first. Either way there is no second buffer, so decoders that need a [`BufRead`]
+/// read the input's buffer.
 pub struct PeekableReader<T, const N: usize> {
-    chain: Chain<std::io::Cursor<Buf<N>>, T>,
+    // Empty unless the peeked bytes had to be copied out of the input.
+    peek: Buf<N>,
+    peek_pos: usize,
+    reader: T,
 }
 
 impl<T: Read, const N: usize> Read for PeekableReader<T, N> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.chain.read(buf)
+        let copied = &self.peek.as_slice()[self.peek_pos..];
+        if copied.is_empty() {
+            return self.reader.read(buf);
+        }
+        let read = buf.len().min(copied.len());
+        buf[..read].copy_from_slice(&copied[..read]);
+        self.peek_pos += read;
+        Ok(read)
+    }
+}
+
+impl<T: BufRead, const N: usize> BufRead for PeekableReader<T, N> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.peek_pos < self.peek.len {
+            return Ok(&self.peek.as_slice()[self.peek_pos..]);
+        }
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if self.peek_pos < self.peek.len {
+            self.peek_pos = (self.peek_pos + amount).min(self.peek.len);
+        } else {
+            self.reader.consume(amount);
+        }
+    }
+}
+
+impl<T: BufRead, const N: usize> PeekableReader<T, N> {
+    /// Up to `N` bytes from the start of the input. Once it has been read from, this is where
+    /// reading is up to instead.
+    pub fn peek_buf(&mut self) -> io::Result<&[u8]> {
+        peek(&self.peek, &mut self.reader)
     }
 }
 
-impl<T: Read, const N: usize> PeekableReader<T, N> {
-    pub fn peek_buf(&self) -> &[u8] {
-        let (cursor, _) = self.chain.get_ref();
-        cursor.get_ref().as_slice()
+fn peek<'a, const N: usize>(
+    copied: &'a Buf<N>,
+    reader: &'a mut impl BufRead,
+) -> io::Result<&'a [u8]> {
+    if copied.len > 0 {
+        return Ok(copied.as_slice());
     }
+    let available = reader.fill_buf()?;
+    Ok(&available[..available.len().min(N)])
 }
 
-pub struct Peekable<T: Read, const N: usize> {
-    buf: std::io::Cursor<Buf<N>>,
+pub struct Peekable<T: BufRead, const N: usize> {
+    // Empty unless the input's buffer held fewer than `N` bytes.
+    buf: Buf<N>,
     reader: T,
 }
 
-impl<T: Read, const N: usize> Peekable<T, N> {
+impl<T: BufRead, const N: usize> Peekable<T, N> {
     pub fn new(mut reader: T) -> io::Result<Self> {
         let mut buf = Buf::new();
-        let mut total_read = 0;
-
-        while total_read < N {
-            let read = buf.append_from_reader(&mut reader)?;
-            if read == 0 {
-                break;
+        let available = reader.fill_buf()?.len();
+        if available < N {
+            // The input ends soon, or its buffer is small or partly consumed.
+            while buf.len < N {
+                if buf.append_from_buffer(&mut reader)? == 0 {
+                    break;
+                }
             }
-            total_read += read;
         }
-        let buf = std::io::Cursor::new(buf);
         Ok(Self { buf, reader })
     }
 
     pub fn into_reader(self) -> PeekableReader<T, N> {
         PeekableReader {
-            chain: self.buf.chain(self.reader),
+            peek: self.buf,
+            peek_pos: 0,
+            reader: self.reader,
         }
     }
 
-    pub fn peek_buf(&self) -> &[u8] {
-        self.buf.get_ref().as_slice()
+    /// Up to `N` bytes from the start of the input.
+    pub fn peek_buf(&mut self) -> io::Result<&[u8]> {
+        peek(&self.buf, &mut self.reader)
+    }
+}
+
+/// Kept between the readers a [`Buffered`] buffers, such as the entries of an archive, so that
+/// they share one allocation.
+#[derive(Default)]
+pub(crate) struct Buffer {
+    // Empty until it is first filled.
+    data: Vec<u8>,
+    pos: usize,
+    filled: usize,
+}
+
+/// Buffers a reader that is not a [`BufRead`] in a shared [`Buffer`]. Whatever is left in the
+/// buffer when it is dropped is discarded.
+pub(crate) struct Buffered<'a, R> {
+    buffer: &'a mut Buffer,
+    reader: R,
+}
+
+impl<'a, R: Read> Buffered<'a, R> {
+    pub(crate) fn new(reader: R, buffer: &'a mut Buffer) -> Self {
+        buffer.pos = 0;
+        buffer.filled = 0;
+        Self { buffer, reader }
+    }
+}
+
+impl<R: Read> Read for Buffered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Large reads skip the buffer when it is empty, as with `BufReader`.
+        if self.buffer.pos == self.buffer.filled && buf.len() >= BUF_CAPACITY {
+            return self.reader.read(buf);
+        }
+        let available = self.fill_buf()?;
+        let read = buf.len().min(available.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: Read> BufRead for Buffered<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buffer = &mut *self.buffer;
+        if buffer.pos == buffer.filled {
+            if buffer.data.is_empty() {
+                buffer.data = vec![0; BUF_CAPACITY];
+            }
+            buffer.filled = self.reader.read(&mut buffer.data)?;
+            buffer.pos = 0;
+        }
+        Ok(&buffer.data[buffer.pos..buffer.filled])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.buffer.pos = (self.buffer.pos + amount).min(self.buffer.filled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peekable_reader() {
+        let data: Vec<u8> = (0..100u8).collect();
+
+        // The input's buffer holds enough bytes, so they are peeked at in place.
+        let mut peekable: Peekable<_, 8> = Peekable::new(data.as_slice()).unwrap();
+        assert_eq!(peekable.peek_buf().unwrap(), &data[..8]);
+        assert!(peekable.buf.as_slice().is_empty());
+        let mut reader = peekable.into_reader();
+        assert_eq!(reader.fill_buf().unwrap(), &data[..]);
+        reader.consume(3);
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[3..]);
+
+        // Through a buffer that holds fewer bytes, they are copied and handed out first.
+        let mut buffer = Buffer::default();
+        let mut buffered = Buffered::new(data.as_slice(), &mut buffer);
+        buffered.fill_buf().unwrap();
+        buffered.consume(95);
+        let mut peekable: Peekable<_, 8> = Peekable::new(buffered).unwrap();
+        assert_eq!(peekable.peek_buf().unwrap(), &data[95..]);
+        let mut reader = peekable.into_reader();
+        assert_eq!(reader.fill_buf().unwrap(), &data[95..]);
+        reader.consume(2);
+        let mut buf = [0; 10];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &data[97..]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_buffered() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let mut buffer = Buffer::default();
+        let mut buffered = Buffered::new(&data[..50], &mut buffer);
+        let mut buf = [0; 10];
+        buffered.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[..10]);
+        // The buffer is reused, and what the last reader left in it is dropped.
+        let mut buffered = Buffered::new(&data[50..], &mut buffer);
+        let mut rest = vec![];
+        buffered.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[50..]);
+        assert_eq!(buffer.data.len(), BUF_CAPACITY);
     }
 }
@@ -0,0 +1,333 @@
+//! Choosing which formats are detected, including formats implemented outside this crate.
+
+use crate::container::{DynContainer, is_cfb, is_cpio, is_email, is_warc, is_xar};
+use crate::stream::{is_pbzx, is_zstd};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::Read;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+
+/// The formats that are built into this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumIter, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Pbzx,
+    Tar,
+    Zip,
+    Xar,
+    Cpio,
+    Cfb,
+    Warc,
+    Email,
+}
+
+impl Format {
+    /// Returns true for the compression formats, which wrap a single stream, and false for the
+    /// archive formats.
+    pub fn is_compression(self) -> bool {
+        matches!(
+            self,
+            Self::Gzip | Self::Zstd | Self::Bzip2 | Self::Xz | Self::Pbzx
+        )
+    }
+
+    /// Returns true if `buf`, the first bytes of the input, is in this format.
+    pub(crate) fn detect(self, buf: &[u8]) -> bool {
+        match self {
+            Self::Gzip => infer::archive::is_gz(buf),
+            Self::Zstd => is_zstd(buf),
+            Self::Bzip2 => infer::archive::is_bz2(buf),
+            Self::Xz => infer::archive::is_xz(buf),
+            Self::Pbzx => is_pbzx(buf),
+            Self::Tar => infer::archive::is_tar(buf),
+            Self::Zip => infer::archive::is_zip(buf),
+            Self::Xar => is_xar(buf),
+            Self::Cpio => is_cpio(buf),
+            Self::Cfb => is_cfb(buf),
+            Self::Warc => is_warc(buf),
+            Self::Email => is_email(buf),
+        }
+    }
+
+    /// The error returned when the format is detected but its cargo feature is disabled.
+    pub(crate) fn unsupported(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{self} was detected, but the `{self}` feature is not enabled"),
+        )
+    }
+}
+
+/// A compression format implemented outside this crate.
+pub trait CodecFormat: Send + Sync {
+    /// A short name for the format, used in logs.
+    fn name(&self) -> &str;
+
+    /// Returns true if `buf`, the first bytes of the input, is in this format.
+    fn detect(&self, buf: &[u8]) -> bool;
+
+    /// Wraps the input, including the bytes passed to [`detect`](Self::detect), in a decoder.
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>>;
+}
+
+/// An archive format implemented outside this crate. The container it opens is usually a type
+/// that implements [`Container`](crate::Container).
+pub trait ContainerFormat: Send + Sync {
+    /// A short name for the format, used in logs.
+    fn name(&self) -> &str;
+
+    /// Returns true if `buf`, the first bytes of the input, is in this format.
+    fn detect(&self, buf: &[u8]) -> bool;
+
+    /// Opens the input, including the bytes passed to [`detect`](Self::detect), as a container.
+    fn open<'a>(&self, reader: Box<dyn Read + 'a>) -> io::Result<Box<dyn DynContainer + 'a>>;
+}
+
+/// The set of formats that are detected when reading, in the order they are checked.
+///
+/// The built-in [`Format`]s are checked in the order they are declared, compression formats
+/// first. Formats that are disabled are not detected, so their data is yielded as-is. Formats
+/// added with [`codec`](Self::codec) and [`container`](Self::container) are checked just before
+/// the built-in compression and archive formats respectively, and
+/// [`codec_before`](Self::codec_before) and [`container_before`](Self::container_before) place
+/// them just before a given built-in format. The first format that matches the input is used.
+///
+/// ```rust
+/// use anyreader::{Format, FormatRegistry, ReadOptions};
+///
+/// // Treat jars and other zip files as opaque files.
+/// let options = ReadOptions::new().formats(FormatRegistry::new().disable(Format::Zip));
+/// ```
+#[derive(Clone)]
+pub struct FormatRegistry {
+    detectors: Vec<Detector>,
+    disabled: Vec<Format>,
+}
+
+/// A format in a [`FormatRegistry`].
+#[derive(Clone)]
+enum Detector {
+    Builtin(Format),
+    Codec(Arc<dyn CodecFormat>),
+    Container(Arc<dyn ContainerFormat>),
+}
+
+/// The format that an input was detected as by a [`FormatRegistry`].
+pub(crate) enum Detected<'a> {
+    Builtin(Format),
+    Codec(&'a Arc<dyn CodecFormat>),
+    Container(&'a Arc<dyn ContainerFormat>),
+}
+
+impl Detected<'_> {
+    /// The built-in compression format, if that is what was detected.
+    pub(crate) fn compression(&self) -> Option<Format> {
+        match self {
+            Self::Builtin(format) if format.is_compression() => Some(*format),
+            _ => None,
+        }
+    }
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        Self {
+            detectors: Format::iter().map(Detector::Builtin).collect(),
+            disabled: vec![],
+        }
+    }
+}
+
+impl Debug for FormatRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let detectors: Vec<_> = self
+            .detectors
+            .iter()
+            .map(|d| match d {
+                Detector::Builtin(format) => format.to_string(),
+                Detector::Codec(c) => c.name().to_string(),
+                Detector::Container(c) => c.name().to_string(),
+            })
+            .collect();
+        f.debug_struct("FormatRegistry")
+            .field("detectors", &detectors)
+            .field("disabled", &self.disabled)
+            .finish()
+    }
+}
+
+impl FormatRegistry {
+    /// A registry with every built-in format enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with no formats enabled, so everything is read as-is.
+    pub fn empty() -> Self {
+        Self {
+            disabled: Format::iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Enables a built-in format.
+    pub fn enable(mut self, format: Format) -> Self {
+        self.disabled.retain(|f| *f != format);
+        self
+    }
+
+    /// Disables a built-in format.
+    pub fn disable(mut self, format: Format) -> Self {
+        if !self.disabled.contains(&format) {
+            self.disabled.push(format);
+        }
+        self
+    }
+
+    /// Adds a compression format, checked after the formats already added with this method and
+    /// before the built-in compression formats.
+    pub fn codec(self, codec: impl CodecFormat + 'static) -> Self {
+        let before = Format::iter().find(|f| f.is_compression()).unwrap();
+        self.codec_before(before, codec)
+    }
+
+    /// Adds an archive format, checked after the compression formats and the formats already
+    /// added with this method, and before the built-in archive formats.
+    pub fn container(self, container: impl ContainerFormat + 'static) -> Self {
+        let before = Format::iter().find(|f| !f.is_compression()).unwrap();
+        self.container_before(before, container)
+    }
+
+    /// Adds a compression format, checked just before the built-in `format`.
+    pub fn codec_before(mut self, format: Format, codec: impl CodecFormat + 'static) -> Self {
+        let index = self.position(format);
+        self.detectors
+            .insert(index, Detector::Codec(Arc::new(codec)));
+        self
+    }
+
+    /// Adds an archive format, checked just before the built-in `format`.
+    pub fn container_before(
+        mut self,
+        format: Format,
+        container: impl ContainerFormat + 'static,
+    ) -> Self {
+        let index = self.position(format);
+        self.detectors
+            .insert(index, Detector::Container(Arc::new(container)));
+        self
+    }
+
+    fn position(&self, format: Format) -> usize {
+        self.detectors
+            .iter()
+            .position(|d| matches!(d, Detector::Builtin(f) if *f == format))
+            .expect("every built-in format is registered")
+    }
+
+    /// Returns true if the built-in format is enabled.
+    pub fn is_enabled(&self, format: Format) -> bool {
+        !self.disabled.contains(&format)
+    }
+
+    /// Returns the first enabled format that `buf`, the first bytes of the input, is in.
+    pub(crate) fn detect(&self, buf: &[u8]) -> Option<Detected<'_>> {
+        self.detectors.iter().find_map(|d| match d {
+            Detector::Builtin(format) => (self.is_enabled(*format) && format.detect(buf))
+                .then_some(Detected::Builtin(*format)),
+            Detector::Codec(c) => c.detect(buf).then_some(Detected::Codec(c)),
+            Detector::Container(c) => c.detect(buf).then_some(Detected::Container(c)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{ContainerFormat, Detected, Format, FormatRegistry};
+    use crate::stream::StreamKind;
+    use crate::{ContainerKind, DynContainer, ReadOptions};
+    use assert_matches::assert_matches;
+    use std::io::Read;
+
+    // A zstd frame header.
+    const ZSTD_DATA: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn test_disabled_format_is_raw() {
+        let formats = FormatRegistry::new().disable(Format::Zstd);
+        assert!(!formats.is_enabled(Format::Zstd));
+        let options = ReadOptions::new().formats(formats);
+        let kind = ContainerKind::from_reader_with(ZSTD_DATA, &options).unwrap();
+        assert_matches!(kind, ContainerKind::Stream(StreamKind::Raw(_)));
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_unsupported_format() {
+        let err = ContainerKind::from_reader(ZSTD_DATA).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    /// Matches any input.
+    struct Anything;
+
+    impl ContainerFormat for Anything {
+        fn name(&self) -> &str {
+            "anything"
+        }
+
+        fn detect(&self, _buf: &[u8]) -> bool {
+            true
+        }
+
+        fn open<'a>(
+            &self,
+            _reader: Box<dyn Read + 'a>,
+        ) -> std::io::Result<Box<dyn DynContainer + 'a>> {
+            // Only detection is tested.
+            Err(std::io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    #[test]
+    fn test_format_order() {
+        const XAR_DATA: &[u8] = b"xar!\0\x1c\0\x01";
+
+        let formats = FormatRegistry::new().container(Anything);
+        assert!(matches!(
+            formats.detect(ZSTD_DATA),
+            Some(Detected::Builtin(Format::Zstd))
+        ));
+        assert!(matches!(
+            formats.detect(XAR_DATA),
+            Some(Detected::Container(_))
+        ));
+
+        // Placed between the built-in formats, it is only used when none before it match.
+        let formats = FormatRegistry::new().container_before(Format::Cpio, Anything);
+        assert!(matches!(
+            formats.detect(XAR_DATA),
+            Some(Detected::Builtin(Format::Xar))
+        ));
+        assert!(matches!(
+            formats.detect(b"070707"),
+            Some(Detected::Container(_))
+        ));
+        assert!(matches!(
+            formats.detect(b"hello"),
+            Some(Detected::Container(_))
+        ));
+
+        // Disabling a built-in format keeps its place.
+        let formats = formats.disable(Format::Xar).enable(Format::Xar);
+        assert!(matches!(
+            formats.detect(XAR_DATA),
+            Some(Detected::Builtin(Format::Xar))
+        ));
+    }
+}
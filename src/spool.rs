@@ -0,0 +1,198 @@
+//! Buffering entries so that formats which need random access can read them.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tracing::trace;
+
+const DEFAULT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Controls how entries are buffered by a [`Spool`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpoolOptions {
+    memory_limit: usize,
+    pub(crate) max_size: Option<u64>,
+    temp_dir: Option<PathBuf>,
+}
+
+impl Default for SpoolOptions {
+    fn default() -> Self {
+        Self {
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            max_size: None,
+            temp_dir: None,
+        }
+    }
+}
+
+impl SpoolOptions {
+    /// Buffers up to 16 MiB in memory, and any larger entry in a temporary file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many bytes are buffered in memory before spilling to a temporary file.
+    pub fn memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Sets the largest entry that will be spooled. Larger entries return an error.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Sets the directory that temporary files are created in, instead of the system default.
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+}
+
+/// A seekable copy of a reader, held in memory or in a temporary file. The file is deleted when
+/// the spool is dropped.
+#[derive(Debug)]
+pub struct Spool {
+    inner: SpoolInner,
+    len: u64,
+}
+
+#[derive(Debug)]
+enum SpoolInner {
+    Memory(Cursor<Vec<u8>>),
+    File(BufReader<File>),
+}
+
+impl Spool {
+    /// Reads `reader` to the end, positioning the spool at the start.
+    pub fn new(reader: impl Read, options: &SpoolOptions) -> io::Result<Self> {
+        let limit = match options.max_size {
+            Some(max) => max.saturating_add(1),
+            None => u64::MAX,
+        };
+        let mut reader = reader.take(limit);
+
+        let mut buf = Vec::new();
+        let memory_limit = options.memory_limit.saturating_add(1) as u64;
+        (&mut reader).take(memory_limit).read_to_end(&mut buf)?;
+        let inner = if buf.len() as u64 <= options.memory_limit as u64 {
+            SpoolInner::Memory(Cursor::new(buf))
+        } else {
+            let mut file = match &options.temp_dir {
+                Some(dir) => tempfile::tempfile_in(dir)?,
+                None => tempfile::tempfile()?,
+            };
+            trace!(path = ?options.temp_dir, "spooling to a temporary file");
+            file.write_all(&buf)?;
+            drop(buf);
+            io::copy(&mut reader, &mut file)?;
+            file.seek(SeekFrom::Start(0))?;
+            SpoolInner::File(BufReader::new(file))
+        };
+
+        let len = match &inner {
+            SpoolInner::Memory(c) => c.get_ref().len() as u64,
+            SpoolInner::File(f) => f.get_ref().metadata()?.len(),
+        };
+        if let Some(max) = options.max_size
+            && len > max
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("entry is larger than the spool limit of {max} bytes"),
+            ));
+        }
+        Ok(Self { inner, len })
+    }
+
+    /// The number of bytes in the spool.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the spool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the data is held in memory rather than a temporary file.
+    pub fn is_in_memory(&self) -> bool {
+        matches!(self.inner, SpoolInner::Memory(_))
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            SpoolInner::Memory(c) => c.read(buf),
+            SpoolInner::File(f) => f.read(buf),
+        }
+    }
+}
+
+impl BufRead for Spool {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match &mut self.inner {
+            SpoolInner::Memory(c) => c.fill_buf(),
+            SpoolInner::File(f) => f.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match &mut self.inner {
+            SpoolInner::Memory(c) => c.consume(amount),
+            SpoolInner::File(f) => f.consume(amount),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.inner {
+            SpoolInner::Memory(c) => c.seek(pos),
+            SpoolInner::File(f) => f.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spool::{Spool, SpoolOptions};
+    use std::io::{Read, Seek, SeekFrom};
+
+    const DATA: &[u8] = b"hello world";
+
+    #[test]
+    fn test_spool_memory() {
+        let mut spool = Spool::new(DATA, &SpoolOptions::new()).unwrap();
+        assert!(spool.is_in_memory());
+        assert_eq!(spool.len(), DATA.len() as u64);
+        let mut buf = String::new();
+        spool.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf.as_bytes(), DATA);
+    }
+
+    #[test]
+    fn test_spool_file() {
+        let options = SpoolOptions::new().memory_limit(4);
+        let mut spool = Spool::new(DATA, &options).unwrap();
+        assert!(!spool.is_in_memory());
+        assert_eq!(spool.len(), DATA.len() as u64);
+        spool.seek(SeekFrom::Start(6)).unwrap();
+        let mut buf = String::new();
+        spool.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "world");
+    }
+
+    #[test]
+    fn test_spool_max_size() {
+        let options = SpoolOptions::new().memory_limit(4).max_size(5);
+        let err = Spool::new(DATA, &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+        // The limit is inclusive.
+        let options = SpoolOptions::new().max_size(DATA.len() as u64);
+        assert!(Spool::new(DATA, &options).is_ok());
+    }
+}
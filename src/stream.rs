@@ -1,16 +1,50 @@
+#[cfg(any(feature = "zstd", feature = "xz"))]
+mod backend;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "pbzx")]
+mod pbzx;
+mod size;
+#[cfg(any(feature = "gzip", all(feature = "xz", feature = "c-backend")))]
+mod threaded;
+
+use crate::ReadOptions;
+use crate::SizeHint;
 use crate::peekable::{Peekable, PeekableReader};
-use flate2::read::GzDecoder;
+use crate::registry::Format;
+#[cfg(all(feature = "xz", feature = "xar"))]
+pub(crate) use backend::LzmaDecoder;
+#[cfg(feature = "xz")]
+pub use backend::XzDecoder;
+#[cfg(feature = "zstd")]
+pub use backend::ZstdDecoder;
+#[cfg(all(feature = "zstd", feature = "index"))]
+pub(crate) use backend::ZstdFrameDecoder;
+#[cfg(feature = "gzip")]
+pub use gzip::GzipDecoder;
+#[cfg(feature = "gzip")]
+pub(crate) use gzip::is_gzip;
+#[cfg(feature = "pbzx")]
+pub use pbzx::PbzxDecoder;
+pub(crate) use size::trailer_size;
 use std::fmt::{Debug, Formatter};
 use std::io;
-use std::io::{BufReader, Read};
+use std::io::{BufRead, Read};
+#[cfg(feature = "gzip")]
+pub use threaded::BgzfDecoder;
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+pub use threaded::ThreadedXzDecoder;
 use tracing::trace;
 
-pub enum StreamKind<T: Read, const N: usize> {
+const PBZX_MAGIC: &[u8] = b"pbzx";
+
+#[allow(clippy::large_enum_variant)]
+pub enum StreamKind<T: BufRead, const N: usize> {
     Compressed(CompressionKind<T, N>),
     Raw(PeekableReader<T, N>),
 }
 
-impl<T: Read, const N: usize> Debug for StreamKind<T, N> {
+impl<T: BufRead, const N: usize> Debug for StreamKind<T, N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             StreamKind::Compressed(c) => write!(f, "StreamKind::Compressed({:?})", c),
@@ -19,7 +53,7 @@ impl<T: Read, const N: usize> Debug for StreamKind<T, N> {
     }
 }
 
-impl<T: Read, const N: usize> Read for StreamKind<T, N> {
+impl<T: BufRead, const N: usize> Read for StreamKind<T, N> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             StreamKind::Compressed(r) => r.read(buf),
@@ -28,73 +62,233 @@ impl<T: Read, const N: usize> Read for StreamKind<T, N> {
     }
 }
 
-impl<T: Read, const N: usize> StreamKind<T, N> {
+impl<T: BufRead, const N: usize> StreamKind<T, N> {
     pub fn from_peekable(peekable: Peekable<T, N>) -> io::Result<StreamKind<T, N>> {
-        let buf = peekable.peek_buf();
-
-        if infer::archive::is_gz(buf) {
-            trace!("gzip detected");
-            let decoder = GzDecoder::new(peekable.into_reader());
-            Ok(StreamKind::Compressed(CompressionKind::Gzip(decoder)))
-        } else if is_zstd(buf) {
-            trace!("zstd detected");
-            let decoder = zstd::Decoder::with_buffer(BufReader::new(peekable.into_reader()))?;
-            Ok(StreamKind::Compressed(CompressionKind::Zst(decoder)))
-        } else if infer::archive::is_bz2(buf) {
-            trace!("bzip2 detected");
-            let decoder = bzip2::read::BzDecoder::new(peekable.into_reader());
-            Ok(StreamKind::Compressed(CompressionKind::Bzip2(decoder)))
-        } else if infer::archive::is_xz(buf) {
-            trace!("xz detected");
-            let decoder = liblzma::read::XzDecoder::new_multi_decoder(peekable.into_reader());
-            Ok(StreamKind::Compressed(CompressionKind::Xz(decoder)))
-        } else {
+        Self::from_peekable_with(peekable, &ReadOptions::default())
+    }
+
+    /// Detects the built-in compression formats that are enabled in the
+    /// [`formats`](ReadOptions::formats) of `options`. Input that is detected as any other format
+    /// is raw. Streams are decoded on the [`decode_threads`](ReadOptions::decode_threads) of
+    /// `options`.
+    #[cfg_attr(
+        not(any(
+            feature = "gzip",
+            feature = "zstd",
+            feature = "bzip2",
+            feature = "xz",
+            feature = "pbzx"
+        )),
+        allow(unused_variables, unreachable_code)
+    )]
+    pub fn from_peekable_with(
+        mut peekable: Peekable<T, N>,
+        options: &ReadOptions,
+    ) -> io::Result<StreamKind<T, N>> {
+        let detected = options.formats.detect(peekable.peek_buf()?);
+        let Some(format) = detected.and_then(|d| d.compression()) else {
             trace!("raw detected");
-            Ok(StreamKind::Raw(peekable.into_reader()))
-        }
+            return Ok(StreamKind::Raw(peekable.into_reader()));
+        };
+        trace!(%format, "compression detected");
+        #[cfg(feature = "gzip")]
+        let bgzf = options.decode_thread_count() > 1 && is_bgzf(peekable.peek_buf()?);
+        let reader = peekable.into_reader();
+        let compression = match format {
+            #[cfg(feature = "gzip")]
+            Format::Gzip if bgzf => {
+                CompressionKind::Bgzf(BgzfDecoder::new(reader, options.decode_thread_count()))
+            }
+            #[cfg(feature = "gzip")]
+            Format::Gzip => CompressionKind::Gzip(GzipDecoder::new(reader)),
+            #[cfg(feature = "zstd")]
+            Format::Zstd => CompressionKind::Zst(ZstdDecoder::new(reader)?),
+            #[cfg(feature = "bzip2")]
+            Format::Bzip2 => CompressionKind::Bzip2(bzip2::bufread::BzDecoder::new(reader)),
+            #[cfg(all(feature = "xz", feature = "c-backend"))]
+            Format::Xz if options.decode_thread_count() > 1 => CompressionKind::ThreadedXz(
+                ThreadedXzDecoder::new(reader, options.decode_thread_count())?,
+            ),
+            #[cfg(feature = "xz")]
+            Format::Xz => CompressionKind::Xz(XzDecoder::new_multi(reader)),
+            #[cfg(feature = "pbzx")]
+            Format::Pbzx => CompressionKind::Pbzx(PbzxDecoder::new(reader)),
+            format => return Err(format.unsupported()),
+        };
+        Ok(StreamKind::Compressed(compression))
     }
 }
 
+pub(crate) fn is_pbzx(buf: &[u8]) -> bool {
+    buf.starts_with(PBZX_MAGIC)
+}
+
+/// Returns true if `buf` starts with a BGZF member: a gzip member with a single `BC` extra field
+/// holding the member's size.
+pub(crate) fn is_bgzf(buf: &[u8]) -> bool {
+    buf.len() >= 16
+        && buf[..4] == [0x1f, 0x8b, 0x08, 0x04]
+        && buf[10..16] == [6, 0, b'B', b'C', 2, 0]
+}
+
 // Lower value for compression detection only.
-const STREAM_BUF_SIZE: usize = 8;
+pub(crate) const STREAM_BUF_SIZE: usize = 8;
 
-impl<T: Read> StreamKind<T, STREAM_BUF_SIZE> {
+impl<T: BufRead> StreamKind<T, STREAM_BUF_SIZE> {
     pub fn from_reader(reader: T) -> io::Result<StreamKind<T, STREAM_BUF_SIZE>> {
         let peekable: Peekable<T, 8> = Peekable::new(reader)?;
         Self::from_peekable(peekable)
     }
 }
 
-pub enum CompressionKind<T: Read, const N: usize> {
-    Gzip(GzDecoder<PeekableReader<T, N>>),
-    Zst(zstd::Decoder<'static, BufReader<PeekableReader<T, N>>>),
-    Bzip2(bzip2::read::BzDecoder<PeekableReader<T, N>>),
-    Xz(liblzma::read::XzDecoder<PeekableReader<T, N>>),
+#[allow(clippy::large_enum_variant)]
+pub enum CompressionKind<T: BufRead, const N: usize> {
+    #[cfg(feature = "gzip")]
+    Gzip(GzipDecoder<PeekableReader<T, N>>),
+    /// Gzip made of BGZF members, inflated on several threads.
+    #[cfg(feature = "gzip")]
+    Bgzf(BgzfDecoder<PeekableReader<T, N>>),
+    #[cfg(feature = "zstd")]
+    Zst(ZstdDecoder<PeekableReader<T, N>>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::bufread::BzDecoder<PeekableReader<T, N>>),
+    #[cfg(feature = "xz")]
+    Xz(XzDecoder<PeekableReader<T, N>>),
+    /// Xz decoded on several threads.
+    #[cfg(all(feature = "xz", feature = "c-backend"))]
+    ThreadedXz(ThreadedXzDecoder<PeekableReader<T, N>>),
+    #[cfg(feature = "pbzx")]
+    Pbzx(PbzxDecoder<PeekableReader<T, N>>),
+    // Keeps the type parameters used when every compression format is disabled.
+    #[cfg(not(any(
+        feature = "gzip",
+        feature = "zstd",
+        feature = "bzip2",
+        feature = "xz",
+        feature = "pbzx"
+    )))]
+    #[doc(hidden)]
+    _Unsupported(std::convert::Infallible, std::marker::PhantomData<T>),
 }
 
-impl<T: Read, const N: usize> Debug for CompressionKind<T, N> {
+impl<T: BufRead, const N: usize> CompressionKind<T, N> {
+    /// The compression format.
+    pub fn format(&self) -> Format {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(_) | Self::Bgzf(_) => Format::Gzip,
+            #[cfg(feature = "zstd")]
+            Self::Zst(_) => Format::Zstd,
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2(_) => Format::Bzip2,
+            #[cfg(feature = "xz")]
+            Self::Xz(_) => Format::Xz,
+            #[cfg(all(feature = "xz", feature = "c-backend"))]
+            Self::ThreadedXz(_) => Format::Xz,
+            #[cfg(feature = "pbzx")]
+            Self::Pbzx(_) => Format::Pbzx,
+            #[cfg(not(any(
+                feature = "gzip",
+                feature = "zstd",
+                feature = "bzip2",
+                feature = "xz",
+                feature = "pbzx"
+            )))]
+            Self::_Unsupported(never, _) => match *never {},
+        }
+    }
+
+    /// The uncompressed size recorded at the start of the stream. Only zstd records one, in its
+    /// frame header.
+    pub fn size_hint(&self) -> SizeHint {
+        match self {
+            #[cfg(feature = "zstd")]
+            Self::Zst(decoder) => decoder.content_size(),
+            #[allow(unreachable_patterns)]
+            _ => SizeHint::Unknown,
+        }
+    }
+}
+
+impl<T: BufRead, const N: usize> Debug for CompressionKind<T, N> {
+    #[cfg_attr(
+        not(any(
+            feature = "gzip",
+            feature = "zstd",
+            feature = "bzip2",
+            feature = "xz",
+            feature = "pbzx"
+        )),
+        allow(unused_variables)
+    )]
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            #[cfg(feature = "gzip")]
             Self::Gzip(_) => f.write_str("Gzip"),
+            #[cfg(feature = "gzip")]
+            Self::Bgzf(_) => f.write_str("Bgzf"),
+            #[cfg(feature = "zstd")]
             Self::Zst(_) => f.write_str("Zstd"),
+            #[cfg(feature = "bzip2")]
             Self::Bzip2(_) => f.write_str("Bzip2"),
+            #[cfg(feature = "xz")]
             Self::Xz(_) => f.write_str("Xz"),
+            #[cfg(all(feature = "xz", feature = "c-backend"))]
+            Self::ThreadedXz(_) => f.write_str("ThreadedXz"),
+            #[cfg(feature = "pbzx")]
+            Self::Pbzx(_) => f.write_str("Pbzx"),
+            #[cfg(not(any(
+                feature = "gzip",
+                feature = "zstd",
+                feature = "bzip2",
+                feature = "xz",
+                feature = "pbzx"
+            )))]
+            Self::_Unsupported(never, _) => match *never {},
         }
     }
 }
 
-impl<T: Read, const N: usize> Read for CompressionKind<T, N> {
+impl<T: BufRead, const N: usize> Read for CompressionKind<T, N> {
+    #[cfg_attr(
+        not(any(
+            feature = "gzip",
+            feature = "zstd",
+            feature = "bzip2",
+            feature = "xz",
+            feature = "pbzx"
+        )),
+        allow(unused_variables)
+    )]
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
+            #[cfg(feature = "gzip")]
             Self::Gzip(r) => r.read(buf),
+            #[cfg(feature = "gzip")]
+            Self::Bgzf(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
             Self::Zst(r) => r.read(buf),
+            #[cfg(feature = "bzip2")]
             Self::Bzip2(r) => r.read(buf),
+            #[cfg(feature = "xz")]
             Self::Xz(r) => r.read(buf),
+            #[cfg(all(feature = "xz", feature = "c-backend"))]
+            Self::ThreadedXz(r) => r.read(buf),
+            #[cfg(feature = "pbzx")]
+            Self::Pbzx(r) => r.read(buf),
+            #[cfg(not(any(
+                feature = "gzip",
+                feature = "zstd",
+                feature = "bzip2",
+                feature = "xz",
+                feature = "pbzx"
+            )))]
+            Self::_Unsupported(never, _) => match *never {},
         }
     }
 }
 
-fn is_zstd(buffer: &[u8]) -> bool {
+pub(crate) fn is_zstd(buffer: &[u8]) -> bool {
     // https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#zstandard-frames
     // 4 Bytes, little-endian format. Value : 0xFD2FB528
 
@@ -111,11 +305,21 @@ fn is_zstd(buffer: &[u8]) -> bool {
         || (magic_from_buffer & SKIPPABLE_FRAME_MASK) == SKIPPABLE_FRAME_BASE
 }
 
-#[cfg(test)]
+#[cfg(all(
+    test,
+    feature = "gzip",
+    feature = "zstd",
+    feature = "bzip2",
+    feature = "xz",
+    feature = "pbzx"
+))]
 mod tests {
     use crate::stream::{CompressionKind, StreamKind};
+    use crate::{ContainerKind, ReadOptions};
     use assert_matches::assert_matches;
-    use std::io::Write;
+    use lzma_rust2::{XzOptions, XzWriter};
+    use ruzstd::encoding::CompressionLevel;
+    use std::io::{Read, Write};
 
     use tracing_test::traced_test;
 
@@ -142,11 +346,28 @@ mod tests {
     #[traced_test]
     #[test]
     fn test_zstd_file() {
-        let data = zstd::encode_all(DATA, 1).unwrap();
+        let data = ruzstd::encoding::compress_to_vec(DATA, CompressionLevel::Fastest);
         let file_kind = StreamKind::from_reader(data.as_slice()).unwrap();
         assert_matches!(file_kind, StreamKind::Compressed(CompressionKind::Zst(_)));
     }
 
+    #[traced_test]
+    #[test]
+    fn test_zstd_frames() {
+        let frame = ruzstd::encoding::compress_to_vec(DATA, CompressionLevel::Fastest);
+        // A skippable frame holding four bytes of metadata, between two frames.
+        let mut data = frame.clone();
+        data.extend_from_slice(&0x184D2A50u32.to_le_bytes());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"meta");
+        data.extend_from_slice(&frame);
+
+        let mut file_kind = StreamKind::from_reader(data.as_slice()).unwrap();
+        let mut out = Vec::new();
+        file_kind.read_to_end(&mut out).unwrap();
+        assert_eq!(out, [DATA, DATA].concat());
+    }
+
     #[traced_test]
     #[test]
     fn test_bzip2() {
@@ -160,10 +381,153 @@ mod tests {
     #[traced_test]
     #[test]
     fn test_xz() {
-        let mut data = liblzma::write::XzEncoder::new(Vec::new(), 1);
+        let mut data = XzWriter::new(Vec::new(), XzOptions::with_preset(1)).unwrap();
         data.write_all(DATA).unwrap();
         let data = data.finish().unwrap();
-        let file_kind = StreamKind::from_reader(data.as_slice()).unwrap();
+        let mut file_kind = StreamKind::from_reader(data.as_slice()).unwrap();
         assert_matches!(file_kind, StreamKind::Compressed(CompressionKind::Xz(_)));
+        let mut out = Vec::new();
+        file_kind.read_to_end(&mut out).unwrap();
+        assert_eq!(out, DATA);
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_pbzx() {
+        let mut chunk = XzWriter::new(Vec::new(), XzOptions::with_preset(1)).unwrap();
+        chunk.write_all(DATA).unwrap();
+        let chunk = chunk.finish().unwrap();
+        let mut data = b"pbzx".to_vec();
+        data.extend_from_slice(&(1u64 << 24).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&(chunk.len() as u64).to_be_bytes());
+        data.extend_from_slice(&chunk);
+
+        let mut file_kind = StreamKind::from_reader(data.as_slice()).unwrap();
+        assert_matches!(file_kind, StreamKind::Compressed(CompressionKind::Pbzx(_)));
+        let mut out = Vec::new();
+        file_kind.read_to_end(&mut out).unwrap();
+        assert_eq!(out, DATA);
+    }
+
+    /// Compresses each chunk as a BGZF member, ending with the empty member `bgzip` writes.
+    fn bgzf(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![];
+        for chunk in chunks.iter().chain([&b""[..]].iter()) {
+            let mut encoder = flate2::GzBuilder::new()
+                .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                .write(Vec::new(), Default::default());
+            encoder.write_all(chunk).unwrap();
+            let mut member = encoder.finish().unwrap();
+            let size = (member.len() - 1) as u16;
+            member[16..18].copy_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&member);
+        }
+        data
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_bgzf() {
+        let chunks: Vec<Vec<u8>> = (0..200)
+            .map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let chunks: Vec<&[u8]> = chunks.iter().map(|c| c.as_slice()).collect();
+        let data = bgzf(&chunks);
+        let options = ReadOptions::new().decode_threads(4);
+        let kind = ContainerKind::from_reader_with(data.as_slice(), &options).unwrap();
+        let mut stream = assert_matches!(
+            kind,
+            ContainerKind::Stream(StreamKind::Compressed(c @ CompressionKind::Bgzf(_))) => c
+        );
+        let mut out = Vec::new();
+        stream.read_to_end(&mut out).unwrap();
+        assert_eq!(out, chunks.concat());
+
+        // With one thread the members are read as ordinary concatenated gzip.
+        let kind = ContainerKind::from_reader(data.as_slice()).unwrap();
+        assert_matches!(
+            kind,
+            ContainerKind::Stream(StreamKind::Compressed(CompressionKind::Gzip(_)))
+        );
+
+        // Members from the first one that isn't a BGZF block on are decoded in order.
+        let gzip = |data: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let data = [bgzf(&chunks), gzip(DATA), bgzf(&[DATA]), gzip(DATA)].concat();
+        let kind = ContainerKind::from_reader_with(data.as_slice(), &options).unwrap();
+        let mut stream = assert_matches!(kind, ContainerKind::Stream(s) => s);
+        let mut out = Vec::new();
+        stream.read_to_end(&mut out).unwrap();
+        assert_eq!(out, [chunks.concat(), DATA.repeat(3)].concat());
+
+        // A corrupt member is still an error.
+        let mut data = [bgzf(&[DATA]), gzip(DATA)].concat();
+        let len = data.len();
+        data[len - 10] ^= 0xff;
+        let kind = ContainerKind::from_reader_with(data.as_slice(), &options).unwrap();
+        let mut stream = assert_matches!(kind, ContainerKind::Stream(s) => s);
+        assert!(std::io::copy(&mut stream, &mut std::io::sink()).is_err());
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_gzip_trailing_data() {
+        let gzip = |data: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let members = [gzip(DATA), gzip(DATA)].concat();
+        let bgzf = [bgzf(&[DATA]), bgzf(&[DATA])].concat();
+        // Zero padding, as tape and block devices add, and junk after the last member end the
+        // stream.
+        for trailer in [&[0; 512][..], b"junk"] {
+            for (data, threads) in [(&members, 1), (&bgzf, 4)] {
+                let data = [data.as_slice(), trailer].concat();
+                let options = ReadOptions::new().decode_threads(threads);
+                let kind = ContainerKind::from_reader_with(data.as_slice(), &options).unwrap();
+                let mut stream = assert_matches!(kind, ContainerKind::Stream(s) => s);
+                let mut out = Vec::new();
+                stream.read_to_end(&mut out).unwrap();
+                assert_eq!(out, DATA.repeat(2));
+            }
+        }
+
+        // A member that is cut short is still an error.
+        let data = &members[..members.len() - 4];
+        let mut stream = StreamKind::from_reader(data).unwrap();
+        assert!(std::io::copy(&mut stream, &mut std::io::sink()).is_err());
+    }
+
+    #[cfg(feature = "c-backend")]
+    #[traced_test]
+    #[test]
+    fn test_threaded_xz() {
+        let data: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut options = XzOptions::with_preset(1);
+        options.set_block_size(std::num::NonZeroU64::new(64 * 1024));
+        let mut stream = XzWriter::new(Vec::new(), options).unwrap();
+        stream.write_all(&data).unwrap();
+        let stream = stream.finish().unwrap();
+        // Two streams with padding between them.
+        let input = [stream.as_slice(), &[0; 4], stream.as_slice()].concat();
+
+        let options = ReadOptions::new().decode_threads(4);
+        let kind = StreamKind::from_peekable_with(
+            crate::peekable::Peekable::<_, 8>::new(input.as_slice()).unwrap(),
+            &options,
+        )
+        .unwrap();
+        let mut stream = assert_matches!(
+            kind,
+            StreamKind::Compressed(c @ CompressionKind::ThreadedXz(_)) => c
+        );
+        let mut out = Vec::new();
+        stream.read_to_end(&mut out).unwrap();
+        assert_eq!(out, [data.as_slice(), data.as_slice()].concat());
     }
 }
@@ -0,0 +1,330 @@
+//! Decoders whose implementation depends on the backend feature. With `c-backend` they wrap the
+//! reference C libraries, and with only `pure-rust` they wrap decoders written in Rust.
+
+#[cfg(feature = "zstd")]
+use crate::SizeHint;
+#[cfg(feature = "zstd")]
+use crate::stream::size::zstd_content_size;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{BufRead, Read};
+
+#[cfg(all(feature = "zstd", feature = "c-backend"))]
+type ZstdInner<R> = zstd::Decoder<'static, R>;
+#[cfg(all(feature = "zstd", not(feature = "c-backend")))]
+type ZstdInner<R> = pure::MultiFrameDecoder<R>;
+
+/// Decodes a zstd stream, including any concatenated and skippable frames. The input is read
+/// through its own buffer, so wrap a plain [`Read`] in a [`BufReader`](io::BufReader).
+#[cfg(feature = "zstd")]
+pub struct ZstdDecoder<R: BufRead> {
+    inner: ZstdInner<R>,
+    content_size: SizeHint,
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> ZstdDecoder<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let content_size = zstd_content_size(reader.fill_buf()?);
+        #[cfg(feature = "c-backend")]
+        let inner = zstd::Decoder::with_buffer(reader)?;
+        #[cfg(not(feature = "c-backend"))]
+        let inner = pure::MultiFrameDecoder::new(reader);
+        Ok(Self {
+            inner,
+            content_size,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// The content size recorded in the header of the first frame, if it was in the input's
+    /// buffer when the decoder was created.
+    pub fn content_size(&self) -> SizeHint {
+        self.content_size
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> Debug for ZstdDecoder<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZstdDecoder").finish()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+type XzInner<R> = liblzma::bufread::XzDecoder<R>;
+// The reader holds its dictionary buffers inline, which would make every enum holding it large.
+#[cfg(all(feature = "xz", not(feature = "c-backend")))]
+type XzInner<R> = Box<lzma_rust2::XzReader<R>>;
+
+/// Decodes an xz stream. The input is read through its own buffer, so wrap a plain [`Read`] in a
+/// [`BufReader`](io::BufReader).
+#[cfg(feature = "xz")]
+pub struct XzDecoder<R: BufRead> {
+    inner: XzInner<R>,
+}
+
+#[cfg(feature = "xz")]
+impl<R: BufRead> XzDecoder<R> {
+    /// Decodes a single xz stream, leaving anything after it unread.
+    pub fn new(reader: R) -> Self {
+        #[cfg(feature = "c-backend")]
+        let inner = liblzma::bufread::XzDecoder::new(reader);
+        #[cfg(not(feature = "c-backend"))]
+        let inner = Box::new(lzma_rust2::XzReader::new(reader, false));
+        Self { inner }
+    }
+
+    /// Decodes concatenated xz streams as one.
+    pub fn new_multi(reader: R) -> Self {
+        #[cfg(feature = "c-backend")]
+        let inner = liblzma::bufread::XzDecoder::new_multi_decoder(reader);
+        #[cfg(not(feature = "c-backend"))]
+        let inner = Box::new(lzma_rust2::XzReader::new(reader, true));
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> R {
+        #[cfg(feature = "c-backend")]
+        let inner = self.inner;
+        #[cfg(not(feature = "c-backend"))]
+        let inner = *self.inner;
+        inner.into_inner()
+    }
+}
+
+#[cfg(feature = "xz")]
+impl<R: BufRead> Debug for XzDecoder<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("XzDecoder").finish()
+    }
+}
+
+#[cfg(feature = "xz")]
+impl<R: BufRead> Read for XzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(all(feature = "xz", feature = "xar", feature = "c-backend"))]
+type LzmaInner<R> = liblzma::bufread::XzDecoder<R>;
+#[cfg(all(feature = "xz", feature = "xar", not(feature = "c-backend")))]
+type LzmaInner<R> = Box<lzma_rust2::LzmaReader<R>>;
+
+/// Decodes a legacy `.lzma` stream, also known as LZMA-alone: a 13 byte header followed by raw
+/// LZMA data, without the xz container around it.
+#[cfg(all(feature = "xz", feature = "xar"))]
+pub(crate) struct LzmaDecoder<R: BufRead> {
+    inner: LzmaInner<R>,
+}
+
+#[cfg(all(feature = "xz", feature = "xar"))]
+impl<R: BufRead> LzmaDecoder<R> {
+    pub(crate) fn new(reader: R) -> io::Result<Self> {
+        #[cfg(feature = "c-backend")]
+        let inner = liblzma::bufread::XzDecoder::new_stream(
+            reader,
+            liblzma::stream::Stream::new_lzma_decoder(u64::MAX)?,
+        );
+        #[cfg(not(feature = "c-backend"))]
+        let inner = Box::new(lzma_rust2::LzmaReader::new_mem_limit(
+            reader,
+            u32::MAX,
+            None,
+        )?);
+        Ok(Self { inner })
+    }
+}
+
+#[cfg(all(feature = "xz", feature = "xar"))]
+impl<R: BufRead> Read for LzmaDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(all(feature = "zstd", feature = "index", feature = "c-backend"))]
+type ZstdFrameInner<R> = zstd::Decoder<'static, R>;
+#[cfg(all(feature = "zstd", feature = "index", not(feature = "c-backend")))]
+type ZstdFrameInner<R> = pure::SingleFrameDecoder<R>;
+
+/// Decodes a single zstd frame, or skips a single skippable frame, reading nothing after it.
+#[cfg(all(feature = "zstd", feature = "index"))]
+pub(crate) struct ZstdFrameDecoder<R: BufRead> {
+    inner: ZstdFrameInner<R>,
+}
+
+#[cfg(all(feature = "zstd", feature = "index"))]
+impl<R: BufRead> ZstdFrameDecoder<R> {
+    pub(crate) fn new(reader: R) -> io::Result<Self> {
+        #[cfg(feature = "c-backend")]
+        let inner = zstd::Decoder::with_buffer(reader)?.single_frame();
+        #[cfg(not(feature = "c-backend"))]
+        let inner = pure::SingleFrameDecoder::new(reader);
+        Ok(Self { inner })
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.inner.finish()
+    }
+}
+
+#[cfg(all(feature = "zstd", feature = "index"))]
+impl<R: BufRead> Read for ZstdFrameDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(all(feature = "zstd", not(feature = "c-backend")))]
+mod pure {
+    use ruzstd::decoding::errors::{FrameDecoderError, ReadFrameHeaderError};
+    use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+    use std::io;
+    use std::io::{BufRead, Read};
+
+    /// `ruzstd`'s streaming decoder only reads a single frame, while the C library continues
+    /// with any frames that follow it.
+    pub struct MultiFrameDecoder<R: BufRead> {
+        source: R,
+        frame: Box<FrameDecoder>,
+        in_frame: bool,
+    }
+
+    impl<R: BufRead> MultiFrameDecoder<R> {
+        pub fn new(reader: R) -> Self {
+            Self {
+                source: reader,
+                frame: Box::new(FrameDecoder::new()),
+                in_frame: false,
+            }
+        }
+
+        pub fn get_ref(&self) -> &R {
+            &self.source
+        }
+
+        // Returns false at the end of the input.
+        fn next_frame(&mut self) -> io::Result<bool> {
+            loop {
+                if self.source.fill_buf()?.is_empty() {
+                    return Ok(false);
+                }
+                if start_frame(&mut self.frame, &mut self.source)? {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    impl<R: BufRead> Read for MultiFrameDecoder<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                if self.in_frame {
+                    let read = read_frame(&mut self.frame, &mut self.source, buf)?;
+                    if read > 0 {
+                        return Ok(read);
+                    }
+                    self.in_frame = false;
+                }
+                if !self.next_frame()? {
+                    return Ok(0);
+                }
+                self.in_frame = true;
+            }
+        }
+    }
+
+    /// Decodes the first frame of its input, and stops.
+    #[cfg(feature = "index")]
+    pub struct SingleFrameDecoder<R: BufRead> {
+        source: R,
+        frame: Box<FrameDecoder>,
+        started: bool,
+        in_frame: bool,
+    }
+
+    #[cfg(feature = "index")]
+    impl<R: BufRead> SingleFrameDecoder<R> {
+        pub fn new(reader: R) -> Self {
+            Self {
+                source: reader,
+                frame: Box::new(FrameDecoder::new()),
+                started: false,
+                in_frame: false,
+            }
+        }
+
+        // Named after the C library's method, so both backends can be called the same way.
+        pub fn finish(self) -> R {
+            self.source
+        }
+    }
+
+    #[cfg(feature = "index")]
+    impl<R: BufRead> Read for SingleFrameDecoder<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            if !self.started {
+                self.started = true;
+                self.in_frame = start_frame(&mut self.frame, &mut self.source)?;
+            }
+            if !self.in_frame {
+                return Ok(0);
+            }
+            let read = read_frame(&mut self.frame, &mut self.source, buf)?;
+            self.in_frame = read > 0;
+            Ok(read)
+        }
+    }
+
+    /// Reads the header of the next frame, returning false if it was a skippable frame and has
+    /// been skipped.
+    fn start_frame(frame: &mut FrameDecoder, source: &mut impl BufRead) -> io::Result<bool> {
+        match frame.reset(&mut *source) {
+            Ok(()) => Ok(true),
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                let skipped = io::copy(&mut (&mut *source).take(length as u64), &mut io::sink())?;
+                if skipped != length as u64 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(false)
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    /// Reads from the current frame, returning 0 once all of it has been read.
+    fn read_frame(
+        frame: &mut FrameDecoder,
+        source: &mut impl BufRead,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        while frame.can_collect() < buf.len() && !frame.is_finished() {
+            let needed = buf.len() - frame.can_collect();
+            frame
+                .decode_blocks(&mut *source, BlockDecodingStrategy::UptoBytes(needed))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        frame.read(buf)
+    }
+}
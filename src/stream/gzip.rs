@@ -0,0 +1,54 @@
+use flate2::bufread::GzDecoder;
+use std::io;
+use std::io::{BufRead, Read};
+
+/// Decodes gzip made of one or more concatenated members, as written by e.g. `.warc.gz` tools.
+///
+/// Another member is only started when the bytes after a member begin with the gzip magic, so
+/// zero padding or other trailing data after the last member ends the stream instead of failing
+/// it.
+pub struct GzipDecoder<R: BufRead> {
+    // Only `None` while starting the next member.
+    member: Option<GzDecoder<R>>,
+    done: bool,
+}
+
+impl<R: BufRead> GzipDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            member: Some(GzDecoder::new(reader)),
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.member.as_ref().unwrap().get_ref()
+    }
+}
+
+impl<R: BufRead> Read for GzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.done {
+                return Ok(0);
+            }
+            let member = self.member.as_mut().unwrap();
+            let read = member.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            if !is_gzip(member.get_mut().fill_buf()?) {
+                self.done = true;
+                return Ok(0);
+            }
+            let reader = self.member.take().unwrap().into_inner();
+            self.member = Some(GzDecoder::new(reader));
+        }
+    }
+}
+
+/// Returns true if `buf` could be the start of a gzip member. A buffer holding only the first
+/// byte of the magic is taken as a member, so that the decoder reports it if it is not one.
+pub(crate) fn is_gzip(buf: &[u8]) -> bool {
+    matches!(buf, [0x1f, 0x8b, ..] | [0x1f])
+}
@@ -0,0 +1,106 @@
+use crate::stream::{XzDecoder, is_pbzx};
+use std::io;
+use std::io::{BufReader, Chain, Cursor, Read, Take};
+
+const XZ_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
+// Set in a chunk's flags when another chunk follows it.
+const MORE_CHUNKS: u64 = 1 << 24;
+
+// The start of each chunk is read to check for the xz magic, so it is chained back in front.
+type Chunk<R> = Chain<Cursor<Vec<u8>>, Take<R>>;
+
+enum State<R: Read> {
+    Start(R),
+    // The flag is set when another chunk follows this one.
+    Xz(XzDecoder<BufReader<Chunk<R>>>, bool),
+    Raw(Chunk<R>, bool),
+    Done,
+}
+
+/// Decodes the pbzx stream format used for the `Payload` of newer macOS installer packages.
+///
+/// The stream is a sequence of chunks, each of which is either an xz stream or stored as-is.
+pub struct PbzxDecoder<R: Read> {
+    state: State<R>,
+}
+
+impl<R: Read> PbzxDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            state: State::Start(reader),
+        }
+    }
+
+    fn next_chunk(mut reader: R) -> io::Result<State<R>> {
+        let mut header = Vec::with_capacity(16);
+        (&mut reader).take(16).read_to_end(&mut header)?;
+        match header.len() {
+            // Some writers set the flag on the last chunk too, so also stop at a clean EOF.
+            0 => return Ok(State::Done),
+            16 => {}
+            _ => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+        let flags = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let length = u64::from_be_bytes(header[8..].try_into().unwrap());
+        let more = flags & MORE_CHUNKS != 0;
+
+        let mut chunk = reader.take(length);
+        let mut magic = Vec::with_capacity(XZ_MAGIC.len());
+        (&mut chunk)
+            .take(XZ_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        let is_xz = magic == XZ_MAGIC;
+        let chunk = Cursor::new(magic).chain(chunk);
+        Ok(if is_xz {
+            State::Xz(XzDecoder::new(BufReader::new(chunk)), more)
+        } else {
+            State::Raw(chunk, more)
+        })
+    }
+}
+
+impl<R: Read> Read for PbzxDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = match &mut self.state {
+                State::Start(_) => 0,
+                State::Xz(d, _) => d.read(buf)?,
+                State::Raw(r, _) => r.read(buf)?,
+                State::Done => return Ok(0),
+            };
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let reader = match std::mem::replace(&mut self.state, State::Done) {
+                State::Start(mut reader) => {
+                    // Magic followed by the flags for the first chunk.
+                    let mut header = [0; 12];
+                    reader.read_exact(&mut header)?;
+                    if !is_pbzx(&header) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid pbzx header magic",
+                        ));
+                    }
+                    let flags = u64::from_be_bytes(header[4..].try_into().unwrap());
+                    if flags & MORE_CHUNKS == 0 {
+                        return Ok(0);
+                    }
+                    reader
+                }
+                // Whatever the buffer holds is within the chunk, so it is skipped anyway.
+                State::Xz(d, true) => end_of_chunk(d.into_inner().into_inner())?,
+                State::Raw(r, true) => end_of_chunk(r)?,
+                _ => return Ok(0),
+            };
+            self.state = Self::next_chunk(reader)?;
+        }
+    }
+}
+
+// Skips any bytes left in the chunk after the end of its xz stream.
+fn end_of_chunk<R: Read>(chunk: Chunk<R>) -> io::Result<R> {
+    let (_, mut rest) = chunk.into_inner();
+    io::copy(&mut rest, &mut io::sink())?;
+    Ok(rest.into_inner())
+}
@@ -0,0 +1,198 @@
+//! Reads the uncompressed size that some compression formats record in their headers or
+//! trailers.
+
+use crate::SizeHint;
+use crate::container::ARCHIVE_BUF_SIZE;
+use crate::registry::{Format, FormatRegistry};
+use crate::stream::is_bgzf;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+const XZ_FOOTER_MAGIC: &[u8] = b"YZ";
+const XZ_HEADER_SIZE: u64 = 12;
+const XZ_FOOTER_SIZE: u64 = 12;
+// Larger indexes are valid, but are not worth reading into memory just for a size hint.
+const MAX_XZ_INDEX_SIZE: u64 = 1024 * 1024;
+
+/// Returns the content size from the header of the zstd frame that `buf` starts with.
+///
+/// The size only covers the first frame, so it is an estimate: a stream of several frames, such
+/// as one written by `pzstd`, is larger.
+#[cfg(feature = "zstd")]
+pub(crate) fn zstd_content_size(buf: &[u8]) -> SizeHint {
+    let Some(header) = buf.strip_prefix(ZSTD_MAGIC) else {
+        return SizeHint::Unknown;
+    };
+    let Some(&descriptor) = header.first() else {
+        return SizeHint::Unknown;
+    };
+    let single_segment = descriptor & 0x20 != 0;
+    let size_len = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => return SizeHint::Unknown,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let window_len = if single_segment { 0 } else { 1 };
+    let dictionary_len = [0, 1, 2, 4][usize::from(descriptor & 0x03)];
+    let start = 1 + window_len + dictionary_len;
+    let Some(field) = header.get(start..start + size_len) else {
+        return SizeHint::Unknown;
+    };
+    let mut bytes = [0; 8];
+    bytes[..size_len].copy_from_slice(field);
+    let size = u64::from_le_bytes(bytes);
+    // Two byte sizes are stored with an offset, so that they do not overlap one byte sizes.
+    let size = if size_len == 2 { size + 256 } else { size };
+    SizeHint::Estimated(size)
+}
+
+/// Reads the uncompressed size of a seekable gzip or xz input from its end, leaving the reader
+/// where it was.
+///
+/// A gzip trailer only records the size modulo 2^32, and only for the last member, so it is a
+/// lower bound. Gzip made of BGZF members, which are at most 64 KiB each, is always unknown. An
+/// xz index records the size of every block, so it is exact when the input is a single stream.
+pub(crate) fn trailer_size<R: Read + Seek>(
+    reader: &mut R,
+    formats: &FormatRegistry,
+) -> io::Result<SizeHint> {
+    let start = reader.stream_position()?;
+    let mut magic = Vec::with_capacity(ARCHIVE_BUF_SIZE);
+    (&mut *reader)
+        .take(ARCHIVE_BUF_SIZE as u64)
+        .read_to_end(&mut magic)?;
+    let hint = match formats.detect(&magic).and_then(|d| d.compression()) {
+        Some(Format::Gzip) if is_bgzf(&magic) => SizeHint::Unknown,
+        Some(Format::Gzip) => gzip_last_member_size(reader, start)?,
+        Some(Format::Xz) => xz_size(reader, start)?,
+        _ => SizeHint::Unknown,
+    };
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(hint)
+}
+
+fn gzip_last_member_size<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<SizeHint> {
+    // The smallest gzip member is a 10 byte header, an empty deflate block and an 8 byte trailer.
+    let end = reader.seek(SeekFrom::End(0))?;
+    if end.saturating_sub(start) < 20 {
+        return Ok(SizeHint::Unknown);
+    }
+    reader.seek(SeekFrom::End(-4))?;
+    let mut size = [0; 4];
+    reader.read_exact(&mut size)?;
+    Ok(SizeHint::AtLeast(u32::from_le_bytes(size).into()))
+}
+
+fn xz_size<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<SizeHint> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let len = end.saturating_sub(start);
+    if len < XZ_HEADER_SIZE + XZ_FOOTER_SIZE {
+        return Ok(SizeHint::Unknown);
+    }
+    reader.seek(SeekFrom::End(-(XZ_FOOTER_SIZE as i64)))?;
+    let mut footer = [0; XZ_FOOTER_SIZE as usize];
+    reader.read_exact(&mut footer)?;
+    // Stream padding after the footer is allowed, but rare enough not to look for.
+    if &footer[10..] != XZ_FOOTER_MAGIC {
+        return Ok(SizeHint::Unknown);
+    }
+    let backward_size = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+    let index_size = (u64::from(backward_size) + 1) * 4;
+    if index_size > MAX_XZ_INDEX_SIZE || XZ_HEADER_SIZE + index_size + XZ_FOOTER_SIZE > len {
+        return Ok(SizeHint::Unknown);
+    }
+    reader.seek(SeekFrom::Start(end - XZ_FOOTER_SIZE - index_size))?;
+    let mut index = vec![0; index_size as usize];
+    reader.read_exact(&mut index)?;
+    let Some((blocks_size, size)) = parse_xz_index(&index) else {
+        return Ok(SizeHint::Unknown);
+    };
+    // With concatenated streams the index only describes the last one.
+    if XZ_HEADER_SIZE + blocks_size + index_size + XZ_FOOTER_SIZE != len {
+        return Ok(SizeHint::Unknown);
+    }
+    Ok(SizeHint::Exact(size))
+}
+
+/// Returns the total size of the blocks, including their padding, and of the uncompressed data.
+fn parse_xz_index(index: &[u8]) -> Option<(u64, u64)> {
+    let (&indicator, mut rest) = index.split_first()?;
+    if indicator != 0 {
+        return None;
+    }
+    let records = read_varint(&mut rest)?;
+    let mut blocks_size = 0u64;
+    let mut size = 0u64;
+    for _ in 0..records {
+        let unpadded = read_varint(&mut rest)?;
+        let uncompressed = read_varint(&mut rest)?;
+        blocks_size = blocks_size.checked_add(unpadded.checked_next_multiple_of(4)?)?;
+        size = size.checked_add(uncompressed)?;
+    }
+    Some((blocks_size, size))
+}
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7F) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_content_size() {
+        // Single segment, one byte size.
+        let header = [0x28, 0xB5, 0x2F, 0xFD, 0x20, 11];
+        assert_eq!(zstd_content_size(&header), SizeHint::Estimated(11));
+        // Window descriptor and a two byte size.
+        let header = [0x28, 0xB5, 0x2F, 0xFD, 0x40, 0x00, 0x00, 0x01];
+        assert_eq!(zstd_content_size(&header), SizeHint::Estimated(512));
+        // No size.
+        let header = [0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x00];
+        assert_eq!(zstd_content_size(&header), SizeHint::Unknown);
+        // Truncated.
+        assert_eq!(zstd_content_size(&header[..4]), SizeHint::Unknown);
+    }
+
+    #[test]
+    fn test_parse_xz_index() {
+        // Two records, with unpadded sizes that are padded to 4 bytes.
+        let index = [0x00, 0x02, 0x0D, 0x80, 0x01, 0x10, 0x05];
+        assert_eq!(parse_xz_index(&index), Some((16 + 16, 128 + 5)));
+        assert_eq!(parse_xz_index(&index[..4]), None);
+    }
+
+    #[test]
+    fn test_gzip_size() {
+        // A gzip member whose trailer records 1100 bytes. The size is read without decoding it.
+        let header = [0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0, 0xff];
+        let trailer = [0, 0, 0, 0, 0x4c, 0x04, 0, 0];
+        let gzip = [&header[..], &[0; 16], &trailer].concat();
+        let hint = trailer_size(&mut Cursor::new(&gzip), &FormatRegistry::new()).unwrap();
+        assert_eq!(hint, SizeHint::AtLeast(1100));
+
+        // The last BGZF member is usually the empty one that ends the file.
+        let header = [
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 41, 0,
+        ];
+        let bgzf = [&header[..], &[0; 16], &trailer].concat();
+        let hint = trailer_size(&mut Cursor::new(&bgzf), &FormatRegistry::new()).unwrap();
+        assert_eq!(hint, SizeHint::Unknown);
+    }
+}
@@ -0,0 +1,267 @@
+//! Decoders that spread the work of decompressing a single stream over several threads.
+
+#[cfg(feature = "gzip")]
+use crate::stream::{GzipDecoder, is_bgzf, is_gzip};
+use std::fmt::{Debug, Formatter};
+use std::io;
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+use std::io::BufRead;
+use std::io::Read;
+
+/// The length of a BGZF member header, up to and including the `BSIZE` field.
+#[cfg(feature = "gzip")]
+const BGZF_HEADER_LEN: usize = 18;
+
+/// How many members each thread inflates at a time.
+#[cfg(feature = "gzip")]
+const MEMBERS_PER_THREAD: usize = 16;
+
+/// Decodes gzip made of BGZF members, as written by `bgzip` and used for `.bam` and `.vcf.gz`
+/// files. Every member records its compressed size, so batches of them are read and inflated on
+/// several threads at once. From the first member that is not a BGZF block on, the rest of the
+/// input is decoded as ordinary concatenated gzip on the reading thread. Padding or other data
+/// that does not start a gzip member ends the input.
+#[cfg(feature = "gzip")]
+pub struct BgzfDecoder<R: Read> {
+    // Only `None` while switching to the sequential decoder.
+    input: Option<Input<R>>,
+    threads: usize,
+    decoded: Vec<u8>,
+    position: usize,
+}
+
+// The bytes of the first member that is not a BGZF block are chained back in front.
+#[cfg(feature = "gzip")]
+type Rest<R> = io::BufReader<io::Chain<io::Cursor<Vec<u8>>, R>>;
+
+#[cfg(feature = "gzip")]
+enum Input<R: Read> {
+    Bgzf(R),
+    /// The members from the first one that is not a BGZF block, starting with the bytes of it
+    /// that were already read.
+    Gzip(Box<GzipDecoder<Rest<R>>>),
+    /// Padding or other trailing data after the last member, which is left unread.
+    End(R),
+}
+
+/// A member read from the input.
+#[cfg(feature = "gzip")]
+enum Member {
+    Bgzf(Vec<u8>),
+    /// The start of a member that is not a BGZF block.
+    Other(Vec<u8>),
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> BgzfDecoder<R> {
+    pub fn new(reader: R, threads: usize) -> Self {
+        Self {
+            input: Some(Input::Bgzf(reader)),
+            threads: threads.max(1),
+            decoded: vec![],
+            position: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        match self.input.as_ref().unwrap() {
+            Input::Bgzf(reader) => reader,
+            Input::Gzip(decoder) => decoder.get_ref().get_ref().get_ref().1,
+            Input::End(reader) => reader,
+        }
+    }
+
+    /// Reads the next member, or returns `None` at the end of the input.
+    fn read_member(reader: &mut R) -> io::Result<Option<Member>> {
+        let mut member = Vec::with_capacity(BGZF_HEADER_LEN);
+        reader
+            .take(BGZF_HEADER_LEN as u64)
+            .read_to_end(&mut member)?;
+        if member.is_empty() {
+            return Ok(None);
+        }
+        if member.len() < BGZF_HEADER_LEN || !is_bgzf(&member) {
+            return Ok(Some(Member::Other(member)));
+        }
+        let size = u16::from_le_bytes([member[16], member[17]]) as usize + 1;
+        // The member ends with a CRC32 and the uncompressed size.
+        if size < BGZF_HEADER_LEN + 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "BGZF block size is too small",
+            ));
+        }
+        member.reserve_exact(size - BGZF_HEADER_LEN);
+        reader
+            .take((size - BGZF_HEADER_LEN) as u64)
+            .read_to_end(&mut member)?;
+        if member.len() < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "BGZF block is truncated",
+            ));
+        }
+        Ok(Some(Member::Bgzf(member)))
+    }
+
+    /// Reads and inflates the next batch of members, returning false at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        let Some(Input::Bgzf(reader)) = &mut self.input else {
+            return Ok(false);
+        };
+        let mut members = vec![];
+        let mut other = None;
+        while members.len() < self.threads * MEMBERS_PER_THREAD {
+            match Self::read_member(reader)? {
+                Some(Member::Bgzf(member)) => members.push(member),
+                Some(Member::Other(start)) => {
+                    other = Some(start);
+                    break;
+                }
+                None => break,
+            }
+        }
+        if let Some(start) = other {
+            let Some(Input::Bgzf(reader)) = self.input.take() else {
+                unreachable!()
+            };
+            self.input = Some(if is_gzip(&start) {
+                let reader = io::BufReader::new(io::Cursor::new(start).chain(reader));
+                Input::Gzip(Box::new(GzipDecoder::new(reader)))
+            } else {
+                Input::End(reader)
+            });
+        } else if members.is_empty() {
+            return Ok(false);
+        }
+        // Each thread inflates a run of consecutive members, so joining the runs in order gives
+        // the output in order.
+        let run = members.len().div_ceil(self.threads).max(1);
+        let runs = std::thread::scope(|scope| {
+            let handles: Vec<_> = members
+                .chunks(run)
+                .map(|members| scope.spawn(|| inflate(members)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+        self.decoded = runs.concat();
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> Debug for BgzfDecoder<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BgzfDecoder")
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn inflate(members: &[Vec<u8>]) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    for member in members {
+        flate2::read::GzDecoder::new(member.as_slice()).read_to_end(&mut out)?;
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> Read for BgzfDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A batch can decode to nothing, such as the empty member that ends a BGZF file.
+        while self.position == self.decoded.len() {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            if let Some(Input::Gzip(decoder)) = &mut self.input {
+                return decoder.read(buf);
+            }
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let n = (&self.decoded[self.position..]).read(buf)?;
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Memory use above which liblzma decodes on fewer threads.
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+const XZ_MEMLIMIT_THREADING: u64 = 1024 * 1024 * 1024;
+
+/// Decodes concatenated xz streams with liblzma's multi-threaded decoder, which decodes the
+/// blocks of a stream in parallel when their sizes are recorded in the block headers, as `xz -T`
+/// writes them.
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+pub struct ThreadedXzDecoder<R: BufRead> {
+    // `None` once every stream has been read.
+    inner: Option<liblzma::bufread::XzDecoder<R>>,
+    threads: u32,
+}
+
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+impl<R: BufRead> ThreadedXzDecoder<R> {
+    pub fn new(reader: R, threads: usize) -> io::Result<Self> {
+        let threads = u32::try_from(threads).unwrap_or(u32::MAX).max(1);
+        let inner = Self::decoder(reader, threads)?;
+        Ok(Self {
+            inner: Some(inner),
+            threads,
+        })
+    }
+
+    fn decoder(reader: R, threads: u32) -> io::Result<liblzma::bufread::XzDecoder<R>> {
+        // The multi-threaded decoder can't be told to read concatenated streams, so each stream
+        // gets a new one.
+        let stream = liblzma::stream::MtStreamBuilder::new()
+            .threads(threads)
+            .memlimit_threading(XZ_MEMLIMIT_THREADING)
+            .memlimit_stop(u64::MAX)
+            .decoder()?;
+        Ok(liblzma::bufread::XzDecoder::new_stream(reader, stream))
+    }
+}
+
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+impl<R: BufRead> Debug for ThreadedXzDecoder<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadedXzDecoder")
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+#[cfg(all(feature = "xz", feature = "c-backend"))]
+impl<R: BufRead> Read for ThreadedXzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(decoder) = &mut self.inner else {
+                return Ok(0);
+            };
+            let n = decoder.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let mut reader = self.inner.take().unwrap().into_inner();
+            // Streams can be followed by padding made of zeros.
+            loop {
+                let zeros = reader.fill_buf()?.iter().take_while(|b| **b == 0).count();
+                if zeros == 0 {
+                    break;
+                }
+                reader.consume(zeros);
+            }
+            if reader.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            self.inner = Some(Self::decoder(reader, self.threads)?);
+        }
+    }
+}
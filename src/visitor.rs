@@ -0,0 +1,333 @@
+//! Receiving the files and errors found while reading an input.
+
+use crate::checksum::{Checksum, ChecksumReader, Verification};
+use crate::error::Error;
+#[cfg(feature = "hash")]
+use crate::hash::{FileHashes, HashingReader};
+#[cfg(feature = "index")]
+use crate::index::IndexBuilder;
+use crate::parallel::Dispatcher;
+use crate::spool::{Spool, SpoolOptions};
+use crate::{EntryOffsets, FileItem, ReadOptions, SizeHint, error};
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Receives the files found while reading an input, and decides what happens when part of it
+/// cannot be read.
+///
+/// Closures taking a [`FileItem`] implement this trait, and stop at the first error.
+pub trait Visitor {
+    /// Called with each file that is not an archive or compressed.
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> io::Result<()>;
+
+    /// Called when an entry cannot be read. Returning `Ok(())` skips the entry and continues
+    /// with the next one, while returning an error stops reading and returns it.
+    ///
+    /// When the header of an entry is corrupt the archive holding it cannot be read any further,
+    /// so reading continues after that archive instead. The messages of a mailbox and the
+    /// records of a WARC file are the exception, as the next one can still be found after one
+    /// that cannot be parsed. Errors returned by [`visit_file`](Visitor::visit_file) always stop
+    /// reading.
+    fn visit_error(&mut self, error: Error) -> io::Result<()> {
+        Err(error.into())
+    }
+
+    /// Called before the entries of an archive at `path` are read, with the name of its format
+    /// such as `tar`. Compression layers are not containers, so a `.tar.gz` is entered once as a
+    /// `tar` archive.
+    fn enter_container(&mut self, path: &Path, format: &str) -> io::Result<()> {
+        let _ = (path, format);
+        Ok(())
+    }
+
+    /// Called after every entry of the archive at `path` has been read, with totals for
+    /// everything inside it. Not called if reading stops part way through the archive.
+    fn leave_container(&mut self, path: &Path, stats: &ContainerStats) -> io::Result<()> {
+        let _ = (path, stats);
+        Ok(())
+    }
+
+    /// Called when an entry with a stored [`Checksum`] has been read to the end and checked
+    /// against it, whether or not it matched.
+    ///
+    /// Entries that were not read to the end are not checked, unless
+    /// [`ReadOptions::verify_checksums`] is set. Only zip and cpio entries have stored checksums.
+    /// Compressed streams such as gzip and xz check their own checksums as they are read, and
+    /// fail with a corrupt error if they do not match, without calling this.
+    fn verified(&mut self, verification: &Verification) -> io::Result<()> {
+        let _ = verification;
+        Ok(())
+    }
+
+    /// Called with the hashes of each file after [`visit_file`](Visitor::visit_file) returns,
+    /// when hashing is enabled with [`ReadOptions::hash`]. Any part of the file that was not
+    /// read by `visit_file` is read first, so the hashes always cover the whole file.
+    #[cfg(feature = "hash")]
+    fn hashed(&mut self, hashes: &FileHashes) -> io::Result<()> {
+        let _ = hashes;
+        Ok(())
+    }
+}
+
+/// Totals for the contents of an archive, passed to [`Visitor::leave_container`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContainerStats {
+    /// The number of entries directly inside the archive, including directories and nested
+    /// archives.
+    pub entries: u64,
+    /// The number of files yielded from the archive, including those inside nested archives.
+    pub files: u64,
+    /// The number of bytes read from those files by the visitor.
+    pub bytes: u64,
+    /// The number of entries inside the archive, or inside nested archives, that were skipped
+    /// because they could not be read.
+    pub skipped: u64,
+}
+
+impl<F> Visitor for F
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+{
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> io::Result<()> {
+        self(item)
+    }
+}
+
+/// What happened to the entries read by [`ReadOptions::recursive_read_lenient`].
+#[derive(Debug, Default)]
+pub struct ReadSummary {
+    /// The errors of the entries that were skipped, in the order they happened.
+    pub skipped: Vec<Error>,
+    /// The entries that were checked against a stored checksum, and the result.
+    pub verified: Vec<Verification>,
+}
+
+/// Passes files to a callback, and records errors instead of stopping at them.
+pub(crate) struct Lenient<'a, F, E> {
+    pub(crate) callback: &'a mut F,
+    pub(crate) on_error: E,
+    pub(crate) summary: ReadSummary,
+}
+
+impl<F, E> Visitor for Lenient<'_, F, E>
+where
+    F: FnMut(FileItem<&mut dyn Read>) -> io::Result<()>,
+    E: FnMut(&Error),
+{
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> io::Result<()> {
+        (self.callback)(item)
+    }
+
+    fn visit_error(&mut self, error: Error) -> io::Result<()> {
+        (self.on_error)(&error);
+        self.summary.skipped.push(error);
+        Ok(())
+    }
+
+    fn verified(&mut self, verification: &Verification) -> io::Result<()> {
+        self.summary.verified.push(verification.clone());
+        Ok(())
+    }
+}
+
+/// Spools the file at a single path, for [`ReadOptions::open_path`].
+pub(crate) struct Find<'a> {
+    pub(crate) target: &'a Path,
+    pub(crate) spool: SpoolOptions,
+    pub(crate) found: Option<Spool>,
+}
+
+impl Visitor for Find<'_> {
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> io::Result<()> {
+        if item.path == self.target && !item.kind.is_directory() {
+            self.found = Some(Spool::new(item.reader, &self.spool)?);
+        }
+        Ok(())
+    }
+}
+
+/// The state of a single read of an input.
+pub(crate) struct Walk<'a, V: Visitor> {
+    pub(crate) options: &'a ReadOptions,
+    pub(crate) visitor: &'a mut V,
+    /// The formats enclosing the current position, outermost first.
+    pub(crate) layers: Vec<String>,
+    /// The totals of the archives enclosing the current position, outermost first.
+    containers: Vec<ContainerStats>,
+    /// The stored checksum of the entry being read, until it is yielded or decompressed.
+    pub(crate) entry_checksum: Option<Checksum>,
+    /// The offsets of the entry being read, until it is yielded.
+    pub(crate) entry_offsets: Option<EntryOffsets>,
+    /// The only path to read, when looking for a single file. Entries that are not the path or
+    /// one of its parents are skipped.
+    pub(crate) target: Option<PathBuf>,
+    /// Hands the entries of the outermost archive to worker threads, when reading in parallel.
+    pub(crate) parallel: Option<&'a Dispatcher>,
+    /// Records the entries of the input's tar archive, when building an index.
+    #[cfg(feature = "index")]
+    pub(crate) index: Option<IndexBuilder>,
+}
+
+impl<'a, V: Visitor> Walk<'a, V> {
+    pub(crate) fn new(options: &'a ReadOptions, visitor: &'a mut V) -> Self {
+        Self {
+            options,
+            visitor,
+            layers: vec![],
+            containers: vec![],
+            entry_checksum: None,
+            entry_offsets: None,
+            target: None,
+            parallel: None,
+            #[cfg(feature = "index")]
+            index: None,
+        }
+    }
+
+    /// Adds the current position to an error from reading `path`.
+    pub(crate) fn context(&self, err: io::Error, path: &Path) -> io::Error {
+        error::with_context(err, path, &self.layers)
+    }
+
+    /// Runs `f` inside a layer of `format`, if there is one.
+    pub(crate) fn in_layer(
+        &mut self,
+        format: Option<String>,
+        path: &Path,
+        f: impl FnOnce(&mut Self) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let Some(format) = format else {
+            return f(self);
+        };
+        self.layers.push(format);
+        let result = f(self).map_err(|e| self.context(e, path));
+        self.layers.pop();
+        result
+    }
+
+    /// Passes an error from reading `path` to the visitor, returning an error if reading should
+    /// stop.
+    pub(crate) fn report(&mut self, err: io::Error, path: &Path) -> io::Result<()> {
+        if error::is_fatal(&err) {
+            return Err(err);
+        }
+        let err = Error::from_io(self.context(err, path), path);
+        self.visitor
+            .visit_error(err)
+            .map_err(|e| error::fatal(e, path, &self.layers))?;
+        if let Some(stats) = self.containers.last_mut() {
+            stats.skipped += 1;
+        }
+        Ok(())
+    }
+
+    /// Starts an archive at `path`, whose format is the innermost layer.
+    pub(crate) fn enter_container(&mut self, path: &Path) -> io::Result<()> {
+        let format = self.layers.last().map(String::as_str).unwrap_or_default();
+        self.visitor
+            .enter_container(path, format)
+            .map_err(|e| error::fatal(e, path, &self.layers))?;
+        self.containers.push(ContainerStats::default());
+        Ok(())
+    }
+
+    /// Finishes the innermost archive, adding its totals to the archive enclosing it.
+    pub(crate) fn leave_container(&mut self, path: &Path) -> io::Result<()> {
+        let stats = self.containers.pop().unwrap_or_default();
+        if let Some(parent) = self.containers.last_mut() {
+            parent.files += stats.files;
+            parent.bytes += stats.bytes;
+            parent.skipped += stats.skipped;
+        }
+        self.visitor
+            .leave_container(path, &stats)
+            .map_err(|e| error::fatal(e, path, &self.layers))
+    }
+
+    /// Checks an entry that has been read against its stored checksum, reading the rest of it
+    /// first if checksums are verified strictly.
+    pub(crate) fn verify(&mut self, path: &Path, mut reader: ChecksumReader) -> io::Result<()> {
+        if reader.expected().is_none() {
+            return Ok(());
+        }
+        if self.options.verify_checksums {
+            io::copy(&mut reader, &mut io::sink())?;
+        }
+        let Some(verification) = reader.finish(path.to_path_buf()) else {
+            return Ok(());
+        };
+        self.visitor
+            .verified(&verification)
+            .map_err(|e| error::fatal(e, path, &self.layers))?;
+        if self.options.verify_checksums && !verification.is_match() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checksum mismatch: expected {}, got {}",
+                    verification.expected, verification.actual
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Finishes hashing a file that has been yielded, and passes the hashes to the visitor.
+    #[cfg(feature = "hash")]
+    pub(crate) fn hash(&mut self, path: &Path, reader: HashingReader) -> io::Result<()> {
+        if !reader.is_enabled() {
+            return Ok(());
+        }
+        let hashes = reader
+            .finish(path.to_path_buf())
+            .map_err(|e| self.context(e, path))?;
+        self.visitor
+            .hashed(&hashes)
+            .map_err(|e| error::fatal(e, path, &self.layers))
+    }
+
+    /// Returns false if the entry at `path` can be skipped without reading it, because it cannot
+    /// hold the file being looked for or is filtered out.
+    pub(crate) fn wants(&self, path: &Path) -> bool {
+        self.target.as_ref().is_none_or(|t| t.starts_with(path)) && self.options.filter.allows(path)
+    }
+
+    /// Adds an entry of the innermost archive to the index being built, if it is the input's
+    /// tar archive.
+    #[cfg(feature = "index")]
+    pub(crate) fn index_entry<T: Read>(&mut self, item: &FileItem<T>) {
+        if let Some(index) = &mut self.index {
+            index.record(&self.layers, self.containers.len(), item);
+        }
+    }
+
+    /// Returns the worker threads to hand an entry with `size_hint` to, if it is an entry of the
+    /// outermost archive and fits in their memory budget.
+    pub(crate) fn dispatcher(&self, size_hint: SizeHint) -> Option<&'a Dispatcher> {
+        self.parallel
+            .filter(|d| self.containers.len() == 1 && d.accepts(size_hint))
+    }
+
+    pub(crate) fn count_entry(&mut self) {
+        if let Some(stats) = self.containers.last_mut() {
+            stats.entries += 1;
+        }
+    }
+
+    /// Records a file yielded to the visitor, and the bytes it read from it.
+    pub(crate) fn count_file(&mut self, bytes: u64) {
+        if let Some(stats) = self.containers.last_mut() {
+            stats.files += 1;
+            stats.bytes += bytes;
+        }
+    }
+
+    /// Reports an error that reached the top of the input, and converts what is left into the
+    /// public error type.
+    pub(crate) fn finish(&mut self, result: io::Result<()>, path: &Path) -> Result<(), Error> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => self.report(e, path).map_err(|e| Error::from_io(e, path)),
+        }
+    }
+}
@@ -1,7 +1,16 @@
 mod utils;
 
 use crate::utils::{gzip_data, xz_data, zstd_data};
-use anyreader::{iterate_archive, recursive_read, SizeHint};
+use anyreader::{
+    Checksum, CodecFormat, Container, ContainerFormat, ContainerStats, DetectedFormat,
+    DetectedLayer, DynContainer, EntryOffsets, ErrorCategory, FileHashes, FileItem, FileKind,
+    Format, FormatRegistry, HashAlgorithm, Index, Items, Metadata, OciView, ParallelOptions,
+    ReadOptions, SizeHint, SpoolOptions, Visitor, detect, detect_layers, iterate_archive,
+    iterate_archive_seekable, open_entry, open_path, read_oci_image, recursive_read,
+    recursive_read_seekable,
+};
+use std::io::{Cursor, Read, Write};
+use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
 use tracing_test::traced_test;
 
@@ -53,6 +62,180 @@ fn test_zip() {
     );
 }
 
+#[traced_test]
+#[test]
+fn test_zip_truncated() {
+    let archive = utils::zip_archive([("file-1", DATA), ("file-2", DATA)]);
+    // Cut the archive in the middle of the second local header.
+    let second = archive
+        .windows(4)
+        .enumerate()
+        .filter(|(_, w)| *w == b"PK\x03\x04")
+        .nth(1)
+        .unwrap()
+        .0;
+    let mut paths = vec![];
+    let mut errors = vec![];
+    ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            &archive[..second + 10],
+            &mut |item| {
+                paths.push(item.path);
+                Ok(())
+            },
+            |e| errors.push((e.path().to_path_buf(), e.category())),
+        )
+        .unwrap();
+    assert_eq!(paths, [PathBuf::from("root/file-1")]);
+    assert_eq!(errors, [(PathBuf::from("root"), ErrorCategory::Truncated)]);
+}
+
+fn process_seekable(data: &[u8]) -> Vec<(PathBuf, Vec<u8>, SizeHint)> {
+    let mut result = Vec::new();
+    recursive_read_seekable(Path::new("root"), Cursor::new(data), &mut |item| {
+        let mut buf = Vec::new();
+        item.reader.read_to_end(&mut buf).unwrap();
+        result.push((item.path, buf, item.size_hint));
+        Ok(())
+    })
+    .unwrap();
+    result
+}
+
+#[traced_test]
+#[test]
+fn test_zip_seekable() {
+    let archive: Vec<u8> = utils::zip_archive([
+        ("file-1", gzip_data(DATA)),
+        ("inner", utils::zip_archive([("file-2", DATA.to_vec())])),
+    ]);
+    let x = process_seekable(&archive);
+    assert_eq!(
+        x,
+        vec![
+            (
+                "root/file-1".into(),
+                DATA.to_vec(),
+                SizeHint::CompressedSize(gzip_data(DATA).len() as u64)
+            ),
+            (
+                "root/inner/file-2".into(),
+                DATA.to_vec(),
+                SizeHint::Exact(DATA.len() as u64)
+            ),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_zip_seekable_data_descriptors() {
+    // Stored entries with data descriptors can't be read from the local headers alone.
+    let archive = utils::zip_stream_archive([("file-1", DATA), ("file-2", b"second".as_slice())]);
+    let x = process_seekable(&archive);
+    assert_eq!(
+        x,
+        vec![
+            ("root/file-1".into(), DATA.to_vec(), SizeHint::Exact(11)),
+            ("root/file-2".into(), b"second".to_vec(), SizeHint::Exact(6)),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_zip_seekable_self_extracting() {
+    let mut archive = b"MZ\x90\x00 self-extractor stub".to_vec();
+    archive.extend(utils::zip_archive([("file-1", DATA)]));
+    let x = process_seekable(&archive);
+    assert_eq!(
+        x,
+        vec![("root/file-1".into(), DATA.to_vec(), SizeHint::Exact(11))]
+    );
+
+    let mut entries = vec![];
+    iterate_archive_seekable(Cursor::new(&archive), |item| {
+        entries.push(item.path);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(entries, vec![PathBuf::from("file-1")]);
+}
+
+#[traced_test]
+#[test]
+fn test_seekable_not_zip() {
+    // Inputs that look like executables but have no central directory are read as usual.
+    let x = process_seekable(b"MZ not really an executable");
+    assert_eq!(
+        x,
+        vec![(
+            "root".into(),
+            b"MZ not really an executable".to_vec(),
+            SizeHint::Unknown
+        )]
+    );
+    let archive = utils::tar_archive([("file-1", gzip_data(DATA))]);
+    let x = process_seekable(&gzip_data(archive));
+    assert_eq!(x.len(), 1);
+    assert_eq!(x[0].0, PathBuf::from("root/file-1"));
+    assert_eq!(x[0].1, DATA.to_vec());
+}
+
+#[traced_test]
+#[test]
+fn test_spooled_nested_zip() {
+    // Entries with data descriptors inside a nested zip are only readable with random access.
+    let inner = utils::zip_stream_archive([("file-1", DATA), ("file-2", b"second".as_slice())]);
+    let archive = gzip_data(utils::tar_archive([("inner.zip", inner)]));
+
+    // Without spooling, they are reported as unsupported.
+    let err = recursive_read(Path::new("root"), archive.as_slice(), &mut |_| Ok(())).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Unsupported);
+    assert_eq!(err.path(), Path::new("root/inner.zip"));
+
+    for options in [SpoolOptions::new(), SpoolOptions::new().memory_limit(0)] {
+        let mut result = Vec::new();
+        ReadOptions::new()
+            .spool(options)
+            .recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+                let mut buf = Vec::new();
+                item.reader.read_to_end(&mut buf).unwrap();
+                result.push((item.path, buf, item.size_hint));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (
+                    "root/inner.zip/file-1".into(),
+                    DATA.to_vec(),
+                    SizeHint::Exact(11)
+                ),
+                (
+                    "root/inner.zip/file-2".into(),
+                    b"second".to_vec(),
+                    SizeHint::Exact(6)
+                ),
+            ]
+        );
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_spool_max_size() {
+    let inner = utils::zip_archive([("file-1", DATA)]);
+    let archive = utils::tar_archive([("inner.zip", inner)]);
+    let err = ReadOptions::new()
+        .spool(SpoolOptions::new().max_size(10))
+        .recursive_read(Path::new("root"), archive.as_slice(), &mut |_| Ok(()))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+}
+
 #[traced_test]
 #[test]
 fn test_mixed() {
@@ -254,6 +437,62 @@ fn test_size_hint_compressed_entry() {
     assert_eq!(size_hints[0].any_known(), Some(compressed_len));
 }
 
+#[test]
+fn test_size_hint_zstd_header() {
+    // A single raw block in a frame whose header records the content size.
+    let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x20, DATA.len() as u8];
+    frame.extend_from_slice(&((DATA.len() as u32) << 3 | 1).to_le_bytes()[..3]);
+    frame.extend_from_slice(DATA);
+    let archive = utils::tar_archive([("file.zst", frame)]);
+    let x = process(&archive);
+    assert_eq!(x, vec![("root/file.zst".into(), DATA.to_vec())]);
+
+    let mut size_hints = Vec::new();
+    recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+        size_hints.push(item.size_hint);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(size_hints, [SizeHint::Estimated(DATA.len() as u64)]);
+    assert_eq!(size_hints[0].estimated(), Some(DATA.len() as u64));
+}
+
+#[test]
+fn test_size_hint_seekable_trailer() {
+    let data = DATA.repeat(100);
+    let x = process_seekable(&xz_data(&data));
+    assert_eq!(
+        x,
+        vec![("root".into(), data.clone(), SizeHint::Exact(1100))]
+    );
+    let x = process_seekable(&gzip_data(&data));
+    assert_eq!(
+        x,
+        vec![("root".into(), data.clone(), SizeHint::AtLeast(1100))]
+    );
+
+    // Concatenated xz streams are not described by the last index.
+    let mut xz = xz_data(DATA);
+    xz.extend(xz_data(DATA));
+    let x = process_seekable(&xz);
+    assert_eq!(x, vec![("root".into(), DATA.repeat(2), SizeHint::Unknown)]);
+    // A gzip trailer only covers the last member, so its size is a lower bound.
+    let mut gzip = gzip_data(DATA);
+    gzip.extend(gzip_data(DATA));
+    let x = process_seekable(&gzip);
+    let hint = SizeHint::AtLeast(DATA.len() as u64);
+    assert_eq!(x, vec![("root".into(), DATA.repeat(2), hint)]);
+
+    // Without seeking, the trailers cannot be read.
+    let mut size_hints = Vec::new();
+    recursive_read(Path::new("root"), xz_data(&data).as_slice(), &mut |item| {
+        size_hints.push(item.size_hint);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(size_hints, [SizeHint::Unknown]);
+}
+
 #[traced_test]
 #[test]
 fn test_size_hint_raw_stream() {
@@ -272,3 +511,1510 @@ fn test_size_hint_raw_stream() {
     assert_eq!(size_hints[0].compressed_size(), None);
     assert_eq!(size_hints[0].any_known(), None);
 }
+
+#[traced_test]
+#[test]
+fn test_cpio() {
+    let archive = utils::cpio_archive([
+        ("./file-1", xz_data(gzip_data(DATA))),
+        ("inner.tar", utils::tar_archive([("file-2", DATA.to_vec())])),
+    ]);
+    let x = process(archive.as_slice());
+    assert_eq!(
+        x,
+        vec![
+            ("root/file-1".into(), DATA.to_vec()),
+            ("root/inner.tar/file-2".into(), DATA.to_vec()),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_xar_pkg() {
+    let package = utils::xar_archive([
+        ("Distribution", DATA.to_vec()),
+        (
+            "first.pkg/Payload",
+            gzip_data(utils::cpio_archive([("./usr/bin/tool", DATA.to_vec())])),
+        ),
+        (
+            "second.pkg/Payload",
+            utils::pbzx_data(utils::cpio_archive([("./etc/config", DATA.repeat(100))])),
+        ),
+    ]);
+
+    let mut files = Vec::new();
+    recursive_read(Path::new("root.pkg"), package.as_slice(), &mut |item| {
+        if item.kind.is_file() {
+            let mut buf = Vec::new();
+            item.reader.read_to_end(&mut buf)?;
+            files.push((item.path, buf));
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        files,
+        vec![
+            ("root.pkg/Distribution".into(), DATA.to_vec()),
+            (
+                "root.pkg/first.pkg/Payload/usr/bin/tool".into(),
+                DATA.to_vec()
+            ),
+            (
+                "root.pkg/second.pkg/Payload/etc/config".into(),
+                DATA.repeat(100)
+            ),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_iterate_archive_xar() {
+    let package = utils::xar_archive([
+        ("Distribution", DATA.to_vec()),
+        ("first.pkg/Payload", gzip_data(DATA)),
+    ]);
+
+    let mut entries = Vec::new();
+    iterate_archive(package.as_slice(), |item| {
+        let mut buf = Vec::new();
+        item.reader.read_to_end(&mut buf)?;
+        entries.push((item.path.clone(), item.kind.is_directory(), buf));
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        entries,
+        vec![
+            (PathBuf::from("first.pkg"), true, vec![]),
+            (PathBuf::from("Distribution"), false, DATA.to_vec()),
+            // The xar encoding is removed, but the payload itself is left compressed.
+            (PathBuf::from("first.pkg/Payload"), false, gzip_data(DATA)),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_xar_encodings() {
+    // `application/x-lzma` is legacy `.lzma` data rather than xz.
+    for encoding in [
+        "application/x-lzma",
+        "application/x-xz",
+        "application/octet-stream",
+    ] {
+        let package = utils::xar_archive_encoded(encoding, [("Distribution", DATA.repeat(100))]);
+        let mut entries = Vec::new();
+        iterate_archive(package.as_slice(), |item| {
+            entries.push((item.path, utils::read_vec(item.reader)));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![(PathBuf::from("Distribution"), DATA.repeat(100))],
+            "{encoding}"
+        );
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_cfb() {
+    let archive = utils::cfb_archive([
+        ("Binary.cab", gzip_data(DATA)),
+        (
+            "__attach_version1.0_#00000000/__substg1.0_37010102",
+            utils::zip_archive([("file-2", DATA.to_vec())]),
+        ),
+    ]);
+
+    let mut entries = Vec::new();
+    recursive_read(Path::new("root.msi"), archive.as_slice(), &mut |item| {
+        let mut buf = Vec::new();
+        item.reader.read_to_end(&mut buf)?;
+        entries.push((item.path, item.kind.is_directory(), buf));
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        entries,
+        vec![
+            ("root.msi/Binary.cab".into(), false, DATA.to_vec()),
+            (
+                "root.msi/__attach_version1.0_#00000000".into(),
+                true,
+                vec![]
+            ),
+            (
+                "root.msi/__attach_version1.0_#00000000/__substg1.0_37010102/file-2".into(),
+                false,
+                DATA.to_vec()
+            ),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_email() {
+    let message = utils::email_message(
+        "hello world",
+        [
+            ("files.zip", utils::zip_archive([("file-2", DATA.to_vec())])),
+            ("../escape.gz", gzip_data(DATA)),
+        ],
+    );
+    let x = process(message.as_slice());
+    assert_eq!(
+        x,
+        vec![
+            ("root/part-1.txt".into(), DATA.to_vec()),
+            ("root/files.zip/file-2".into(), DATA.to_vec()),
+            ("root/escape.gz".into(), DATA.to_vec()),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_mbox() {
+    let mut mailbox = b"From sender@example.com Mon Jan  1 00:00:00 2024\n".to_vec();
+    mailbox.extend(utils::email_message(
+        "first",
+        [("a.tar", utils::tar_archive([("file-1", DATA)]))],
+    ));
+    mailbox.extend(b"\nFrom sender@example.com Tue Jan  2 00:00:00 2024\n");
+    mailbox.extend(b"From: sender@example.com\nSubject: plain\n\nsecond\n");
+    let x = process(mailbox.as_slice());
+    assert_eq!(
+        x,
+        vec![
+            ("root/message-1/part-1.txt".into(), b"first".to_vec()),
+            ("root/message-1/a.tar/file-1".into(), DATA.to_vec()),
+            ("root/message-2/part-0.txt".into(), b"second\n".to_vec()),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_mbox_unparseable_message() {
+    // The second message is empty, so there is nothing to parse.
+    let mut mailbox = b"From sender@example.com Mon Jan  1 00:00:00 2024\n".to_vec();
+    mailbox.extend(b"From: sender@example.com\nSubject: first\n\nfirst\n\n");
+    mailbox.extend(b"From sender@example.com Tue Jan  2 00:00:00 2024\n");
+    mailbox.extend(b"From sender@example.com Wed Jan  3 00:00:00 2024\n");
+    mailbox.extend(b"From: sender@example.com\nSubject: third\n\nthird\n");
+    let mut paths = vec![];
+    let summary = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            mailbox.as_slice(),
+            &mut |item| {
+                paths.push(item.path);
+                Ok(())
+            },
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(summary.skipped.len(), 1);
+    assert_eq!(summary.skipped[0].category(), ErrorCategory::Corrupt);
+    // Messages are separated by their `From ` lines, so the ones after it are still read.
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("root/message-1/part-0.txt"),
+            PathBuf::from("root/message-3/part-0.txt"),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_mbox_message_too_large() {
+    let mut mailbox = b"From sender@example.com Mon Jan  1 00:00:00 2024\n".to_vec();
+    mailbox.extend(b"From: sender@example.com\nSubject: first\n\n");
+    mailbox.extend(b"a long line\n".repeat(100));
+    mailbox.extend(b"From sender@example.com Tue Jan  2 00:00:00 2024\n");
+    mailbox.extend(b"From: sender@example.com\nSubject: second\n\n>From the sender\n");
+    let mut entries = vec![];
+    let summary = ReadOptions::new()
+        .spool(SpoolOptions::new().max_size(1000))
+        .recursive_read_lenient(
+            Path::new("root"),
+            mailbox.as_slice(),
+            &mut |item| {
+                entries.push((item.path, utils::read_vec(item.reader)));
+                Ok(())
+            },
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(summary.skipped.len(), 1);
+    assert_eq!(summary.skipped[0].category(), ErrorCategory::LimitExceeded);
+    assert_eq!(
+        entries,
+        [(
+            PathBuf::from("root/message-2/part-0.txt"),
+            b"From the sender\n".to_vec()
+        )]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_warc() {
+    let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: application/x-tar\r\n\r\n".to_vec();
+    response.extend(utils::tar_archive([("file-1", DATA)]));
+    let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6; ext=1\r\n world\r\n0\r\n\r\n";
+
+    let mut archive = utils::warc_record(
+        &[
+            ("WARC-Type", "warcinfo"),
+            ("Content-Type", "application/warc-fields"),
+        ],
+        "software: test\r\n",
+    );
+    archive.extend(utils::warc_record(
+        &[
+            ("WARC-Type", "response"),
+            ("WARC-Target-URI", "http://example.com/files.tar"),
+            ("Content-Type", "application/http; msgtype=response"),
+        ],
+        response,
+    ));
+    archive.extend(utils::warc_record(
+        &[
+            ("WARC-Type", "response"),
+            ("WARC-Target-URI", "<http://example.com/chunked>"),
+            ("Content-Type", "application/http;msgtype=response"),
+        ],
+        chunked,
+    ));
+    archive.extend(utils::warc_record(
+        &[
+            ("WARC-Type", "resource"),
+            ("WARC-Target-URI", "file:///data.gz"),
+            ("Content-Type", "application/gzip"),
+        ],
+        gzip_data(DATA),
+    ));
+
+    let mut entries = Vec::new();
+    recursive_read(
+        Path::new("crawl.warc.gz"),
+        archive.as_slice(),
+        &mut |item| {
+            let mut buf = Vec::new();
+            item.reader.read_to_end(&mut buf)?;
+            let warc_type = item.metadata.get("warc-type").map(str::to_string);
+            entries.push((item.path, warc_type, buf));
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        entries,
+        vec![
+            (
+                "crawl.warc.gz/record-1".into(),
+                Some("warcinfo".to_string()),
+                b"software: test\r\n".to_vec()
+            ),
+            (
+                "crawl.warc.gz/http://example.com/files.tar/file-1".into(),
+                // Metadata belongs to the record, not to the entries of a nested archive.
+                None,
+                DATA.to_vec()
+            ),
+            (
+                "crawl.warc.gz/http://example.com/chunked".into(),
+                Some("response".to_string()),
+                DATA.to_vec()
+            ),
+            (
+                "crawl.warc.gz/file:///data.gz".into(),
+                Some("resource".to_string()),
+                DATA.to_vec()
+            ),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_warc_latin1_headers() {
+    // A Latin-1 byte in an HTTP header, as some servers send in cookies and redirects.
+    let response = b"HTTP/1.1 302 Found\r\nLocation: /caf\xe9\r\n\r\nmoved";
+    let mut archive = utils::warc_record(
+        &[
+            ("WARC-Target-URI", "http://example.com/redirect"),
+            ("Content-Type", "application/http; msgtype=response"),
+        ],
+        response,
+    );
+    archive.extend(utils::warc_record(
+        &[("WARC-Target-URI", "http://example.com/next")],
+        DATA,
+    ));
+
+    let x = process(&archive);
+    assert_eq!(
+        x,
+        vec![
+            ("root/http://example.com/redirect".into(), b"moved".to_vec()),
+            ("root/http://example.com/next".into(), DATA.to_vec()),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_warc_invalid_http_headers() {
+    // A header line without a colon, after the first header.
+    let response = b"HTTP/1.1 200 OK\r\nServer: test\r\nbroken\r\n\r\nbody";
+    let mut archive = utils::warc_record(
+        &[
+            ("WARC-Target-URI", "http://example.com/broken"),
+            ("Content-Type", "application/http; msgtype=response"),
+        ],
+        response,
+    );
+    archive.extend(utils::warc_record(
+        &[("WARC-Target-URI", "http://example.com/next")],
+        DATA,
+    ));
+
+    let mut entries = vec![];
+    let summary = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            archive.as_slice(),
+            &mut |item| {
+                entries.push((item.path, utils::read_vec(item.reader)));
+                Ok(())
+            },
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(summary.skipped.len(), 1);
+    assert_eq!(summary.skipped[0].category(), ErrorCategory::Corrupt);
+    // The rest of the broken record is skipped, and the next one is read.
+    assert_eq!(
+        entries,
+        [(PathBuf::from("root/http://example.com/next"), DATA.to_vec())]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_multi_member_gzip() {
+    let mut data = gzip_data(&DATA[..5]);
+    data.extend(gzip_data(&DATA[5..]));
+    let x = process(data.as_slice());
+    assert_eq!(x, vec![("root".into(), DATA.to_vec())]);
+}
+
+fn image_layers() -> Vec<Vec<u8>> {
+    vec![
+        utils::tar_archive([
+            ("etc/passwd", b"old".to_vec()),
+            ("etc/group", b"group".to_vec()),
+            ("app/removed.txt", b"removed".to_vec()),
+            ("opt/data/a", b"a".to_vec()),
+        ]),
+        utils::tar_archive([
+            ("./etc/passwd", b"new".to_vec()),
+            ("app/.wh.removed.txt", vec![]),
+            ("opt/data/.wh..wh..opq", vec![]),
+            ("opt/data/b", b"b".to_vec()),
+            (
+                "lib.tar.gz",
+                gzip_data(utils::tar_archive([("file-1", DATA)])),
+            ),
+        ]),
+    ]
+}
+
+fn read_image(data: &[u8], view: OciView) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut result = Vec::new();
+    read_oci_image(Path::new("root"), data, view, &mut |item| {
+        let mut buf = Vec::new();
+        item.reader.read_to_end(&mut buf)?;
+        result.push((item.path, buf));
+        Ok(())
+    })
+    .unwrap();
+    result
+}
+
+#[traced_test]
+#[test]
+fn test_docker_image_merged() {
+    let image = gzip_data(utils::docker_image("app:latest", &image_layers()));
+    assert_eq!(
+        read_image(&image, OciView::Merged),
+        vec![
+            // The layers are read from the top down.
+            ("root/app:latest/etc/passwd".into(), b"new".to_vec()),
+            ("root/app:latest/opt/data/b".into(), b"b".to_vec()),
+            ("root/app:latest/lib.tar.gz/file-1".into(), DATA.to_vec()),
+            ("root/app:latest/etc/group".into(), b"group".to_vec()),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_oci_image_per_layer() {
+    let image = utils::oci_image("v1", &image_layers());
+    let entries = read_image(&image, OciView::PerLayer);
+    let first_layer = format!("root/v1/{:064x}", 0);
+    let second_layer = format!("root/v1/{:064x}", 1);
+    assert_eq!(entries.len(), 9);
+    assert_eq!(
+        entries[0],
+        (Path::new(&first_layer).join("etc/passwd"), b"old".to_vec())
+    );
+    assert_eq!(
+        entries[4],
+        (Path::new(&second_layer).join("etc/passwd"), b"new".to_vec())
+    );
+    assert_eq!(
+        entries[5],
+        (Path::new(&second_layer).join("app/.wh.removed.txt"), vec![])
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_oci_image_merged() {
+    let image = utils::oci_image("v1", &image_layers());
+    let paths: Vec<_> = read_image(&image, OciView::Merged)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("root/v1/etc/passwd"),
+            PathBuf::from("root/v1/opt/data/b"),
+            PathBuf::from("root/v1/lib.tar.gz/file-1"),
+            PathBuf::from("root/v1/etc/group"),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_oci_image_options() {
+    let image = utils::docker_image("app:latest", &image_layers());
+    // The filter applies to the paths of the files in the image, and the formats to the layers
+    // as well as to the files inside them.
+    let options = ReadOptions::new()
+        .include(["**/etc/passwd", "**/lib.tar.gz"])
+        .formats(FormatRegistry::new().disable(Format::Gzip));
+    let mut entries = Vec::new();
+    options
+        .read_oci_image(
+            Path::new("root"),
+            image.as_slice(),
+            OciView::Merged,
+            &mut |item| {
+                entries.push((item.path, utils::read_vec(item.reader)));
+                Ok(())
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            ("root/app:latest/etc/passwd".into(), b"new".to_vec()),
+            (
+                "root/app:latest/lib.tar.gz".into(),
+                gzip_data(utils::tar_archive([("file-1", DATA)]))
+            ),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_oci_image_replaced_directory() {
+    let layers = vec![
+        utils::tar_archive([("opt/x/a", b"a".to_vec()), ("opt/y/b", b"b".to_vec())]),
+        // A file replaces the `opt/x` directory, and a whiteout below `opt/y` is kept to the
+        // layers underneath it.
+        utils::tar_archive([("opt/x", b"x".to_vec()), ("opt/y/.wh.c", vec![])]),
+        utils::tar_archive([("opt/y/c", b"c".to_vec())]),
+    ];
+    let image = utils::oci_image("v1", &layers);
+    assert_eq!(
+        read_image(&image, OciView::Merged),
+        vec![
+            ("root/v1/opt/y/c".into(), b"c".to_vec()),
+            ("root/v1/opt/x".into(), b"x".to_vec()),
+            ("root/v1/opt/y/b".into(), b"b".to_vec()),
+        ]
+    );
+}
+
+/// A toy archive format: a `MYAR` line, then a `<name> <len>` line before each file's data.
+struct MyArchive;
+
+struct MyArchiveContainer<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> Container for MyArchiveContainer<R> {
+    fn items(&mut self) -> std::io::Result<impl Items> {
+        let mut data = Vec::new();
+        self.reader.read_to_end(&mut data)?;
+        let mut rest = &data[b"MYAR\n".len()..];
+        let mut items = Vec::new();
+        while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+            let header = std::str::from_utf8(&rest[..end]).unwrap();
+            let (name, len) = header.split_once(' ').unwrap();
+            let len: usize = len.parse().unwrap();
+            let contents = rest[end + 1..end + 1 + len].to_vec();
+            rest = &rest[end + 1 + len..];
+            items.push(Ok(FileItem {
+                path: name.into(),
+                reader: Cursor::new(contents),
+                kind: FileKind::File,
+                size_hint: SizeHint::Exact(len as u64),
+                metadata: Metadata::default(),
+                content_type: None,
+                checksum: None,
+                offsets: None,
+            }));
+        }
+        Ok(items.into_iter())
+    }
+}
+
+impl ContainerFormat for MyArchive {
+    fn name(&self) -> &str {
+        "myar"
+    }
+
+    fn detect(&self, buf: &[u8]) -> bool {
+        buf.starts_with(b"MYAR\n")
+    }
+
+    fn open<'a>(&self, reader: Box<dyn Read + 'a>) -> std::io::Result<Box<dyn DynContainer + 'a>> {
+        Ok(Box::new(MyArchiveContainer { reader }))
+    }
+}
+
+/// A toy compression format: `XOR1` followed by the data xor-ed with 0x55.
+struct Xor;
+
+impl CodecFormat for Xor {
+    fn name(&self) -> &str {
+        "xor"
+    }
+
+    fn detect(&self, buf: &[u8]) -> bool {
+        buf.starts_with(b"XOR1")
+    }
+
+    fn decoder<'a>(&self, mut reader: Box<dyn Read + 'a>) -> std::io::Result<Box<dyn Read + 'a>> {
+        reader.read_exact(&mut [0; 4])?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Box::new(Cursor::new(
+            data.into_iter().map(|b| b ^ 0x55).collect::<Vec<_>>(),
+        )))
+    }
+}
+
+fn xor_data(data: &[u8]) -> Vec<u8> {
+    let mut out = b"XOR1".to_vec();
+    out.extend(data.iter().map(|b| b ^ 0x55));
+    out
+}
+
+fn process_with(options: &ReadOptions, data: &[u8]) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut result = Vec::new();
+    options
+        .recursive_read(Path::new("root"), data, &mut |item| {
+            let mut buf = Vec::new();
+            item.reader.read_to_end(&mut buf).unwrap();
+            result.push((item.path, buf));
+            Ok(())
+        })
+        .unwrap();
+    result
+}
+
+#[traced_test]
+#[test]
+fn test_registry_disable_format() {
+    let jar = utils::zip_archive([("Main.class", DATA)]);
+    let archive = utils::tar_archive([("app.jar", jar.clone()), ("file.gz", gzip_data(DATA))]);
+    let options = ReadOptions::new().formats(FormatRegistry::new().disable(Format::Zip));
+    assert_eq!(
+        process_with(&options, &archive),
+        vec![
+            ("root/app.jar".into(), jar),
+            ("root/file.gz".into(), DATA.to_vec()),
+        ]
+    );
+
+    let options = ReadOptions::new().formats(FormatRegistry::empty());
+    assert_eq!(
+        process_with(&options, &archive),
+        vec![("root".into(), archive.clone())]
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_registry_custom_formats() {
+    let mut myar = b"MYAR\n".to_vec();
+    myar.extend(format!("file-1 {}\n", DATA.len()).as_bytes());
+    myar.extend(DATA);
+    let inner = gzip_data(DATA);
+    myar.extend(format!("file-2.gz {}\n", inner.len()).as_bytes());
+    myar.extend(&inner);
+    let archive = utils::tar_archive([("data.myar.xor", xor_data(&myar))]);
+
+    // Without the formats, the entry is read as-is.
+    assert_eq!(
+        process(&archive),
+        vec![("root/data.myar.xor".into(), xor_data(&myar))]
+    );
+
+    let options = ReadOptions::new().formats(FormatRegistry::new().codec(Xor).container(MyArchive));
+    assert_eq!(
+        process_with(&options, &archive),
+        vec![
+            ("root/data.myar.xor/file-1".into(), DATA.to_vec()),
+            ("root/data.myar.xor/file-2.gz".into(), DATA.to_vec()),
+        ]
+    );
+
+    let mut entries = vec![];
+    options
+        .iterate_archive(xor_data(&myar).as_slice(), |item| {
+            entries.push((item.path, item.size_hint));
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            ("file-1".into(), SizeHint::Exact(DATA.len() as u64)),
+            ("file-2.gz".into(), SizeHint::Exact(inner.len() as u64)),
+        ]
+    );
+}
+
+#[test]
+fn test_detect() {
+    assert_eq!(
+        detect(&zstd_data(DATA)),
+        DetectedFormat::Compressed(Format::Zstd)
+    );
+    let archive = utils::tar_archive([("file", DATA)]);
+    assert_eq!(detect(&archive), DetectedFormat::Archive(Format::Tar));
+    assert_eq!(detect(DATA), DetectedFormat::Unknown);
+    // Tar needs the header up to its magic, at byte 257.
+    assert_eq!(detect(&archive[..100]), DetectedFormat::Unknown);
+}
+
+#[traced_test]
+#[test]
+fn test_detect_layers() {
+    let archive = utils::tar_archive([("file", DATA)]);
+    let data = xz_data(gzip_data(&archive));
+    let layers = detect_layers(data.as_slice()).unwrap();
+    let formats: Vec<_> = layers.iter().map(|l| l.format).collect();
+    assert_eq!(formats, [Format::Xz, Format::Gzip, Format::Tar]);
+    assert_eq!(layers[0].bytes_read, data.len() as u64);
+    assert_eq!(
+        layers[2],
+        DetectedLayer {
+            format: Format::Tar,
+            bytes_read: 262
+        }
+    );
+
+    assert!(detect_layers(DATA).unwrap().is_empty());
+    let layers = detect_layers(gzip_data(DATA).as_slice()).unwrap();
+    assert_eq!(layers.len(), 1);
+}
+
+#[test]
+fn test_content_types() {
+    let png = [&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A], DATA].concat();
+    let elf = [b"\x7fELF\x02\x01\x01\x00".as_slice(), &[0; 56], DATA].concat();
+    let archive = utils::tar_archive([
+        ("image.png", gzip_data(&png)),
+        ("binary", elf),
+        ("text", DATA.to_vec()),
+    ]);
+
+    let mut types = vec![];
+    ReadOptions::new()
+        .content_types(true)
+        .recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+            types.push(item.content_type.map(|t| (t.mime_type, t.extension)));
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(
+        types,
+        [
+            Some(("image/png", "png")),
+            Some(("application/x-executable", "elf")),
+            None
+        ]
+    );
+
+    recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+        assert_eq!(item.content_type, None);
+        Ok(())
+    })
+    .unwrap();
+}
+
+fn read_all(data: &[u8]) -> Result<(), anyreader::Error> {
+    recursive_read(Path::new("root"), data, &mut |item| {
+        std::io::copy(item.reader, &mut std::io::sink())?;
+        Ok(())
+    })
+}
+
+#[traced_test]
+#[test]
+fn test_error_context() {
+    let xz = xz_data(vec![b'a'; 1000]);
+    let archive = utils::zip_archive([
+        ("ok", DATA.to_vec()),
+        ("nested/file.xz", xz[..xz.len() / 2].to_vec()),
+    ]);
+    let err = read_all(&archive).unwrap_err();
+    assert_eq!(err.path(), Path::new("root/nested/file.xz"));
+    assert_eq!(err.layers(), ["zip", "xz"]);
+    assert_ne!(err.category(), ErrorCategory::Callback);
+
+    // The context survives a round trip through io::Error.
+    let io_err = std::io::Error::from(err);
+    let err = io_err
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<anyreader::Error>()
+        .unwrap();
+    assert_eq!(err.path(), Path::new("root/nested/file.xz"));
+}
+
+#[test]
+fn test_error_truncated() {
+    let data = gzip_data(DATA);
+    let err = read_all(&data[..data.len() - 4]).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Truncated);
+    assert_eq!(err.layers(), ["gzip"]);
+}
+
+#[test]
+fn test_error_callback() {
+    let archive = utils::tar_archive([("a", DATA), ("b", DATA)]);
+    let err = recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+        if item.path.ends_with("b") {
+            return Err(std::io::Error::other("stop"));
+        }
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Callback);
+    assert_eq!(err.path(), Path::new("root/b"));
+    assert_eq!(err.layers(), ["tar"]);
+    assert_eq!(err.to_string(), "root/b (tar): stop");
+}
+
+#[test]
+fn test_lenient_skips_corrupt_entries() {
+    let gz = gzip_data(
+        (0..100_000u32)
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>(),
+    );
+    let archive = utils::tar_archive([
+        ("a", DATA.to_vec()),
+        ("broken.gz", gz[..gz.len() / 2].to_vec()),
+        ("c", DATA.to_vec()),
+    ]);
+    let mut files = vec![];
+    let mut reported = vec![];
+    let summary = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            archive.as_slice(),
+            &mut |item| {
+                let mut data = vec![];
+                let result = item.reader.read_to_end(&mut data);
+                files.push((item.path, data));
+                result.map(|_| ())
+            },
+            |err| reported.push(err.path().to_path_buf()),
+        )
+        .unwrap();
+    assert_eq!(summary.skipped.len(), 1);
+    let err = &summary.skipped[0];
+    assert_eq!(err.path(), Path::new("root/broken.gz"));
+    assert_eq!(err.layers(), ["tar", "gzip"]);
+    assert_eq!(err.category(), ErrorCategory::Truncated);
+    assert_eq!(reported, [PathBuf::from("root/broken.gz")]);
+
+    // The partially readable data of the broken entry is still yielded.
+    let paths: Vec<_> = files.iter().map(|(p, _)| p.as_path()).collect();
+    assert_eq!(
+        paths,
+        [
+            Path::new("root/a"),
+            Path::new("root/broken.gz"),
+            Path::new("root/c")
+        ]
+    );
+    assert_eq!(files[2].1, DATA);
+
+    // The default is to stop at the first error.
+    let err = read_all(&archive).unwrap_err();
+    assert_eq!(err.path(), Path::new("root/broken.gz"));
+}
+
+#[test]
+fn test_lenient_callback_error_stops() {
+    let archive = utils::tar_archive([("a", DATA), ("b", DATA), ("c", DATA)]);
+    let mut seen = 0;
+    let err = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            archive.as_slice(),
+            &mut |item| {
+                seen += 1;
+                if item.path.ends_with("b") {
+                    return Err(std::io::Error::other("stop"));
+                }
+                Ok(())
+            },
+            |_| panic!("callback errors are not skipped"),
+        )
+        .unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Callback);
+    assert_eq!(seen, 2);
+}
+
+#[derive(Default)]
+struct TreeVisitor {
+    events: Vec<String>,
+    stats: Vec<(PathBuf, ContainerStats)>,
+}
+
+impl Visitor for TreeVisitor {
+    fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> std::io::Result<()> {
+        std::io::copy(item.reader, &mut std::io::sink())?;
+        self.events.push(format!("file {}", item.path.display()));
+        Ok(())
+    }
+
+    fn enter_container(&mut self, path: &Path, format: &str) -> std::io::Result<()> {
+        self.events
+            .push(format!("enter {} {format}", path.display()));
+        Ok(())
+    }
+
+    fn leave_container(&mut self, path: &Path, stats: &ContainerStats) -> std::io::Result<()> {
+        self.events.push(format!("leave {}", path.display()));
+        self.stats.push((path.to_path_buf(), *stats));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_container_events() {
+    let zip = utils::zip_archive([("b", DATA.to_vec()), ("c", DATA.to_vec())]);
+    let archive = gzip_data(utils::tar_archive([
+        ("a", DATA.to_vec()),
+        ("nested.zip", zip),
+    ]));
+    let mut visitor = TreeVisitor::default();
+    ReadOptions::new()
+        .visit(Path::new("root.tar.gz"), archive.as_slice(), &mut visitor)
+        .unwrap();
+    assert_eq!(
+        visitor.events,
+        [
+            "enter root.tar.gz tar",
+            "file root.tar.gz/a",
+            "enter root.tar.gz/nested.zip zip",
+            "file root.tar.gz/nested.zip/b",
+            "file root.tar.gz/nested.zip/c",
+            "leave root.tar.gz/nested.zip",
+            "leave root.tar.gz",
+        ]
+    );
+    let len = DATA.len() as u64;
+    assert_eq!(
+        visitor.stats,
+        [
+            (
+                PathBuf::from("root.tar.gz/nested.zip"),
+                ContainerStats {
+                    entries: 2,
+                    files: 2,
+                    bytes: 2 * len,
+                    skipped: 0
+                }
+            ),
+            (
+                PathBuf::from("root.tar.gz"),
+                ContainerStats {
+                    entries: 2,
+                    files: 3,
+                    bytes: 3 * len,
+                    skipped: 0
+                }
+            ),
+        ]
+    );
+}
+
+// A cpio archive with one entry in the `070702` format, which stores a sum of the data.
+fn cpio_crc_archive(checksum: u32) -> Vec<u8> {
+    let mut archive = utils::cpio_archive([("a", DATA)]);
+    archive[..6].copy_from_slice(b"070702");
+    archive[102..110].copy_from_slice(format!("{checksum:08X}").as_bytes());
+    archive
+}
+
+#[test]
+fn test_checksums_verified() {
+    let archive = utils::zip_archive([("a", DATA.to_vec()), ("b.gz", gzip_data(DATA))]);
+    let mut checksums = vec![];
+    let summary = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            archive.as_slice(),
+            &mut |item| {
+                checksums.push(item.checksum);
+                std::io::copy(item.reader, &mut std::io::sink())?;
+                Ok(())
+            },
+            |_| {},
+        )
+        .unwrap();
+    let crc = Checksum::Crc32(crc32fast::hash(DATA));
+    // The checksum of a decompressed entry covers the compressed data.
+    assert_eq!(checksums, [Some(crc), None]);
+    assert!(summary.skipped.is_empty());
+    let verified: Vec<_> = summary
+        .verified
+        .iter()
+        .map(|v| (v.path.clone(), v.expected.algorithm(), v.is_match()))
+        .collect();
+    assert_eq!(
+        verified,
+        [
+            (PathBuf::from("root/a"), "crc32", true),
+            (PathBuf::from("root/b.gz"), "crc32", true),
+        ]
+    );
+}
+
+#[test]
+fn test_checksums_mismatch() {
+    let sum = DATA.iter().map(|b| *b as u32).sum::<u32>();
+    let x = process(&cpio_crc_archive(sum));
+    assert_eq!(x, vec![("root/a".into(), DATA.to_vec())]);
+
+    // Without strict verification, a mismatch is only reported.
+    let summary = ReadOptions::new()
+        .recursive_read_lenient(
+            Path::new("root"),
+            cpio_crc_archive(sum + 1).as_slice(),
+            &mut |item| {
+                std::io::copy(item.reader, &mut std::io::sink())?;
+                Ok(())
+            },
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(summary.verified.len(), 1);
+    assert_eq!(summary.verified[0].actual, Checksum::Sum32(sum));
+    assert!(!summary.verified[0].is_match());
+
+    // Strict verification reads entries the callback did not, and fails on a mismatch.
+    let err = ReadOptions::new()
+        .verify_checksums(true)
+        .recursive_read(
+            Path::new("root"),
+            cpio_crc_archive(sum + 1).as_slice(),
+            &mut |_| Ok(()),
+        )
+        .unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Corrupt);
+    assert_eq!(err.path(), Path::new("root/a"));
+    assert_eq!(err.layers(), ["cpio"]);
+    ReadOptions::new()
+        .verify_checksums(true)
+        .recursive_read(
+            Path::new("root"),
+            cpio_crc_archive(sum).as_slice(),
+            &mut |_| Ok(()),
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_cpio_name_too_long() {
+    let mut archive = utils::cpio_archive([("a", DATA)]);
+    archive[94..102].copy_from_slice(b"FFFFFFFF");
+    let err = recursive_read(Path::new("root"), archive.as_slice(), &mut |_| Ok(())).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Corrupt);
+    assert_eq!(err.io_error().to_string(), "cpio entry name is too long");
+}
+
+const DATA_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+const DATA_BLAKE3: &str = "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24";
+
+#[test]
+fn test_hash_files() {
+    let archive = gzip_data(utils::tar_archive([
+        ("a", DATA.to_vec()),
+        (
+            "nested.tar.zst",
+            zstd_data(utils::tar_archive([("b", DATA)])),
+        ),
+    ]));
+    let files = ReadOptions::new()
+        .hash([HashAlgorithm::Sha256, HashAlgorithm::Blake3])
+        .hash_files(Path::new("root"), archive.as_slice())
+        .unwrap();
+    let hashes: Vec<_> = files
+        .iter()
+        .map(|f| {
+            (
+                f.path.to_str().unwrap(),
+                f.size,
+                f.get(HashAlgorithm::Sha256).unwrap().to_hex(),
+                f.get(HashAlgorithm::Blake3).unwrap().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        hashes,
+        [
+            (
+                "root/a",
+                11,
+                DATA_SHA256.to_string(),
+                format!("blake3:{DATA_BLAKE3}")
+            ),
+            (
+                "root/nested.tar.zst/b",
+                11,
+                DATA_SHA256.to_string(),
+                format!("blake3:{DATA_BLAKE3}")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_hash_partial_read() {
+    struct FirstByte(Vec<FileHashes>);
+
+    impl Visitor for FirstByte {
+        fn visit_file(&mut self, item: FileItem<&mut dyn Read>) -> std::io::Result<()> {
+            item.reader.read_exact(&mut [0])
+        }
+
+        fn hashed(&mut self, hashes: &FileHashes) -> std::io::Result<()> {
+            self.0.push(hashes.clone());
+            Ok(())
+        }
+    }
+
+    let mut visitor = FirstByte(vec![]);
+    ReadOptions::new()
+        .hash([HashAlgorithm::Sha256])
+        .visit(Path::new("root"), gzip_data(DATA).as_slice(), &mut visitor)
+        .unwrap();
+    assert_eq!(visitor.0.len(), 1);
+    assert_eq!(visitor.0[0].size, 11);
+    assert_eq!(visitor.0[0].digests[0].to_hex(), DATA_SHA256);
+}
+
+fn read_offsets(
+    read: impl FnOnce(&mut dyn FnMut(FileItem<&mut dyn Read>) -> std::io::Result<()>),
+) -> Vec<(PathBuf, Option<EntryOffsets>)> {
+    let mut offsets = vec![];
+    read(&mut |item| {
+        offsets.push((item.path, item.offsets));
+        Ok(())
+    });
+    offsets
+}
+
+#[test]
+fn test_entry_offsets_tar() {
+    let archive = utils::tar_archive([("a", DATA.to_vec()), ("b.gz", gzip_data(DATA))]);
+    let offsets = read_offsets(|mut callback| {
+        recursive_read(Path::new("root"), archive.as_slice(), &mut callback).unwrap()
+    });
+    let at = |header, data| Some(EntryOffsets { header, data });
+    assert_eq!(
+        offsets,
+        [
+            (PathBuf::from("root/a"), at(0, 512)),
+            (PathBuf::from("root/b.gz"), at(1024, 1536)),
+        ]
+    );
+    assert_eq!(&archive[512..512 + DATA.len()], DATA);
+}
+
+#[test]
+fn test_entry_offsets_zip() {
+    let archive = utils::zip_stored_archive([("a", DATA), ("bc", b"second")]);
+    let streamed = read_offsets(|mut callback| {
+        recursive_read(Path::new("root"), archive.as_slice(), &mut callback).unwrap()
+    });
+    let seeked = read_offsets(|mut callback| {
+        recursive_read_seekable(Path::new("root"), Cursor::new(&archive), &mut callback).unwrap()
+    });
+    let at = |header, data| Some(EntryOffsets { header, data });
+    // Each local header is 30 bytes, followed by the name and then the stored data.
+    let expected = [
+        (PathBuf::from("root/a"), at(0, 31)),
+        (PathBuf::from("root/bc"), at(42, 74)),
+    ];
+    assert_eq!(streamed, expected);
+    assert_eq!(seeked, expected);
+    assert_eq!(&archive[42..46], b"PK\x03\x04");
+    assert_eq!(&archive[31..31 + DATA.len()], DATA);
+    assert_eq!(&archive[74..80], b"second");
+}
+
+fn indexed_archive() -> (Vec<u8>, Vec<(&'static str, Vec<u8>)>) {
+    let entries: Vec<_> = ["a", "b", "c", "d", "e"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name, vec![i as u8; 700_000]))
+        .collect();
+    (utils::tar_archive(entries.clone()), entries)
+}
+
+#[test]
+fn test_index_compressed() {
+    let (archive, entries) = indexed_archive();
+    for format in [Format::Gzip, Format::Zstd, Format::Xz] {
+        // Compressed in independent chunks, as parallel compressors do.
+        let data: Vec<u8> = archive
+            .chunks(1024 * 1024)
+            .flat_map(|chunk| match format {
+                Format::Gzip => gzip_data(chunk),
+                Format::Zstd => zstd_data(chunk),
+                _ => xz_data(chunk),
+            })
+            .collect();
+        let mut files = vec![];
+        let index = ReadOptions::new()
+            .recursive_read_indexed(Path::new("root"), data.as_slice(), &mut |item| {
+                files.push(item.path);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(files.len(), entries.len());
+        assert_eq!(index.compression(), Some(format));
+        assert_eq!(index.checkpoints().len(), 4, "{format}");
+        assert_eq!(index.checkpoints()[0].uncompressed, 0);
+        assert_eq!(index.checkpoints()[1].uncompressed, 1024 * 1024);
+        for (name, contents) in &entries {
+            let mut reader = open_entry(&index, Cursor::new(&data), name).unwrap();
+            assert_eq!(&utils::read_vec(&mut reader), contents, "{format} {name}");
+        }
+    }
+}
+
+type Entries = Vec<(&'static str, Vec<u8>)>;
+
+/// A tar archive of large entries, compressed as a single gzip member and as a single xz stream
+/// made of several blocks.
+fn single_stream_archives() -> (Entries, [(Format, Vec<u8>); 2]) {
+    // Random letters repeating further apart than deflate can refer back, so that deflate
+    // splits them into many blocks while xz encodes them quickly.
+    let mut state = 1u32;
+    let letters: Vec<u8> = (0..64 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            b'a' + (state % 16) as u8
+        })
+        .collect();
+    let entries: Vec<_> = ["a", "b", "c", "d", "e"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let data = letters.iter().cycle().skip(i * 1000).take(700_000);
+            (name, data.copied().collect::<Vec<_>>())
+        })
+        .collect();
+    let archive = utils::tar_archive(entries.clone());
+    let mut options = lzma_rust2::XzOptions::with_preset(1);
+    options.set_block_size(NonZeroU64::new(1024 * 1024));
+    let mut encoder = lzma_rust2::XzWriter::new(Vec::new(), options).unwrap();
+    encoder.write_all(&archive).unwrap();
+    let xz = encoder.finish().unwrap();
+    // At the default level, deflate blocks are a few tens of KiB.
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+    encoder.write_all(&archive).unwrap();
+    let gzip = encoder.finish().unwrap();
+
+    (entries, [(Format::Gzip, gzip), (Format::Xz, xz)])
+}
+
+#[test]
+fn test_index_single_stream() {
+    let (entries, archives) = single_stream_archives();
+    for (format, data) in archives {
+        let index = ReadOptions::new()
+            .recursive_read_indexed(Path::new("root"), data.as_slice(), &mut |_| Ok(()))
+            .unwrap();
+        assert_eq!(index.checkpoints().len(), 4, "{format}");
+        assert!(index.checkpoints()[1].uncompressed >= 1024 * 1024);
+        let mut serialized = vec![];
+        index.write_to(&mut serialized).unwrap();
+        let index = Index::read_from(serialized.as_slice()).unwrap();
+        for (name, contents) in &entries {
+            let mut reader = open_entry(&index, Cursor::new(&data), name).unwrap();
+            assert_eq!(&utils::read_vec(&mut reader), contents, "{format} {name}");
+        }
+    }
+}
+
+#[test]
+fn test_index_mangled() {
+    let (_, archives) = single_stream_archives();
+    for (format, data) in archives {
+        let index = ReadOptions::new()
+            .recursive_read_indexed(Path::new("root"), data.as_slice(), &mut |_| Ok(()))
+            .unwrap();
+        let mut serialized = vec![];
+        index.write_to(&mut serialized).unwrap();
+        // The second checkpoint follows the header, the compression format and the first
+        // checkpoint, which is the start of the input.
+        let first = 17 + 8 + format.to_string().len() + 8;
+        let second = first + 17;
+        let kind = second + 16;
+        assert_eq!(serialized[kind], if format == Format::Gzip { 1 } else { 2 });
+
+        let mangle = |offset: usize, bytes: &[u8]| {
+            let mut mangled = serialized.clone();
+            mangled[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Index::read_from(mangled.as_slice())
+        };
+        // Checkpoints that go back in the input.
+        let err = mangle(first, &u64::MAX.to_le_bytes()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{format}");
+        let err = mangle(first + 8, &u64::MAX.to_le_bytes()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{format}");
+        if format == Format::Gzip {
+            let err = mangle(kind + 1, &[8]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        } else {
+            // An xz block after the index of its stream.
+            let err = mangle(kind + 1, &0u64.to_le_bytes()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        // A checkpoint that is valid on its own but does not match the input, which fails when
+        // an entry after it is read rather than panicking.
+        let checkpoint = &index.checkpoints()[1];
+        let entry = index
+            .entries()
+            .iter()
+            .find(|e| e.offsets.data >= checkpoint.uncompressed)
+            .unwrap();
+        let mangled = mangle(second, &(checkpoint.compressed + 1).to_le_bytes()).unwrap();
+        let read = open_entry(&mangled, Cursor::new(&data), &entry.path)
+            .map_err(std::io::Error::from)
+            .and_then(|mut reader| {
+                let mut buf = vec![];
+                reader.read_to_end(&mut buf).map(|_| buf)
+            });
+        assert!(read.is_err(), "{format}");
+    }
+}
+
+#[test]
+fn test_index_round_trip() {
+    let archive = utils::tar_archive([("a", DATA.to_vec()), ("b.gz", gzip_data(DATA))]);
+    let mut files = vec![];
+    let index = ReadOptions::new()
+        .recursive_read_indexed(Path::new("root"), archive.as_slice(), &mut |item| {
+            files.push(item.path);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(files, [PathBuf::from("root/a"), PathBuf::from("root/b.gz")]);
+    assert_eq!(index.compression(), None);
+    assert_eq!(index.entries().len(), 2);
+
+    let mut serialized = vec![];
+    index.write_to(&mut serialized).unwrap();
+    let index = Index::read_from(serialized.as_slice()).unwrap();
+    assert_eq!(index.get("a").unwrap().offsets.data, 512);
+    let reader = open_entry(&index, Cursor::new(&archive), "a").unwrap();
+    assert_eq!(utils::read_vec(reader), DATA);
+    // Entries are opened as they are stored, without decompressing them.
+    let reader = open_entry(&index, Cursor::new(&archive), "b.gz").unwrap();
+    assert_eq!(utils::read_vec(reader), gzip_data(DATA));
+    let err = open_entry(&index, Cursor::new(&archive), "missing")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_open_path() {
+    let jar = utils::zip_archive([("META-INF/MANIFEST.MF", DATA), ("Main.class", b"class")]);
+    let corrupt = &gzip_data(DATA)[..12];
+    let archive = gzip_data(utils::tar_archive([
+        ("before.gz", corrupt),
+        ("pkg/lib.jar", jar.as_slice()),
+        ("after.gz", corrupt),
+    ]));
+    // Entries that cannot hold the file, before and after it, are not read at all.
+    let file = open_path(
+        archive.as_slice(),
+        "release.tar.gz/pkg/lib.jar/META-INF/MANIFEST.MF",
+    )
+    .unwrap();
+    assert_eq!(utils::read_vec(file), DATA);
+    let err = open_path(archive.as_slice(), "release.tar.gz/pkg/lib.jar/missing").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    // The input itself can be opened, after decompressing it.
+    let file = open_path(gzip_data(DATA).as_slice(), "input.gz").unwrap();
+    assert_eq!(utils::read_vec(file), DATA);
+}
+
+#[test]
+fn test_include_exclude() {
+    let corrupt = &gzip_data(DATA)[..12];
+    let archive = utils::tar_archive([
+        ("app/main.py", DATA.to_vec()),
+        (
+            "app/lib.tar.gz",
+            gzip_data(utils::tar_archive([("util.py", DATA)])),
+        ),
+        ("app/README.md", DATA.to_vec()),
+        ("app/node_modules/dep.tar.gz", corrupt.to_vec()),
+        ("docs.gz", corrupt.to_vec()),
+    ]);
+    let read = |options: ReadOptions| {
+        let mut paths = vec![];
+        options
+            .recursive_read(Path::new("root"), archive.as_slice(), &mut |item| {
+                paths.push(item.path);
+                Ok(())
+            })
+            .unwrap();
+        paths
+    };
+    // Excluded entries are skipped without decompressing them, so the corrupt ones are not read.
+    let paths = read(
+        ReadOptions::new()
+            .include(["*.py"])
+            .exclude(["**/node_modules/**", "root/docs.gz"]),
+    );
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("root/app/main.py"),
+            PathBuf::from("root/app/lib.tar.gz/util.py"),
+        ]
+    );
+    // Only the entries that could hold a match are read.
+    let paths = read(ReadOptions::new().include(["root/app/lib.tar.gz"]));
+    assert_eq!(paths, [PathBuf::from("root/app/lib.tar.gz/util.py")]);
+}
+
+fn read_parallel(
+    data: &[u8],
+    parallel: &ParallelOptions,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, anyreader::Error> {
+    let files = std::sync::Mutex::new(vec![]);
+    ReadOptions::new().recursive_read_parallel(Path::new("root"), data, parallel, &|item| {
+        let data = utils::read_vec(item.reader);
+        files.lock().unwrap().push((item.path, data));
+        Ok(())
+    })?;
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    Ok(files)
+}
+
+#[test]
+fn test_parallel() {
+    let entries: Vec<_> = (0..20)
+        .map(|i| {
+            let nested = utils::tar_archive([(format!("{i}.txt"), DATA)]);
+            (format!("{i}.tar.gz"), gzip_data(nested))
+        })
+        .collect();
+    let archive = xz_data(utils::tar_archive(entries));
+    let mut expected = process(&archive);
+    expected.sort();
+    assert_eq!(expected.len(), 20);
+
+    let files = read_parallel(&archive, &ParallelOptions::new().threads(4)).unwrap();
+    assert_eq!(files, expected);
+    // Entries that do not fit in the budget are read on the calling thread.
+    let files = read_parallel(&archive, &ParallelOptions::new().memory_budget(1)).unwrap();
+    assert_eq!(files, expected);
+}
+
+#[test]
+fn test_parallel_error() {
+    let corrupt = &gzip_data(DATA)[..12];
+    let entries: Vec<_> = (0..10)
+        .map(|i| match i {
+            5 => ("5.gz".to_string(), corrupt.to_vec()),
+            i => (format!("{i}.gz"), gzip_data(DATA)),
+        })
+        .collect();
+    let archive = utils::tar_archive(entries);
+    let err = read_parallel(&archive, &ParallelOptions::new().threads(2)).unwrap_err();
+    assert_eq!(err.path(), Path::new("root/5.gz"));
+}
+
+#[test]
+fn test_parallel_panic() {
+    let entries: Vec<_> = (0..10).map(|i| (format!("{i}.txt"), DATA)).collect();
+    let archive = utils::tar_archive(entries);
+    // Only one entry fits in the budget at a time, so the calling thread waits for the worker.
+    let parallel = ParallelOptions::new()
+        .threads(1)
+        .memory_budget(DATA.len() as u64);
+    let result = std::panic::catch_unwind(|| {
+        ReadOptions::new().recursive_read_parallel(
+            Path::new("root"),
+            archive.as_slice(),
+            &parallel,
+            &|_| panic!("callback failed"),
+        )
+    });
+    assert!(result.is_err());
+}
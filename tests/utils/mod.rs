@@ -20,7 +20,7 @@ pub fn gzip_data(data: impl AsRef<[u8]>) -> Vec<u8> {
 }
 
 pub fn zstd_data(data: impl AsRef<[u8]>) -> Vec<u8> {
-    zstd::encode_all(data.as_ref(), 1).unwrap()
+    ruzstd::encoding::compress_to_vec(data.as_ref(), ruzstd::encoding::CompressionLevel::Fastest)
 }
 
 pub fn bz2_data(data: impl AsRef<[u8]>) -> Vec<u8> {
@@ -30,11 +30,23 @@ pub fn bz2_data(data: impl AsRef<[u8]>) -> Vec<u8> {
 }
 
 pub fn xz_data(data: impl AsRef<[u8]>) -> Vec<u8> {
-    let mut encoder = liblzma::write::XzEncoder::new(Vec::new(), 1);
+    let options = lzma_rust2::XzOptions::with_preset(1);
+    let mut encoder = lzma_rust2::XzWriter::new(Vec::new(), options).unwrap();
     encoder.write_all(data.as_ref()).unwrap();
     encoder.finish().unwrap()
 }
 
+/// Legacy `.lzma` data, with the uncompressed size in its header.
+pub fn lzma_data(data: impl AsRef<[u8]>) -> Vec<u8> {
+    let data = data.as_ref();
+    let options = lzma_rust2::LzmaOptions::with_preset(1);
+    let mut encoder =
+        lzma_rust2::LzmaWriter::new_use_header(Vec::new(), &options, Some(data.len() as u64))
+            .unwrap();
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
 pub fn tar_archive(
     files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
 ) -> Vec<u8> {
@@ -62,6 +74,292 @@ pub fn zip_archive(
     a.finish().unwrap().into_inner()
 }
 
+/// A zip with its entries stored uncompressed and without extra fields, so each local header is
+/// 30 bytes followed by the name and then the data.
+pub fn zip_stored_archive(
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut a = zip::write::ZipWriter::new(std::io::Cursor::new(vec![]));
+    for (path, data) in files {
+        a.start_file(path.into().to_string_lossy(), options)
+            .unwrap();
+        a.write_all(data.as_ref()).unwrap();
+    }
+    a.finish().unwrap().into_inner()
+}
+
+/// A zip written to a non-seekable stream, so entries are stored with data descriptors.
+pub fn zip_stream_archive(
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut a = zip::write::ZipWriter::new_stream(Vec::new());
+    for (path, data) in files {
+        a.start_file(path.into().to_string_lossy(), options)
+            .unwrap();
+        a.write_all(data.as_ref()).unwrap();
+    }
+    a.finish().unwrap().into_inner()
+}
+
+pub fn cpio_archive(
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    // "new ASCII" format, as written by `cpio -H newc`.
+    fn entry(out: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let pad = |out: &mut Vec<u8>| out.resize(out.len().next_multiple_of(4), 0);
+        out.extend_from_slice(b"070701");
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+        for field in fields {
+            out.extend_from_slice(format!("{field:08X}").as_bytes());
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        pad(out);
+        out.extend_from_slice(data);
+        pad(out);
+    }
+
+    let mut out = Vec::new();
+    for (path, data) in files {
+        let path = path.into();
+        entry(&mut out, &path.to_string_lossy(), 0o100644, data.as_ref());
+    }
+    entry(&mut out, "TRAILER!!!", 0, &[]);
+    out
+}
+
+pub fn pbzx_data(data: impl AsRef<[u8]>) -> Vec<u8> {
+    // Two chunks: the first xz compressed, the second stored.
+    let data = data.as_ref();
+    let (first, second) = data.split_at(data.len() / 2);
+    let chunk_size: u64 = 1 << 24;
+    let mut out = b"pbzx".to_vec();
+    out.extend_from_slice(&chunk_size.to_be_bytes());
+    let compressed = xz_data(first);
+    out.extend_from_slice(&chunk_size.to_be_bytes());
+    out.extend_from_slice(&(compressed.len() as u64).to_be_bytes());
+    out.extend_from_slice(&compressed);
+    out.extend_from_slice(&0u64.to_be_bytes());
+    out.extend_from_slice(&(second.len() as u64).to_be_bytes());
+    out.extend_from_slice(second);
+    out
+}
+
+pub fn xar_archive(
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    xar_archive_encoded("application/x-gzip", files)
+}
+
+/// A xar archive with each file stored in `encoding`, with its parent directories nested in the
+/// TOC.
+pub fn xar_archive_encoded(
+    encoding: &str,
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    let mut heap = Vec::new();
+    let mut toc = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?><xar><toc>");
+    let mut id = 0;
+    for (path, data) in files {
+        let path = path.into();
+        let data = data.as_ref();
+        let compressed = match encoding {
+            "application/x-gzip" => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), GZCompression::new(1));
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            "application/x-lzma" => lzma_data(data),
+            "application/x-xz" => xz_data(data),
+            _ => data.to_vec(),
+        };
+
+        let components: Vec<_> = path
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .collect();
+        let (name, dirs) = components.split_last().unwrap();
+        for dir in dirs {
+            id += 1;
+            toc.push_str(&format!(
+                "<file id=\"{id}\"><name>{dir}</name><type>directory</type>"
+            ));
+        }
+        id += 1;
+        toc.push_str(&format!(
+            "<file id=\"{id}\"><data><length>{}</length><offset>{}</offset><size>{}</size>\
+             <encoding style=\"{encoding}\"/></data><ea><name>x</name><offset>0</offset>\
+             <length>1</length><size>1</size></ea><type>file</type><name>{name}</name></file>",
+            compressed.len(),
+            heap.len(),
+            data.len(),
+        ));
+        toc.push_str(&"</file>".repeat(dirs.len()));
+        heap.extend_from_slice(&compressed);
+    }
+    toc.push_str("</toc></xar>");
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), GZCompression::new(1));
+    encoder.write_all(toc.as_bytes()).unwrap();
+    let toc_compressed = encoder.finish().unwrap();
+
+    let mut out = b"xar!".to_vec();
+    out.extend_from_slice(&28u16.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&(toc_compressed.len() as u64).to_be_bytes());
+    out.extend_from_slice(&(toc.len() as u64).to_be_bytes());
+    // No checksum.
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&toc_compressed);
+    out.extend_from_slice(&heap);
+    out
+}
+
+pub fn cfb_archive(
+    files: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    let mut file = cfb::CompoundFile::create(std::io::Cursor::new(Vec::new())).unwrap();
+    for (path, data) in files {
+        let path = PathBuf::from("/").join(path.into());
+        if let Some(parent) = path.parent() {
+            file.create_storage_all(parent).unwrap();
+        }
+        let mut stream = file.create_stream(&path).unwrap();
+        stream.write_all(data.as_ref()).unwrap();
+    }
+    file.flush().unwrap();
+    file.into_inner().into_inner()
+}
+
+pub fn base64(data: impl AsRef<[u8]>) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.as_ref().chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A multipart message with a quoted-printable text body and base64 encoded attachments.
+pub fn email_message(
+    body: &str,
+    attachments: impl IntoIterator<Item = (impl Into<PathBuf>, impl AsRef<[u8]>)>,
+) -> Vec<u8> {
+    let mut out = String::from(
+        "From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: test\r\n\
+         MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"BOUNDARY\"\r\n\r\n",
+    );
+    out.push_str("--BOUNDARY\r\nContent-Type: text/plain; charset=utf-8\r\n");
+    out.push_str("Content-Transfer-Encoding: quoted-printable\r\n\r\n");
+    out.push_str(&body.replace(' ', "=20"));
+    out.push_str("\r\n");
+    for (path, data) in attachments {
+        let name = path.into().to_string_lossy().into_owned();
+        out.push_str(&format!(
+            "--BOUNDARY\r\nContent-Type: application/octet-stream\r\n\
+             Content-Disposition: attachment; filename=\"{name}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            base64(data)
+        ));
+    }
+    out.push_str("--BOUNDARY--\r\n");
+    out.into_bytes()
+}
+
+/// A single WARC record, compressed as its own gzip member as is usual for `.warc.gz` files.
+pub fn warc_record(headers: &[(&str, &str)], block: impl AsRef<[u8]>) -> Vec<u8> {
+    let block = block.as_ref();
+    let mut record = b"WARC/1.0\r\n".to_vec();
+    for (name, value) in headers {
+        record.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+    }
+    record.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+    gzip_data(record)
+}
+
+/// A `docker save` style image, with the manifest after the layers as Docker writes it.
+pub fn docker_image(tag: &str, layers: &[Vec<u8>]) -> Vec<u8> {
+    let names: Vec<_> = (0..layers.len())
+        .map(|i| format!("layer-{i}/layer.tar"))
+        .collect();
+    let manifest = format!(
+        r#"[{{"Config":"config.json","RepoTags":["{tag}"],"Layers":[{}]}}]"#,
+        names
+            .iter()
+            .map(|n| format!("\"{n}\""))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let mut files: Vec<(String, Vec<u8>)> = names.into_iter().zip(layers.iter().cloned()).collect();
+    files.push(("config.json".into(), b"{}".to_vec()));
+    files.push(("manifest.json".into(), manifest.into_bytes()));
+    tar_archive(files)
+}
+
+/// An OCI image layout. Blobs are named by fake digests, as they aren't verified.
+pub fn oci_image(ref_name: &str, layers: &[Vec<u8>]) -> Vec<u8> {
+    let layer_digests: Vec<_> = (0..layers.len())
+        .map(|i| format!("sha256:{i:064x}"))
+        .collect();
+    let manifest_digest = format!("sha256:{:064x}", 1000);
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"config":{{"digest":"sha256:{:064x}"}},"layers":[{}]}}"#,
+        999,
+        layer_digests
+            .iter()
+            .map(|d| format!(
+                r#"{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{d}"}}"#
+            ))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let index = format!(
+        r#"{{"schemaVersion":2,"manifests":[{{"digest":"{manifest_digest}","annotations":{{"org.opencontainers.image.ref.name":"{ref_name}"}}}}]}}"#
+    );
+    let blob = |digest: &str| format!("blobs/sha256/{}", digest.trim_start_matches("sha256:"));
+    let mut files = vec![
+        (
+            "oci-layout".to_string(),
+            br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec(),
+        ),
+        ("index.json".to_string(), index.into_bytes()),
+        (blob(&manifest_digest), manifest.into_bytes()),
+    ];
+    for (digest, layer) in layer_digests.iter().zip(layers) {
+        files.push((blob(digest), gzip_data(layer)));
+    }
+    tar_archive(files)
+}
+
 pub fn tar_read_entries(data: impl Read) -> Vec<Vec<u8>> {
     let d = read_vec(data);
     let mut archive = tar::Archive::new(d.as_slice());